@@ -1,6 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use spyglass::SuffixTrie;
+use spyglass::{MappedSuffixTrie,SearchIndex,SuffixTrie};
 
 fn benchmark_find(c: &mut Criterion) {
     let trie = SuffixTrie::from_file(black_box("./resources/tests/large_100/para.txt")).unwrap();
@@ -18,6 +18,20 @@ fn benchmark_shakespeare_100(c: &mut Criterion) {
     c.bench_function("shakespeare_100_sent", |b| b.iter(|| SuffixTrie::from_file(black_box("./resources/tests/untokenized/shakespeare.txt"))));
 }
 
-criterion_group!(benches, benchmark_dir_100, benchmark_shakespeare_100, benchmark_find);
+fn benchmark_open_saved(c: &mut Criterion) {
+    let trie = SuffixTrie::from_directory("./resources/tests/large_100/").unwrap();
+    let saved = std::env::temp_dir().join("spyglass_bench_large_100.sst");
+    let mapped = std::env::temp_dir().join("spyglass_bench_large_100.sstm");
+    let saved = saved.to_str().unwrap();
+    let mapped = mapped.to_str().unwrap();
+    trie.save(saved).unwrap();
+    trie.save_mapped(mapped).unwrap();
+    c.bench_function("load_large_100", |b| b.iter(|| SuffixTrie::load(black_box(saved))));
+    c.bench_function("open_mapped_large_100", |b| b.iter(|| MappedSuffixTrie::open(black_box(mapped))));
+    let mapped_trie = MappedSuffixTrie::open(mapped).unwrap();
+    c.bench_function("find_exact_mapped_large_100", |b| b.iter(|| black_box(&mapped_trie).find_exact("that")));
+}
+
+criterion_group!(benches, benchmark_dir_100, benchmark_shakespeare_100, benchmark_find, benchmark_open_saved);
 criterion_group!(benches_quick, benchmark_shakespeare_100);// benchmark_find);
 criterion_main!(benches);
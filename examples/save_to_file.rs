@@ -1,8 +1,3 @@
-use std::fs::File;
-use std::io::BufWriter;
-
-use bincode;
-
 use spyglass::SuffixTrie;
 
 fn main() {
@@ -10,6 +5,5 @@ fn main() {
     let output_file = std::env::args().nth(2).expect("No output file specified");
     let trie = SuffixTrie::from_directory(&directory).unwrap();
 
-    let mut f = BufWriter::new(File::create(&output_file).unwrap());
-    bincode::serialize_into(&mut f, &trie).unwrap();
+    trie.save(&output_file).unwrap();
 }
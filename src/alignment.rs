@@ -0,0 +1,324 @@
+//! Alignment of a search pattern with the text it matched, showing which
+//! characters of the match are errors.
+//!
+//! Errors are counted in the same way as the edit distance search: a
+//! character of the text can be matched to a different character of the
+//! pattern (a mismatch), left out of the pattern (an insertion) or a
+//! character of the pattern can be missing from the text (a deletion).
+//! Ignorable characters and wildcards match without counting as errors.
+//!
+//! Two passages of text can also be aligned by extending an alignment from
+//! a point where they are known to agree, for finding approximate text
+//! reuse. The first passage takes the place of the text and the second the
+//! place of the pattern.
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{Match,SearchIndex,MULTI_WILDCARD,SINGLE_WILDCARD};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits(alignment: &[AlignedChar]) -> String {
+        alignment.iter().map(|aligned| match aligned.edit {
+            Edit::Match => '=',
+            Edit::Mismatch => 'X',
+            Edit::Insertion => 'I',
+            Edit::Deletion => 'D',
+        }).collect()
+    }
+
+    #[test]
+    fn align_errors() {
+        let no_ignore = HashMap::new();
+        assert_eq!(edits(&align_pattern("sentence", "sentence", &no_ignore, false)), "========");
+        assert_eq!(edits(&align_pattern("sentxnce", "sentence", &no_ignore, false)), "====X===");
+        assert_eq!(edits(&align_pattern("sentnce", "sentence", &no_ignore, false)), "====I===");
+        assert_eq!(edits(&align_pattern("sentennce", "sentence", &no_ignore, false)), "=====D===");
+
+        let alignment = align_pattern("sentennce", "sentence", &no_ignore, false);
+        assert_eq!(alignment[5], AlignedChar { edit: Edit::Deletion, c: 'n' });
+        assert_eq!(count_errors(&alignment), 1);
+    }
+
+    #[test]
+    fn align_ignored_and_wildcards() {
+        let ignore: HashMap<char, bool> = [('\'', true)].iter().cloned().collect();
+        assert_eq!(edits(&align_pattern("rack'd", "racked", &ignore, false)), "======");
+        assert_eq!(edits(&align_pattern("s?nt*s", "sentences", &HashMap::new(), true)), "=========");
+        assert_eq!(edits(&align_pattern("s?nt*s", "sentences", &HashMap::new(), false)), "=X==IIIX=");
+    }
+
+    #[test]
+    fn extend_until_score_drops() {
+        let chars = |string: &str| string.chars().collect::<Vec<char>>();
+        let scoring = ExtensionScoring { match_score: 1, mismatch_score: -1, gap_score: -2, band_width: 3, x_drop: 5 };
+        let extension = extend_alignment(&chars("the knight rode out xxxxxxxxxx"),
+                                         &chars("the knyght rode out yyyyyyyyyy"),
+                                         &scoring);
+        assert_eq!((extension.length_a, extension.length_b), (20, 20));
+        assert_eq!(extension.score, 18);
+        assert_eq!(edits(&extension.alignment), "======X=============");
+
+        let extension = extend_alignment(&chars("a well of water"), &chars("a wel of water"), &scoring);
+        assert_eq!((extension.length_a, extension.length_b, extension.score), (15, 14, 12));
+        assert_eq!(count_errors(&extension.alignment), 1);
+
+        // Nothing in common
+        let extension = extend_alignment(&chars("xyz"), &chars("abc"), &scoring);
+        assert_eq!((extension.length_a, extension.length_b, extension.score), (0, 0, 0));
+    }
+
+    #[test]
+    fn extend_backwards() {
+        let (a, b): (Vec<char>, Vec<char>) = ("yyy the knight".chars().collect(), "xx the knyght".chars().collect());
+        let scoring = ExtensionScoring::default();
+        let backwards = extend_alignment_by(a.len(), |i| a[a.len() - 1 - i], b.len(), |j| b[b.len() - 1 - j], &scoring);
+        let reversed = |chars: &[char]| chars.iter().rev().cloned().collect::<Vec<char>>();
+        assert_eq!(backwards, extend_alignment(&reversed(&a), &reversed(&b), &scoring));
+        assert_eq!((backwards.length_a, backwards.length_b), (11, 11));
+    }
+}
+
+/// How a character of a match relates to the pattern
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum Edit {
+    /// The text character matches the pattern character, is ignorable or
+    /// is matched by a wildcard
+    Match,
+    /// The text character is matched to a different pattern character
+    Mismatch,
+    /// The text character has no counterpart in the pattern
+    Insertion,
+    /// The pattern character has no counterpart in the text
+    Deletion,
+}
+
+/// A character of an alignment. For deletions this is the character of the
+/// pattern missing from the text, otherwise it is the character of the text.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub struct AlignedChar {
+    pub edit: Edit,
+    pub c: char,
+}
+
+/// Number of errors in an alignment
+pub fn count_errors(alignment: &[AlignedChar]) -> usize {
+    alignment.iter().filter(|aligned| aligned.edit != Edit::Match).count()
+}
+
+/// Align a normalised pattern with the string it matched, using as few
+/// errors as possible
+pub fn align_pattern(pattern: &str,
+                     matched: &str,
+                     ignored_characters: &HashMap<char, bool>,
+                     wildcards: bool) -> Vec<AlignedChar> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = matched.chars().collect();
+    let substitution_cost = |pattern_char: char, text_char: char| {
+        if pattern_char == text_char
+            || ignored_characters.contains_key(&pattern_char)
+            || ignored_characters.contains_key(&text_char)
+            || (wildcards && pattern_char == SINGLE_WILDCARD) {
+            0
+        } else {
+            1
+        }
+    };
+    let is_multi_wildcard = |pattern_char: char| wildcards && pattern_char == MULTI_WILDCARD;
+
+    // costs[i][j] is the fewest errors aligning the first i characters of
+    // the pattern with the first j characters of the text
+    let mut costs = vec![vec![0; text.len() + 1]; pattern.len() + 1];
+    for (j, cost) in costs[0].iter_mut().enumerate() {
+        *cost = j;
+    }
+    for i in 1 ..= pattern.len() {
+        let pattern_char = pattern[i - 1];
+        costs[i][0] = if is_multi_wildcard(pattern_char) { costs[i - 1][0] } else { costs[i - 1][0] + 1 };
+        for j in 1 ..= text.len() {
+            costs[i][j] = if is_multi_wildcard(pattern_char) {
+                // The wildcard matches any run of word characters for free
+                if text[j - 1].is_alphanumeric() {
+                    costs[i - 1][j].min(costs[i][j - 1])
+                } else {
+                    costs[i - 1][j]
+                }
+            } else {
+                (costs[i - 1][j - 1] + substitution_cost(pattern_char, text[j - 1]))
+                    .min(costs[i - 1][j] + 1)
+                    .min(costs[i][j - 1] + 1)
+            };
+        }
+    }
+
+    // Trace back through the costs, preferring matches and mismatches to
+    // insertions and deletions
+    let mut alignment = Vec::with_capacity(text.len());
+    let (mut i, mut j) = (pattern.len(), text.len());
+    while i > 0 || j > 0 {
+        let pattern_char = if i > 0 { Some(pattern[i - 1]) } else { None };
+        match pattern_char {
+            Some(pattern_char) if is_multi_wildcard(pattern_char) => {
+                if j > 0 && text[j - 1].is_alphanumeric() && costs[i][j] == costs[i][j - 1] {
+                    alignment.push(AlignedChar { edit: Edit::Match, c: text[j - 1] });
+                    j -= 1;
+                } else {
+                    i -= 1;
+                }
+            },
+            Some(pattern_char) if j > 0 && costs[i][j] == costs[i - 1][j - 1] + substitution_cost(pattern_char, text[j - 1]) => {
+                let edit = if substitution_cost(pattern_char, text[j - 1]) == 0 { Edit::Match } else { Edit::Mismatch };
+                alignment.push(AlignedChar { edit, c: text[j - 1] });
+                i -= 1;
+                j -= 1;
+            },
+            Some(pattern_char) if j == 0 || costs[i][j] == costs[i - 1][j] + 1 => {
+                alignment.push(AlignedChar { edit: Edit::Deletion, c: pattern_char });
+                i -= 1;
+            },
+            _ => {
+                alignment.push(AlignedChar { edit: Edit::Insertion, c: text[j - 1] });
+                j -= 1;
+            },
+        }
+    }
+    alignment.reverse();
+    alignment
+}
+
+/// Align the pattern with the text of a match found in the index, using the
+/// same settings as the search which found it
+pub fn align_match(index: &dyn SearchIndex,
+                   pattern: &str,
+                   match_obj: &Match,
+                   ignored_characters: &HashMap<char, bool>,
+                   wildcards: bool) -> Vec<AlignedChar> {
+    let pattern = index.get_options().normaliser.normalise(pattern);
+    let (_, matching, _) = index.get_strings_of_match(match_obj, 0);
+    align_pattern(&pattern, &matching, ignored_characters, wildcards)
+}
+
+/// Scores used when extending an alignment of two passages
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ExtensionScoring {
+    pub match_score: i32,
+    pub mismatch_score: i32,
+    // Score of each character aligned with nothing
+    pub gap_score: i32,
+    // Largest difference allowed between the number of characters used
+    // from each passage
+    pub band_width: usize,
+    // Extension stops once the score is this far below the best so far
+    pub x_drop: i32,
+}
+
+impl Default for ExtensionScoring {
+    fn default() -> Self {
+        ExtensionScoring {
+            match_score: 1,
+            mismatch_score: -1,
+            gap_score: -2,
+            band_width: 8,
+            x_drop: 10,
+        }
+    }
+}
+
+/// Best scoring alignment of the starts of two passages
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Extension {
+    // Number of characters of each passage in the alignment
+    pub length_a: usize,
+    pub length_b: usize,
+    pub score: i32,
+    pub alignment: Vec<AlignedChar>,
+}
+
+/// Align the starts of the two passages for as long as the alignment keeps
+/// scoring well, returning the best scoring alignment found. Characters of
+/// a with no counterpart in b are insertions and characters of b with no
+/// counterpart in a are deletions. Mismatched characters are those of a.
+pub fn extend_alignment(a: &[char], b: &[char], scoring: &ExtensionScoring) -> Extension {
+    extend_alignment_by(a.len(), |i| a[i], b.len(), |j| b[j], scoring)
+}
+
+/// As extend_alignment, taking the lengths of the passages and functions
+/// giving each of their characters, so that a passage can be read in
+/// reverse without copying it
+pub(crate) fn extend_alignment_by<A, B>(a_len: usize,
+                                        a: A,
+                                        b_len: usize,
+                                        b: B,
+                                        scoring: &ExtensionScoring) -> Extension
+    where A: Fn(usize) -> char,
+          B: Fn(usize) -> char {
+    const UNREACHABLE: i32 = i32::MIN / 2;
+    let band_width = scoring.band_width;
+    let row_width = 2 * band_width + 1;
+    // rows[i][k] is the best score aligning the first i characters of a
+    // with the first j characters of b, where k = j + band_width - i
+    let column = |i: usize, k: usize| (i + k).checked_sub(band_width);
+    let substitution_score = |i: usize, j: usize| {
+        if a(i - 1) == b(j - 1) { scoring.match_score } else { scoring.mismatch_score }
+    };
+
+    let mut rows: Vec<Vec<i32>> = vec![];
+    let (mut best_score, mut best_i, mut best_j) = (0, 0, 0);
+    for i in 0 ..= a_len {
+        let mut row = vec![UNREACHABLE; row_width];
+        for k in 0 .. row_width {
+            let j = match column(i, k) {
+                Some(j) if j <= b_len => j,
+                _ => continue,
+            };
+            let mut score = if i == 0 && j == 0 { 0 } else { UNREACHABLE };
+            if i > 0 && j > 0 {
+                score = score.max(rows[i - 1][k] + substitution_score(i, j));
+            }
+            if i > 0 && k + 1 < row_width {
+                score = score.max(rows[i - 1][k + 1] + scoring.gap_score);
+            }
+            if j > 0 && k > 0 {
+                score = score.max(row[k - 1] + scoring.gap_score);
+            }
+            if score < best_score - scoring.x_drop {
+                // Drop parts of the alignment scoring too badly to recover
+                score = UNREACHABLE;
+            } else if score > best_score {
+                (best_score, best_i, best_j) = (score, i, j);
+            }
+            row[k] = score;
+        }
+        let finished = row.iter().all(|score| *score == UNREACHABLE);
+        rows.push(row);
+        if finished {
+            break;
+        }
+    }
+
+    // Trace back from the best score, preferring matches and mismatches to
+    // gaps
+    let mut alignment = vec![];
+    let (mut i, mut j) = (best_i, best_j);
+    while i > 0 || j > 0 {
+        let k = j + band_width - i;
+        let score = rows[i][k];
+        if i > 0 && j > 0 && score == rows[i - 1][k] + substitution_score(i, j) {
+            let edit = if a(i - 1) == b(j - 1) { Edit::Match } else { Edit::Mismatch };
+            alignment.push(AlignedChar { edit, c: a(i - 1) });
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && k + 1 < row_width && score == rows[i - 1][k + 1] + scoring.gap_score {
+            alignment.push(AlignedChar { edit: Edit::Insertion, c: a(i - 1) });
+            i -= 1;
+        } else {
+            alignment.push(AlignedChar { edit: Edit::Deletion, c: b(j - 1) });
+            j -= 1;
+        }
+    }
+    alignment.reverse();
+    Extension { length_a: best_i, length_b: best_j, score: best_score, alignment }
+}
@@ -0,0 +1,562 @@
+use std::fs;
+use std::io;
+use std::io::{BufWriter,Error,ErrorKind,Write};
+use std::path::Path;
+use std::process;
+
+use clap::{Args,Parser,Subcommand,ValueEnum};
+
+use spyglass::{detect_index_kind,load_index,write_ngram_csv,BooleanQuery,BuildOptions,Collocate,CollocationOptions,DistinctiveOptions,DistinctivePhrase,IndexHeader,IndexKind,MappedSuffixTrie,Match,NgramOptions,NgramTable,NgramUnit,Normaliser,ProximityQuery,SearchIndex,SearchStats,SegmentedIndex,SuffixTrie,TextStore,TrieAnalysis,WordIndex};
+
+mod output;
+mod search;
+mod server;
+mod shell;
+
+use output::{CollocateSort,Highlight,OutputSettings,Query,ReuseFormat,StatsFormat};
+use search::{Scope,SearchSettings,Unit};
+use shell::Shell;
+
+/// Search engine for documents, inspired by bioinformatics
+#[derive(Parser)]
+#[command(name = "spyglass", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build an index from files and directories of files
+    Index(IndexArgs),
+    /// Search an index
+    Search(SearchArgs),
+    /// Find the sentences, lines or texts matching a boolean combination of
+    /// searches, such as "king AND sword AND NOT Arthur"
+    Query(QueryArgs),
+    /// Find where two searches match close to each other, such as
+    /// "knight NEAR/5 lady" or "knight BEFORE/5 lady"
+    Near(NearArgs),
+    /// Count the hits of a search in each text of an index
+    Stats(StatsArgs),
+    /// Find which texts of an index share passages with which others
+    Reuse(ReuseArgs),
+    /// Count the character or word n-grams of the texts of an index
+    Ngrams(NgramsArgs),
+    /// Find strings occurring more often in one group of texts than another
+    Distinctive(DistinctiveArgs),
+    /// Find the words found most often near the matches of a search
+    Collocations(CollocationsArgs),
+    /// Show information about an index
+    Info(InfoArgs),
+    /// Load an index once and search it interactively
+    Shell(ShellArgs),
+    /// Answer searches of an index over HTTP with JSON
+    Serve(ServeArgs),
+}
+
+#[derive(Clone,Copy,PartialEq,Eq,ValueEnum)]
+enum IndexFormat {
+    /// Loaded into memory before searching
+    Standard,
+    /// Searched in place without loading
+    Mapped,
+    /// Can have further texts appended
+    Segmented,
+    /// Searched a word at a time rather than a character at a time
+    Words,
+}
+
+#[derive(Args)]
+struct IndexArgs {
+    /// Path to write the index to
+    #[arg(short, long)]
+    output: String,
+    /// Files, or directories of files, to add to the index
+    #[arg(required = true)]
+    inputs: Vec<String>,
+    #[arg(long, value_enum, default_value = "standard")]
+    format: IndexFormat,
+    /// Add the inputs as a new segment of an existing segmented index
+    #[arg(long, conflicts_with = "format")]
+    append: bool,
+    /// Ignore case when searching
+    #[arg(long)]
+    lowercase: bool,
+    /// String marking the boundaries between sentences in the inputs
+    #[arg(long)]
+    splitter: Option<String>,
+    /// Don't store the texts in the index, read them from the input files
+    /// when they are needed
+    #[arg(long, conflicts_with = "blob_file")]
+    source_files: bool,
+    /// Store the texts in this file rather than in the index. A relative
+    /// path is relative to the directory of the index.
+    #[arg(long)]
+    blob_file: Option<String>,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Path of the index to search
+    index: String,
+    /// Pattern to search for
+    pattern: String,
+    #[command(flatten)]
+    settings: SearchSettings,
+    #[command(flatten)]
+    output: OutputSettings,
+    /// Show at most this many matches
+    #[arg(short = 'm', long)]
+    max_count: Option<usize>,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// Path of the index to search
+    index: String,
+    /// Terms to search for, combined with AND, OR, NOT and parentheses
+    query: String,
+    /// Settings used to search for each term
+    #[command(flatten)]
+    settings: SearchSettings,
+    /// Whether each sentence, line or text is checked against the query
+    #[arg(long, value_enum, default_value = "sentence")]
+    scope: Scope,
+    #[command(flatten)]
+    output: OutputSettings,
+    /// Show at most this many sentences, lines or texts
+    #[arg(short = 'm', long)]
+    max_count: Option<usize>,
+}
+
+#[derive(Args)]
+struct NearArgs {
+    /// Path of the index to search
+    index: String,
+    /// Two terms to search for, with NEAR/k or BEFORE/k between them
+    query: String,
+    /// Settings used to search for each term
+    #[command(flatten)]
+    settings: SearchSettings,
+    /// Whether the distance is a number of words or of characters
+    #[arg(long, value_enum, default_value = "words")]
+    unit: Unit,
+    #[command(flatten)]
+    output: OutputSettings,
+    /// Show at most this many matches
+    #[arg(short = 'm', long)]
+    max_count: Option<usize>,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// Path of the index to search
+    index: String,
+    /// Pattern to search for
+    pattern: String,
+    #[command(flatten)]
+    settings: SearchSettings,
+    /// Also count the hits in each range of this many lines of each text
+    #[arg(long)]
+    lines_per_range: Option<usize>,
+    #[arg(long, value_enum, default_value = "table")]
+    format: StatsFormat,
+}
+
+#[derive(Args)]
+struct ReuseArgs {
+    /// Path of the index, which must be in the standard or mapped format
+    index: String,
+    /// Length of the shortest shared string counted as part of a passage
+    #[arg(long, default_value_t = 20)]
+    min_length: usize,
+    /// Most characters allowed between the shared strings of a passage
+    #[arg(long, default_value_t = 10)]
+    max_gap: usize,
+    #[arg(long, value_enum, default_value = "csv")]
+    format: ReuseFormat,
+}
+
+#[derive(Args)]
+struct NgramsArgs {
+    /// Path of the index, which must be in the standard or mapped format
+    index: String,
+    /// Number of characters or words in each n-gram
+    #[arg(short, default_value_t = 3)]
+    n: usize,
+    /// Count n-grams of words separated by whitespace rather than of
+    /// characters
+    #[arg(long)]
+    words: bool,
+    /// Show only this many of the most frequent n-grams
+    #[arg(long)]
+    top: Option<usize>,
+    /// Leave out n-grams occurring fewer times than this
+    #[arg(long, default_value_t = 1)]
+    min_count: usize,
+    /// Count the n-grams of each text separately
+    #[arg(long)]
+    per_text: bool,
+}
+
+#[derive(Args)]
+struct DistinctiveArgs {
+    /// Path of the index, which must be in the standard or mapped format
+    index: String,
+    /// Names of the texts in the first group
+    #[arg(short = 'a', long, num_args = 1.., required = true)]
+    group_a: Vec<String>,
+    /// Names of the texts in the second group
+    #[arg(short = 'b', long, num_args = 1.., required = true)]
+    group_b: Vec<String>,
+    #[arg(long, default_value_t = 3)]
+    min_length: usize,
+    #[arg(long, default_value_t = 30)]
+    max_length: usize,
+    /// Leave out strings occurring fewer times than this in both groups
+    /// together
+    #[arg(long, default_value_t = 5)]
+    min_count: usize,
+    /// Show only this many of the most distinctive strings
+    #[arg(long, default_value_t = 50)]
+    top: usize,
+    #[arg(long, value_enum, default_value = "table")]
+    format: StatsFormat,
+}
+
+#[derive(Args)]
+struct CollocationsArgs {
+    /// Path of the index, which must be in the standard or mapped format
+    index: String,
+    /// Pattern to search for
+    pattern: String,
+    #[command(flatten)]
+    settings: SearchSettings,
+    /// Number of words before each match to count
+    #[arg(long, default_value_t = 5)]
+    left: usize,
+    /// Number of words after each match to count
+    #[arg(long, default_value_t = 5)]
+    right: usize,
+    /// Leave out words found fewer times than this near the matches
+    #[arg(long, default_value_t = 1)]
+    min_count: usize,
+    #[arg(long, value_enum, default_value = "frequency")]
+    sort: CollocateSort,
+    /// Show only this many words
+    #[arg(long, default_value_t = 20)]
+    top: usize,
+    #[arg(long, value_enum, default_value = "table")]
+    format: StatsFormat,
+}
+
+#[derive(Args)]
+struct ShellArgs {
+    /// Path of the index to search
+    index: String,
+    /// Initial search settings, which can be changed from the shell
+    #[command(flatten)]
+    settings: SearchSettings,
+    /// Initial output settings, which can be changed from the shell
+    #[command(flatten)]
+    output: OutputSettings,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Path of the index to search
+    index: String,
+    /// Address to listen on. Use port 0 to pick any free port.
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    address: String,
+    /// Number of requests to handle at once
+    #[arg(short, long, default_value_t = 4)]
+    threads: usize,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// Path of the index
+    index: String,
+}
+
+/// Load a standard, mapped or word index from the path and evaluate the
+/// body with the trie bound to the given name, for analyses which walk the
+/// whole trie of characters. Each segment of a segmented index has its own
+/// trie, so for one of those the given error is returned instead.
+macro_rules! with_trie {
+    ($path:expr, $segmented_error:expr, |$trie:ident| $body:expr) => {
+        match with_path(detect_index_kind($path), $path)? {
+            IndexKind::Standard => {
+                let $trie = with_path(SuffixTrie::load($path), $path)?;
+                $body
+            },
+            IndexKind::Mapped => {
+                let $trie = with_path(MappedSuffixTrie::open($path), $path)?;
+                $body
+            },
+            IndexKind::Words => {
+                let $trie = with_path(WordIndex::open($path), $path)?.into_trie();
+                $body
+            },
+            IndexKind::Segmented => return Err(Error::new(ErrorKind::InvalidInput, $segmented_error)),
+        }
+    }
+}
+
+/// Add the path to errors from opening a file, so the user knows which
+/// file couldn't be opened
+fn with_path<T>(result: Result<T, io::Error>, path: &str) -> Result<T, io::Error> {
+    result.map_err(|error| Error::new(error.kind(), format!("{}: {}", path, error)))
+}
+
+fn main() {
+    env_logger::init();
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Index(args) => run_index(args),
+        Command::Search(args) => run_search(args),
+        Command::Query(args) => run_query(args),
+        Command::Near(args) => run_near(args),
+        Command::Stats(args) => run_stats(args),
+        Command::Reuse(args) => run_reuse(args),
+        Command::Ngrams(args) => run_ngrams(args),
+        Command::Distinctive(args) => run_distinctive(args),
+        Command::Collocations(args) => run_collocations(args),
+        Command::Info(args) => run_info(args),
+        Command::Shell(args) => run_shell(args),
+        Command::Serve(args) => run_serve(args),
+    };
+    if let Err(error) = result {
+        eprintln!("spyglass: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run_index(args: IndexArgs) -> Result<(), io::Error> {
+    let mut options = BuildOptions::default();
+    if args.lowercase {
+        options.normaliser = Normaliser::DeunicodeLowercase;
+    }
+    if let Some(splitter) = args.splitter {
+        options.sentence_splitter = splitter;
+    }
+
+    let mut trie = SuffixTrie::empty_with_options(options);
+    for input in args.inputs.iter() {
+        if Path::new(input).is_dir() {
+            with_path(trie.add_directory(input), input)?;
+        } else {
+            with_path(trie.add_file(input), input)?;
+        }
+    }
+
+    if args.append {
+        let mut index = with_path(SegmentedIndex::open(&args.output), &args.output)?;
+        index.append(trie)?;
+        return Ok(());
+    }
+
+    let text_store = if args.source_files {
+        TextStore::SourceFiles
+    } else if let Some(blob_file) = args.blob_file {
+        TextStore::BlobFile(blob_file)
+    } else {
+        TextStore::Embedded
+    };
+    if text_store != TextStore::Embedded && args.format != IndexFormat::Standard {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "Texts can only be stored outside the index in the standard format"));
+    }
+
+    match args.format {
+        IndexFormat::Standard => trie.save_with_text_store(&args.output, &text_store),
+        IndexFormat::Mapped => trie.save_mapped(&args.output),
+        IndexFormat::Segmented => SegmentedIndex::create(&args.output, trie).map(|_| ()),
+        IndexFormat::Words => WordIndex::new(trie).save(&args.output),
+    }
+}
+
+fn run_search(args: SearchArgs) -> Result<(), io::Error> {
+    let index = with_path(load_index(&args.index), &args.index)?;
+    if index.matches_words() && (args.settings.wildcard || !args.settings.ignore.is_empty()) {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "A word index matches whole words, so can't ignore characters or match wildcards"));
+    }
+    let mut matches = args.settings.search(index.as_ref(), &args.pattern);
+    if let Some(max_count) = args.max_count {
+        matches.truncate(max_count);
+    }
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    let query = Query { pattern: &args.pattern, settings: &args.settings };
+    output::write_matches(&mut writer, index.as_ref(), &matches, &query, &args.output)?;
+    writer.flush()
+}
+
+fn run_query(args: QueryArgs) -> Result<(), io::Error> {
+    let query = BooleanQuery::parse(&args.query)?;
+    let options = args.settings.boolean_options(args.scope);
+    let index = with_path(load_index(&args.index), &args.index)?;
+    let hits = index.boolean_search(&query, &options)?;
+    let mut spans: Vec<Match> = hits.into_iter().map(|hit| hit.span).collect();
+    if let Some(max_count) = args.max_count {
+        spans.truncate(max_count);
+    }
+
+    // Each span is a whole sentence, line or text rather than a match of a
+    // single pattern, so there are no errors to mark
+    let output = OutputSettings { highlight: Highlight::None, ..args.output };
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    let query = Query { pattern: &args.query, settings: &args.settings };
+    output::write_matches(&mut writer, index.as_ref(), &spans, &query, &output)?;
+    writer.flush()
+}
+
+fn run_near(args: NearArgs) -> Result<(), io::Error> {
+    let query = ProximityQuery::parse(&args.query)?;
+    let options = args.settings.proximity_options(args.unit);
+    let index = with_path(load_index(&args.index), &args.index)?;
+    let mut matches = index.find_near(&query, &options);
+    if let Some(max_count) = args.max_count {
+        matches.truncate(max_count);
+    }
+
+    // The errors of a span can't be marked against either term alone
+    let output = OutputSettings { highlight: Highlight::None, ..args.output };
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    let query = Query { pattern: &args.query, settings: &args.settings };
+    output::write_matches(&mut writer, index.as_ref(), &matches, &query, &output)?;
+    writer.flush()
+}
+
+fn run_stats(args: StatsArgs) -> Result<(), io::Error> {
+    let index = with_path(load_index(&args.index), &args.index)?;
+    let stats = match args.lines_per_range {
+        // Exact matches can be counted without finding where they are
+        None if args.settings.is_exact() => SearchStats::from_exact_counts(index.as_ref(), &args.pattern),
+        _ => {
+            let matches = args.settings.search(index.as_ref(), &args.pattern);
+            let mut stats = SearchStats::from_matches(index.as_ref(), &matches);
+            if let Some(lines_per_range) = args.lines_per_range {
+                stats.add_line_ranges(&matches, lines_per_range);
+            }
+            stats
+        },
+    };
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    output::write_stats(&mut writer, &stats, args.format)?;
+    writer.flush()
+}
+
+fn run_reuse(args: ReuseArgs) -> Result<(), io::Error> {
+    let graph = with_trie!(&args.index, "Reuse can't be found in a segmented index",
+                           |trie| trie.reuse_graph(args.min_length, args.max_gap));
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    output::write_reuse_graph(&mut writer, &graph, args.format)?;
+    writer.flush()
+}
+
+fn run_ngrams(args: NgramsArgs) -> Result<(), io::Error> {
+    let options = NgramOptions {
+        unit: if args.words { NgramUnit::Word } else { NgramUnit::Character },
+        n: args.n,
+        min_count: args.min_count,
+        top_k: args.top,
+    };
+    let tables: Vec<NgramTable> = with_trie!(&args.index, "N-grams can't be counted in a segmented index", |trie| {
+        if args.per_text { trie.ngram_frequencies_per_text(&options) } else { vec![trie.ngram_frequencies(&options)] }
+    });
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    write_ngram_csv(&mut writer, &tables)?;
+    writer.flush()
+}
+
+fn run_distinctive(args: DistinctiveArgs) -> Result<(), io::Error> {
+    let options = DistinctiveOptions {
+        min_length: args.min_length,
+        max_length: args.max_length,
+        min_count: args.min_count,
+        top_k: Some(args.top),
+    };
+    let group_a: Vec<&str> = args.group_a.iter().map(|name| name.as_str()).collect();
+    let group_b: Vec<&str> = args.group_b.iter().map(|name| name.as_str()).collect();
+    let phrases: Vec<DistinctivePhrase> = with_trie!(&args.index, "Distinctive strings can't be found in a segmented index",
+                                                     |trie| trie.find_distinctive_phrases(&group_a, &group_b, &options)?);
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    output::write_distinctive_phrases(&mut writer, &phrases, args.format)?;
+    writer.flush()
+}
+
+fn run_collocations(args: CollocationsArgs) -> Result<(), io::Error> {
+    let options = CollocationOptions {
+        left: args.left,
+        right: args.right,
+        min_count: args.min_count,
+        sort: args.sort.into(),
+        top_k: Some(args.top),
+    };
+    let collocates: Vec<Collocate> = with_trie!(&args.index, "Collocations can't be found in a segmented index",
+                                                |trie| trie.collocations(&args.settings.search(&trie, &args.pattern), &options));
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    output::write_collocates(&mut writer, &collocates, args.format)?;
+    writer.flush()
+}
+
+fn run_info(args: InfoArgs) -> Result<(), io::Error> {
+    let kind = with_path(detect_index_kind(&args.index), &args.index)?;
+    let index: Box<dyn SearchIndex> = with_path(load_index(&args.index), &args.index)?;
+    let size = fs::metadata(&args.index)?.len();
+
+    println!("Index: {}", args.index);
+    match kind {
+        IndexKind::Standard => {
+            let (version, text_store) = match IndexHeader::read(&args.index) {
+                Ok(header) => (header.version, header.text_store),
+                // Indexes saved before the format was versioned have no header
+                Err(_) => (0, TextStore::Embedded),
+            };
+            println!("Format: standard (version {})", version);
+            println!("Text store: {:?}", text_store);
+        },
+        IndexKind::Mapped => println!("Format: memory-mapped"),
+        IndexKind::Segmented => println!("Format: segmented"),
+        IndexKind::Words => println!("Format: words"),
+    }
+    println!("Size: {} bytes", size);
+    let options = index.get_options();
+    println!("Normaliser: {:?}", options.normaliser);
+    println!("Sentence splitter: {:?}", options.sentence_splitter);
+
+    let texts = index.get_text_info();
+    println!("Texts: {}", texts.len());
+    println!("{:>10} {:>8}  name", "chars", "lines");
+    for text in texts.iter() {
+        println!("{:>10} {:>8}  {}", text.num_chars, text.num_lines, text.name);
+    }
+    Ok(())
+}
+
+fn run_shell(args: ShellArgs) -> Result<(), io::Error> {
+    let index = with_path(load_index(&args.index), &args.index)?;
+    Shell::new(index, args.settings, args.output).run()
+}
+
+fn run_serve(args: ServeArgs) -> Result<(), io::Error> {
+    let index = with_path(load_index(&args.index), &args.index)?;
+    server::serve(index, &args.address, args.threads)
+}
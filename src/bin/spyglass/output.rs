@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{IsTerminal,Write};
+
+use clap::{Args,ValueEnum};
+
+use spyglass::{Collocate,CollocationSort,ConcordanceSort,DistinctivePhrase,HighlightStyle,Match,ReuseGraph,SearchIndex,SearchStats};
+
+use crate::search::SearchSettings;
+
+/// Ways of displaying a list of matches
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum OutputFormat {
+    /// Lines containing each match, prefixed with the text name and line number
+    Grep,
+    /// Only the matched part of the text, one match per line
+    Matches,
+    /// One JSON object per match, on its own line
+    Jsonl,
+    /// One CSV row per match, after a header row
+    Csv,
+    /// Keyword-in-context concordance, with the matches lined up in a column
+    Kwic,
+}
+
+/// Ways of displaying statistics of a search
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum StatsFormat {
+    /// A row for each text, followed by the totals
+    Table,
+    /// A single JSON object
+    Json,
+}
+
+/// Ways of exporting the texts sharing passages
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum ReuseFormat {
+    /// A row for each pair of texts sharing passages
+    Csv,
+    /// A GraphViz graph with a node for each text
+    Dot,
+    /// A single JSON object
+    Json,
+}
+
+/// Orders for words found near matches
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum CollocateSort {
+    /// Most often found near the matches first
+    Frequency,
+    /// By pointwise mutual information, favouring rare words
+    Pmi,
+    /// By t-score, favouring common words
+    TScore,
+}
+
+impl From<CollocateSort> for CollocationSort {
+    fn from(sort: CollocateSort) -> Self {
+        match sort {
+            CollocateSort::Frequency => CollocationSort::Frequency,
+            CollocateSort::Pmi => CollocationSort::Pmi,
+            CollocateSort::TScore => CollocationSort::TScore,
+        }
+    }
+}
+
+/// Orders for the lines of a kwic concordance
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum SortOrder {
+    /// In the order of the matches
+    Position,
+    /// Alphabetically by the words to the left, starting with the word
+    /// nearest the match
+    Left,
+    /// Alphabetically by the words to the right
+    Right,
+}
+
+impl From<SortOrder> for ConcordanceSort {
+    fn from(sort: SortOrder) -> Self {
+        match sort {
+            SortOrder::Position => ConcordanceSort::Position,
+            SortOrder::Left => ConcordanceSort::Left,
+            SortOrder::Right => ConcordanceSort::Right,
+        }
+    }
+}
+
+/// Ways of marking the errors in each match
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum Highlight {
+    /// Colours when writing to a terminal, otherwise brackets
+    Auto,
+    /// ANSI colours: bold for matching characters, red for mismatches,
+    /// yellow for extra characters and underlined cyan for missing ones
+    Ansi,
+    /// [mismatches], {extra characters} and <missing characters>
+    Brackets,
+    /// No marking
+    None,
+}
+
+impl Highlight {
+    fn style(self) -> HighlightStyle {
+        match self {
+            Highlight::Auto if io::stdout().is_terminal() => HighlightStyle::Ansi,
+            Highlight::Auto | Highlight::Brackets => HighlightStyle::Brackets,
+            Highlight::Ansi => HighlightStyle::Ansi,
+            Highlight::None => HighlightStyle::Plain,
+        }
+    }
+}
+
+/// The search which found the matches, used to mark the errors in each
+/// match
+pub struct Query<'a> {
+    pub pattern: &'a str,
+    pub settings: &'a SearchSettings,
+}
+
+/// How matches should be displayed
+#[derive(Args,Clone,Debug)]
+pub struct OutputSettings {
+    /// Number of lines of context to show around each match
+    #[arg(short = 'C', long, default_value_t = 0)]
+    pub context: usize,
+    #[arg(long, value_enum, default_value = "grep")]
+    pub format: OutputFormat,
+    /// Number of characters of context either side of each match in the
+    /// kwic format
+    #[arg(long, default_value_t = 40)]
+    pub width: usize,
+    /// Order of the matches in the kwic format
+    #[arg(long, value_enum, default_value = "position")]
+    pub sort: SortOrder,
+    /// How to mark the errors in each match in the grep, matches and kwic
+    /// formats
+    #[arg(long, value_enum, default_value = "auto")]
+    pub highlight: Highlight,
+}
+
+/// Write matches with the given settings
+pub fn write_matches<W: Write>(writer: &mut W,
+                               index: &dyn SearchIndex,
+                               matches: &[Match],
+                               query: &Query,
+                               settings: &OutputSettings) -> Result<(), io::Error> {
+    // The errors of a word index are whole words, which can't be marked
+    // character by character against the pattern
+    let style = if index.matches_words() { HighlightStyle::Plain } else { settings.highlight.style() };
+    let highlighter = Highlighter::new(index, query, style);
+    match settings.format {
+        OutputFormat::Grep => write_grep(writer, index, matches, settings.context, &highlighter),
+        OutputFormat::Matches => write_matched_strings(writer, index, matches, &highlighter),
+        OutputFormat::Jsonl => spyglass::write_json_lines(writer, index, matches, settings.context),
+        OutputFormat::Csv => spyglass::write_csv(writer, index, matches, settings.context),
+        OutputFormat::Kwic => write_kwic(writer, index, matches, settings.width, settings.sort.into(), &highlighter),
+    }
+}
+
+/// Marks the errors in matches of a query
+pub struct Highlighter {
+    pattern: String,
+    ignored_characters: HashMap<char, bool>,
+    wildcards: bool,
+    style: HighlightStyle,
+}
+
+impl Highlighter {
+    pub fn new(index: &dyn SearchIndex, query: &Query, style: HighlightStyle) -> Self {
+        Highlighter {
+            // The matched strings are normalised, so the pattern must be too
+            pattern: index.get_options().normaliser.normalise(query.pattern),
+            ignored_characters: query.settings.ignored_characters(),
+            wildcards: query.settings.wildcard,
+            style,
+        }
+    }
+
+    /// Render the matched string of a match with its errors marked
+    pub fn render(&self, matching: &str) -> String {
+        if self.style == HighlightStyle::Plain {
+            return matching.to_string();
+        }
+        let alignment = spyglass::align_pattern(&self.pattern, matching, &self.ignored_characters, self.wildcards);
+        spyglass::render_alignment(&alignment, self.style)
+    }
+}
+
+/// Write matches in the style of grep. Each line of a match and its context
+/// is prefixed with the name of the text and the line number, followed by
+/// ':' for lines of the match and '-' for lines of context.
+pub fn write_grep<W: Write>(writer: &mut W,
+                            index: &dyn SearchIndex,
+                            matches: &[Match],
+                            context_lines: usize,
+                            highlighter: &Highlighter) -> Result<(), io::Error> {
+    let text_names = index.get_text_names();
+    for (match_number, match_obj) in matches.iter().enumerate() {
+        if context_lines > 0 && match_number > 0 {
+            writeln!(writer, "--")?;
+        }
+        let (before, matching, after) = index.get_strings_of_match(match_obj, context_lines);
+        let first_line = match_obj.start_line - before.matches('\n').count();
+        let lines = format!("{}{}{}", before, highlighter.render(&matching), after);
+        for (line_offset, line) in lines.lines().enumerate() {
+            let line_index = first_line + line_offset;
+            let separator = if line_index >= match_obj.start_line && line_index <= match_obj.end_line {
+                ':'
+            } else {
+                '-'
+            };
+            // Line numbers are shown starting from 1, as in other tools
+            writeln!(writer, "{}{}{}{}{}",
+                     text_names[match_obj.text_index], separator, line_index + 1, separator, line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write just the matched string of each match, with newlines replaced by
+/// spaces so that each match is on a single line
+pub fn write_matched_strings<W: Write>(writer: &mut W,
+                                       index: &dyn SearchIndex,
+                                       matches: &[Match],
+                                       highlighter: &Highlighter) -> Result<(), io::Error> {
+    for match_obj in matches.iter() {
+        let (_, matching, _) = index.get_strings_of_match(match_obj, 0);
+        writeln!(writer, "{}", highlighter.render(&matching).replace('\n', " "))?;
+    }
+    Ok(())
+}
+
+/// Write a concordance of the matches, each prefixed with the name of its
+/// text and its line number padded so that the matches line up
+pub fn write_kwic<W: Write>(writer: &mut W,
+                            index: &dyn SearchIndex,
+                            matches: &[Match],
+                            width: usize,
+                            sort: ConcordanceSort,
+                            highlighter: &Highlighter) -> Result<(), io::Error> {
+    let text_names = index.get_text_names();
+    let mut lines = spyglass::concordance(index, matches, width);
+    spyglass::sort_concordance(&mut lines, sort);
+
+    let locations: Vec<String> = lines.iter().map(|line| {
+        format!("{}:{}:", text_names[line.match_obj.text_index], line.match_obj.start_line + 1)
+    }).collect();
+    let location_width = locations.iter().map(|location| location.chars().count()).max().unwrap_or(0);
+    for (location, line) in locations.iter().zip(lines.iter()) {
+        let (_, matching, _) = index.get_strings_of_match(&line.match_obj, 0);
+        let keyword = highlighter.render(&matching).replace(['\n', '\r', '\t'], " ");
+        writeln!(writer, "{:<location_width$} {:>width$}  {}  {}",
+                 location, line.left, keyword, line.right, location_width = location_width, width = width)?;
+    }
+    Ok(())
+}
+
+/// Write statistics of a search in the given format
+pub fn write_stats<W: Write>(writer: &mut W,
+                             stats: &SearchStats,
+                             format: StatsFormat) -> Result<(), io::Error> {
+    match format {
+        StatsFormat::Table => write_stats_table(writer, stats),
+        StatsFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, stats)?;
+            writeln!(writer)
+        },
+    }
+}
+
+/// Write the texts sharing passages in the given format
+pub fn write_reuse_graph<W: Write>(writer: &mut W,
+                                   graph: &ReuseGraph,
+                                   format: ReuseFormat) -> Result<(), io::Error> {
+    match format {
+        ReuseFormat::Csv => graph.write_csv(writer),
+        ReuseFormat::Dot => graph.write_dot(writer),
+        ReuseFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, graph)?;
+            writeln!(writer)
+        },
+    }
+}
+
+/// Write the distinctive strings in the given format. Tables show the
+/// strings quoted, so that spaces and newlines can be seen.
+pub fn write_distinctive_phrases<W: Write>(writer: &mut W,
+                                           phrases: &[DistinctivePhrase],
+                                           format: StatsFormat) -> Result<(), io::Error> {
+    match format {
+        StatsFormat::Table => {
+            writeln!(writer, "{:>10} {:>5} {:>8} {:>8} {:>10} {:>10}  string",
+                     "log-lik", "group", "count a", "count b", "a per 10k", "b per 10k")?;
+            for phrase in phrases.iter() {
+                writeln!(writer, "{:>10.2} {:>5} {:>8} {:>8} {:>10.3} {:>10.3}  {:?}",
+                         phrase.log_likelihood, format!("{:?}", phrase.favours), phrase.count_a, phrase.count_b,
+                         phrase.per_10k_chars_a, phrase.per_10k_chars_b, phrase.string)?;
+            }
+            Ok(())
+        },
+        StatsFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, phrases)?;
+            writeln!(writer)
+        },
+    }
+}
+
+/// Write the words found near matches in the given format
+pub fn write_collocates<W: Write>(writer: &mut W,
+                                  collocates: &[Collocate],
+                                  format: StatsFormat) -> Result<(), io::Error> {
+    match format {
+        StatsFormat::Table => {
+            writeln!(writer, "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8}  word",
+                     "count", "left", "right", "in index", "pmi", "t-score")?;
+            for collocate in collocates.iter() {
+                writeln!(writer, "{:>8} {:>8} {:>8} {:>8} {:>8.3} {:>8.3}  {}",
+                         collocate.count, collocate.left_count, collocate.right_count,
+                         collocate.corpus_count, collocate.pmi, collocate.t_score, collocate.word)?;
+            }
+            Ok(())
+        },
+        StatsFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, collocates)?;
+            writeln!(writer)
+        },
+    }
+}
+
+/// Write a row of statistics for each text, followed by any breakdown by
+/// lines, and a final row of totals. Line numbers are shown starting from 1.
+pub fn write_stats_table<W: Write>(writer: &mut W, stats: &SearchStats) -> Result<(), io::Error> {
+    writeln!(writer, "{:>8} {:>10} {:>10} {:>8}  name", "hits", "per 10k", "chars", "lines")?;
+    for text in stats.texts.iter() {
+        writeln!(writer, "{:>8} {:>10.3} {:>10} {:>8}  {}",
+                 text.hits, text.hits_per_10k_chars, text.num_chars, text.num_lines, text.text_name)?;
+        for range in text.line_ranges.iter() {
+            writeln!(writer, "{:>8} {:>10} {:>10} {:>8}  lines {}-{}",
+                     range.hits, "", "", "", range.start_line + 1, range.end_line + 1)?;
+        }
+    }
+    writeln!(writer, "{:>8} {:>10.3} {:>10} {:>8}  total ({} of {} texts with hits)",
+             stats.total_hits, stats.hits_per_10k_chars, stats.total_chars, "",
+             stats.texts_with_hits, stats.texts.len())
+}
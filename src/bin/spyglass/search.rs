@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use clap::{Args,ValueEnum};
+
+use spyglass::{BooleanOptions,DistanceUnit,Match,ProximityOptions,QueryScope,SearchIndex};
+
+/// How a pattern should be searched for
+#[derive(Args,Clone,Debug,Default)]
+pub struct SearchSettings {
+    /// Maximum number of errors (mismatches, insertions and deletions)
+    #[arg(short, long, default_value_t = 0)]
+    pub errors: usize,
+    /// Characters which can be skipped or matched to anything without
+    /// counting as an error
+    #[arg(short, long, default_value = "")]
+    pub ignore: String,
+    /// Treat '?' as matching any character and '*' as matching any run of
+    /// word characters
+    #[arg(short, long)]
+    pub wildcard: bool,
+}
+
+impl SearchSettings {
+    pub fn search(&self, index: &dyn SearchIndex, pattern: &str) -> Vec<Match> {
+        let ignored_characters = self.ignored_characters();
+        if self.wildcard {
+            index.find_wildcard_ignore(pattern, self.errors, ignored_characters)
+        } else if !self.is_exact() {
+            index.find_edit_distance_ignore(pattern, self.errors, ignored_characters)
+        } else {
+            index.find_exact(pattern)
+        }
+    }
+
+    /// Whether the settings only allow exact matches
+    pub fn is_exact(&self) -> bool {
+        self.errors == 0 && self.ignore.is_empty() && !self.wildcard
+    }
+
+    pub fn ignored_characters(&self) -> HashMap<char, bool> {
+        self.ignore.chars().map(|c| (c, true)).collect()
+    }
+
+    /// Options for searching for each term of a boolean query
+    pub fn boolean_options(&self, scope: Scope) -> BooleanOptions {
+        BooleanOptions {
+            scope: scope.into(),
+            max_errors: self.errors,
+            ignored_characters: self.ignored_characters(),
+            wildcards: self.wildcard,
+        }
+    }
+
+    /// Options for searching for each term of a proximity query
+    pub fn proximity_options(&self, unit: Unit) -> ProximityOptions {
+        ProximityOptions {
+            unit: unit.into(),
+            max_errors: self.errors,
+            ignored_characters: self.ignored_characters(),
+            wildcards: self.wildcard,
+        }
+    }
+}
+
+/// Units a boolean query is evaluated on
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum Scope {
+    Sentence,
+    Line,
+    Text,
+}
+
+impl From<Scope> for QueryScope {
+    fn from(scope: Scope) -> Self {
+        match scope {
+            Scope::Sentence => QueryScope::Sentence,
+            Scope::Line => QueryScope::Line,
+            Scope::Text => QueryScope::Text,
+        }
+    }
+}
+
+/// What the distance in a proximity query is measured in
+#[derive(Clone,Copy,Debug,PartialEq,Eq,ValueEnum)]
+pub enum Unit {
+    Words,
+    Characters,
+}
+
+impl From<Unit> for DistanceUnit {
+    fn from(unit: Unit) -> Self {
+        match unit {
+            Unit::Words => DistanceUnit::Words,
+            Unit::Characters => DistanceUnit::Characters,
+        }
+    }
+}
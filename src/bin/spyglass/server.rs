@@ -0,0 +1,182 @@
+//! HTTP server answering searches of an index with JSON.
+//!
+//! Endpoints (all GET):
+//!
+//! * `/texts` lists the texts in the index
+//! * `/search?q=PATTERN` searches the index. Optional parameters are
+//!   `errors`, `ignore` and `wildcard` (as for `spyglass search`), and
+//!   `offset` and `limit` to page through the matches.
+//! * `/snippet?text_index=..&index_in_str=..&length=..` gets the text of a
+//!   match returned by `/search`, with `context` lines or
+//!   `context_sentences` sentences either side. The lines of the match are
+//!   worked out from its position, and `start_line` and `end_line` are
+//!   optional, but must agree with them if given.
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header,Method,Request,Response,Server};
+
+use spyglass::{Match,SearchIndex};
+
+use crate::search::SearchSettings;
+
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Serialize)]
+struct SearchResponse {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    matches: Vec<Match>,
+}
+
+#[derive(Serialize)]
+struct SnippetResponse {
+    before: String,
+    matching: String,
+    after: String,
+}
+
+/// Error to report to the client, with its HTTP status code
+struct RequestError {
+    status: u16,
+    message: String,
+}
+
+impl RequestError {
+    fn bad_request(message: String) -> Self {
+        RequestError { status: 400, message }
+    }
+}
+
+/// Serve the index on the given address until the process is killed
+pub fn serve(index: Box<dyn SearchIndex>, address: &str, threads: usize) -> Result<(), io::Error> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    // Report the actual address, as the port may have been chosen by the OS
+    println!("Listening on http://{}", server.server_addr());
+
+    let server = Arc::new(server);
+    let index: Arc<dyn SearchIndex> = Arc::from(index);
+    let workers: Vec<thread::JoinHandle<()>> = (0..threads.max(1)).map(|_| {
+        let server = Arc::clone(&server);
+        let index = Arc::clone(&index);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle(index.as_ref(), request);
+            }
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().map_err(|_| io::Error::other("Server thread panicked"))?;
+    }
+    Ok(())
+}
+
+fn handle(index: &dyn SearchIndex, request: Request) {
+    let url = request.url().to_string();
+    let (path, query) = match url.find('?') {
+        Some(question_mark) => (&url[.. question_mark], &url[question_mark + 1 ..]),
+        None => (url.as_str(), ""),
+    };
+    let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+
+    let result = if *request.method() != Method::Get {
+        Err(RequestError { status: 405, message: "Only GET requests are supported".to_string() })
+    } else {
+        match path {
+            "/texts" => Ok(json!(index.get_text_info())),
+            "/search" => search(index, &params),
+            "/snippet" => snippet(index, &params),
+            _ => Err(RequestError { status: 404, message: format!("Unknown endpoint {}", path) }),
+        }
+    };
+
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(error) => (error.status, json!({ "error": error.message })),
+    };
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+    if let Err(error) = request.respond(response) {
+        log::warn!("Failed to send response: {}", error);
+    }
+}
+
+fn search(index: &dyn SearchIndex,
+          params: &HashMap<String, String>) -> Result<serde_json::Value, RequestError> {
+    let pattern = params.get("q")
+        .ok_or_else(|| RequestError::bad_request("Missing parameter q".to_string()))?;
+    let settings = SearchSettings {
+        errors: number_param(params, "errors", 0)?,
+        ignore: params.get("ignore").cloned().unwrap_or_default(),
+        wildcard: bool_param(params, "wildcard")?,
+    };
+    let offset = number_param(params, "offset", 0)?;
+    let limit = number_param(params, "limit", DEFAULT_LIMIT)?;
+
+    let matches = settings.search(index, pattern);
+    let page = matches.iter().skip(offset).take(limit).cloned().collect();
+    Ok(json!(SearchResponse { total: matches.len(), offset, limit, matches: page }))
+}
+
+fn snippet(index: &dyn SearchIndex,
+           params: &HashMap<String, String>) -> Result<serde_json::Value, RequestError> {
+    let text_index = required_number_param(params, "text_index")?;
+    let index_in_str = required_number_param(params, "index_in_str")?;
+    let length = required_number_param(params, "length")?;
+    let context_lines = number_param(params, "context", 0)?;
+    let context_sentences = params.get("context_sentences")
+        .map(|_| required_number_param(params, "context_sentences"))
+        .transpose()?;
+
+    // The match comes from the client, so work out its lines from its
+    // position rather than trusting them, and check it lies within the index
+    let match_obj = Match {
+        errors: number_param(params, "errors", 0)?,
+        ..index.get_match_at(text_index, index_in_str, length)
+            .ok_or_else(|| RequestError::bad_request("Match does not lie within a text".to_string()))?
+    };
+    for (name, line) in [("start_line", match_obj.start_line), ("end_line", match_obj.end_line)] {
+        if params.contains_key(name) && required_number_param(params, name)? != line {
+            return Err(RequestError::bad_request(format!("The match is on line {} but {} is {}",
+                                                         line, name, params[name])));
+        }
+    }
+
+    let (before, matching, after) = match context_sentences {
+        Some(context_sentences) => index.get_sentences_of_match(&match_obj, context_sentences),
+        None => index.get_strings_of_match(&match_obj, context_lines),
+    };
+    Ok(json!(SnippetResponse { before, matching, after }))
+}
+
+fn required_number_param(params: &HashMap<String, String>,
+                         name: &str) -> Result<usize, RequestError> {
+    let value = params.get(name)
+        .ok_or_else(|| RequestError::bad_request(format!("Missing parameter {}", name)))?;
+    value.parse()
+        .map_err(|_| RequestError::bad_request(format!("Expected a number for {}, got '{}'", name, value)))
+}
+
+fn number_param(params: &HashMap<String, String>,
+                name: &str,
+                default: usize) -> Result<usize, RequestError> {
+    if params.contains_key(name) {
+        required_number_param(params, name)
+    } else {
+        Ok(default)
+    }
+}
+
+fn bool_param(params: &HashMap<String, String>, name: &str) -> Result<bool, RequestError> {
+    match params.get(name).map(|value| value.as_str()) {
+        None | Some("false") | Some("0") => Ok(false),
+        Some("true") | Some("1") | Some("") => Ok(true),
+        Some(value) => Err(RequestError::bad_request(format!("Expected true or false for {}, got '{}'", name, value))),
+    }
+}
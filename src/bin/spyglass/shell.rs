@@ -0,0 +1,215 @@
+use std::env;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::ValueEnum;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use spyglass::{Match,SearchIndex};
+
+use crate::output;
+use crate::output::{Highlight,OutputFormat,OutputSettings,Query,SortOrder};
+use crate::search::SearchSettings;
+
+const HISTORY_FILE: &str = ".spyglass_history";
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+const HELP: &str = "\
+Type a pattern to search for it, or one of the following commands:
+  :search PATTERN  search for a pattern, even one starting with ':'
+  ::PATTERN        search for ':PATTERN'
+  :errors N        allow up to N errors (mismatches, insertions and deletions)
+  :ignore CHARS    characters which can be skipped or matched to anything
+  :wildcard on|off treat '?' and '*' as wildcards
+  :context N       show N lines of context around each match
+  :format FORMAT   display matches as 'grep', 'matches', 'jsonl', 'csv' or 'kwic'
+  :width N         show N characters either side of each match in kwic format
+  :sort ORDER      sort kwic lines by 'position', 'left' or 'right' context
+  :highlight MODE  mark errors with 'auto', 'ansi', 'brackets' or 'none'
+  :page N          show N matches at a time (0 to show all)
+  :more            show the next page of matches (or press Enter)
+  :settings        show the current settings
+  :help            show this message
+  :quit            exit the shell";
+
+/// Interactive shell which searches an index loaded once at startup
+pub struct Shell {
+    index: Box<dyn SearchIndex>,
+    settings: SearchSettings,
+    output: OutputSettings,
+    page_size: usize,
+    // Last pattern searched for, and its matches which haven't been shown yet
+    pattern: String,
+    remaining: Vec<Match>,
+}
+
+impl Shell {
+    pub fn new(index: Box<dyn SearchIndex>, settings: SearchSettings, output: OutputSettings) -> Self {
+        Shell {
+            index,
+            settings,
+            output,
+            page_size: DEFAULT_PAGE_SIZE,
+            pattern: String::new(),
+            remaining: vec![],
+        }
+    }
+
+    /// Read lines from the user until they quit or input ends
+    pub fn run(&mut self) -> Result<(), io::Error> {
+        let mut editor = DefaultEditor::new().map_err(to_io_error)?;
+        let history_path = history_path();
+        if let Some(path) = &history_path {
+            // There is no history the first time the shell is run
+            let _ = editor.load_history(path);
+        }
+
+        println!("Loaded {} texts. Type :help for a list of commands.", self.index.get_text_info().len());
+        let stdout = io::stdout();
+        loop {
+            let line = match editor.readline("spyglass> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(error) => return Err(to_io_error(error)),
+            };
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.as_str()).map_err(to_io_error)?;
+            }
+
+            let mut writer = stdout.lock();
+            let keep_going = self.execute(&line, &mut writer)?;
+            writer.flush()?;
+            if !keep_going {
+                break;
+            }
+        }
+
+        if let Some(path) = &history_path {
+            if let Err(error) = editor.save_history(path) {
+                eprintln!("spyglass: couldn't save history: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Carry out a single command or query, writing any output to writer.
+    /// Returns false if the user asked to quit.
+    pub fn execute<W: Write>(&mut self, line: &str, writer: &mut W) -> Result<bool, io::Error> {
+        let line = line.trim();
+        if line.is_empty() {
+            self.write_page(writer)?;
+            return Ok(true);
+        }
+        if !line.starts_with(':') {
+            self.search(line, writer)?;
+            return Ok(true);
+        }
+        // A doubled colon escapes a pattern which starts with one
+        if let Some(pattern) = line.strip_prefix("::") {
+            self.search(&format!(":{}", pattern), writer)?;
+            return Ok(true);
+        }
+
+        let (command, argument) = match line.find(' ') {
+            Some(space) => (&line[.. space], line[space + 1 ..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            ":quit" | ":q" => return Ok(false),
+            ":help" => writeln!(writer, "{}", HELP)?,
+            ":search" if !argument.is_empty() => self.search(argument, writer)?,
+            ":search" => writeln!(writer, "Expected a pattern to search for")?,
+            ":more" => self.write_page(writer)?,
+            ":settings" => self.write_settings(writer)?,
+            ":errors" => match argument.parse() {
+                Ok(errors) => self.settings.errors = errors,
+                Err(_) => writeln!(writer, "Expected a number of errors, got '{}'", argument)?,
+            },
+            ":context" => match argument.parse() {
+                Ok(context_lines) => self.output.context = context_lines,
+                Err(_) => writeln!(writer, "Expected a number of lines, got '{}'", argument)?,
+            },
+            ":page" => match argument.parse() {
+                Ok(page_size) => self.page_size = page_size,
+                Err(_) => writeln!(writer, "Expected a number of matches, got '{}'", argument)?,
+            },
+            ":ignore" => self.settings.ignore = argument.to_string(),
+            ":wildcard" => match argument {
+                "on" => self.settings.wildcard = true,
+                "off" => self.settings.wildcard = false,
+                _ => writeln!(writer, "Expected 'on' or 'off', got '{}'", argument)?,
+            },
+            ":format" => match OutputFormat::from_str(argument, true) {
+                Ok(format) => self.output.format = format,
+                Err(_) => writeln!(writer, "Unknown format '{}'", argument)?,
+            },
+            ":width" => match argument.parse() {
+                Ok(width) => self.output.width = width,
+                Err(_) => writeln!(writer, "Expected a number of characters, got '{}'", argument)?,
+            },
+            ":highlight" => match Highlight::from_str(argument, true) {
+                Ok(highlight) => self.output.highlight = highlight,
+                Err(_) => writeln!(writer, "Unknown highlight mode '{}'", argument)?,
+            },
+            ":sort" => match SortOrder::from_str(argument, true) {
+                Ok(sort) => self.output.sort = sort,
+                Err(_) => writeln!(writer, "Unknown sort order '{}'", argument)?,
+            },
+            _ => writeln!(writer, "Unknown command '{}', type :help for a list of commands", command)?,
+        }
+        Ok(true)
+    }
+
+    fn search<W: Write>(&mut self, pattern: &str, writer: &mut W) -> Result<(), io::Error> {
+        let start = Instant::now();
+        let matches = self.settings.search(self.index.as_ref(), pattern);
+        let elapsed = start.elapsed();
+
+        writeln!(writer, "{} matches in {:.3} ms", matches.len(), elapsed.as_secs_f64() * 1000.0)?;
+        self.pattern = pattern.to_string();
+        self.remaining = matches;
+        self.write_page(writer)
+    }
+
+    fn write_page<W: Write>(&mut self, writer: &mut W) -> Result<(), io::Error> {
+        let page_length = if self.page_size == 0 {
+            self.remaining.len()
+        } else {
+            self.page_size.min(self.remaining.len())
+        };
+        let page: Vec<Match> = self.remaining.drain(.. page_length).collect();
+        let query = Query { pattern: &self.pattern, settings: &self.settings };
+        output::write_matches(writer, self.index.as_ref(), &page, &query, &self.output)?;
+        if !self.remaining.is_empty() {
+            writeln!(writer, "({} more matches, press Enter to show more)", self.remaining.len())?;
+        }
+        Ok(())
+    }
+
+    fn write_settings<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writeln!(writer, "errors: {}", self.settings.errors)?;
+        writeln!(writer, "ignore: {:?}", self.settings.ignore)?;
+        writeln!(writer, "wildcard: {}", if self.settings.wildcard { "on" } else { "off" })?;
+        writeln!(writer, "context: {}", self.output.context)?;
+        writeln!(writer, "format: {}", self.output.format.to_possible_value().unwrap().get_name())?;
+        writeln!(writer, "width: {}", self.output.width)?;
+        writeln!(writer, "sort: {}", self.output.sort.to_possible_value().unwrap().get_name())?;
+        writeln!(writer, "highlight: {}", self.output.highlight.to_possible_value().unwrap().get_name())?;
+        writeln!(writer, "page: {}", self.page_size)
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+fn to_io_error(error: ReadlineError) -> io::Error {
+    match error {
+        ReadlineError::Io(error) => error,
+        error => io::Error::other(error),
+    }
+}
@@ -0,0 +1,426 @@
+//! Boolean combinations of searches, such as the sentences containing both
+//! `king` and `sword` but not `Arthur`.
+//!
+//! Each term of a query is searched for on its own, and its matches are
+//! grouped by the sentence, line or text they are in. The query is then
+//! evaluated on each of these units, so `NOT` gives the units without a
+//! match of its term. A match crossing a line break is in each line it
+//! touches. Empty units are never part of the results.
+//!
+//! The operators `AND`, `OR` and `NOT` must be written in capitals. `NOT`
+//! binds most tightly and `OR` least, so `a OR b AND NOT c` means
+//! `a OR (b AND (NOT c))`, and parentheses can group terms differently.
+//! Words with no operator between them form a single phrase, and a phrase
+//! in double quotes may contain anything but a double quote, such as an
+//! operator or a parenthesis.
+use std::collections::{BTreeSet,HashMap};
+use std::io;
+use std::io::{Error,ErrorKind};
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::{Leaf,Match,Text,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SuffixTrie;
+
+    fn term(pattern: &str) -> Box<BooleanQuery> {
+        Box::new(BooleanQuery::Term(pattern.to_string()))
+    }
+
+    #[test]
+    fn parse_queries() {
+        assert_eq!(BooleanQuery::parse("round table").unwrap(), *term("round table"));
+        assert_eq!(BooleanQuery::parse("a OR b AND NOT c").unwrap(),
+                   BooleanQuery::Or(term("a"), Box::new(BooleanQuery::And(term("b"), Box::new(BooleanQuery::Not(term("c")))))));
+        assert_eq!(BooleanQuery::parse("(a OR b) AND \"NOT (c)\"").unwrap(),
+                   BooleanQuery::And(Box::new(BooleanQuery::Or(term("a"), term("b"))), term("NOT (c)")));
+
+        for invalid in ["", "a AND", "NOT", "(a OR b", "a) OR b", "a AND \"\"", "a \"b", "a \"b\""] {
+            assert_eq!(BooleanQuery::parse(invalid).unwrap_err().kind(), ErrorKind::InvalidInput, "{}", invalid);
+        }
+    }
+
+    fn units(hits: &[BooleanHit]) -> Vec<(usize, usize)> {
+        hits.iter().map(|hit| (hit.span.text_index, hit.unit)).collect()
+    }
+
+    #[test]
+    fn scopes() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "the king drew his sword<<STOP>>Arthur the king\nhad a sword<<STOP>>a sword");
+        trie.add_sentences_from_text("second", "king\nsword");
+        let query = BooleanQuery::parse("king AND sword AND NOT Arthur").unwrap();
+
+        let hits = trie.boolean_search(&query, &BooleanOptions::default()).unwrap();
+        assert_eq!(units(&hits), vec![(0, 0), (1, 0)]);
+        assert_eq!(trie.get_strings_of_match(&hits[0].span, 0).1, "the king drew his sword");
+        let matched: Vec<(usize, usize)> = hits[0].matches.iter()
+            .map(|match_obj| (match_obj.index_in_str, match_obj.length))
+            .collect();
+        assert_eq!(matched, vec![(4, 4), (18, 5)]);
+
+        let options = BooleanOptions { scope: QueryScope::Line, ..BooleanOptions::default() };
+        assert!(trie.boolean_search(&query, &options).unwrap().is_empty());
+        let hits = trie.boolean_search(&BooleanQuery::parse("sword AND NOT king").unwrap(), &options).unwrap();
+        assert_eq!(units(&hits), vec![(0, 1), (1, 1)]);
+
+        let options = BooleanOptions { scope: QueryScope::Text, ..BooleanOptions::default() };
+        let hits = trie.boolean_search(&BooleanQuery::parse("NOT Arthur").unwrap(), &options).unwrap();
+        assert_eq!(units(&hits), vec![(1, 0)]);
+        assert_eq!(hits[0].span.length, 10);
+        assert!(hits[0].matches.is_empty());
+    }
+
+    #[test]
+    fn approximate_terms() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "the knight<<STOP>>the night<<STOP>>the day");
+        let query = BooleanQuery::parse("knight OR day").unwrap();
+        assert_eq!(units(&trie.boolean_search(&query, &BooleanOptions::default()).unwrap()), vec![(0, 0), (0, 2)]);
+        let options = BooleanOptions { max_errors: 1, ..BooleanOptions::default() };
+        assert_eq!(units(&trie.boolean_search(&query, &options).unwrap()), vec![(0, 0), (0, 1), (0, 2)]);
+    }
+}
+
+/// The units a query is evaluated on
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum QueryScope {
+    Sentence,
+    Line,
+    Text,
+}
+
+/// A boolean combination of search terms
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum BooleanQuery {
+    Term(String),
+    And(Box<BooleanQuery>, Box<BooleanQuery>),
+    Or(Box<BooleanQuery>, Box<BooleanQuery>),
+    Not(Box<BooleanQuery>),
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub(crate) enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Quoted(String),
+}
+
+pub(crate) fn invalid_query(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("Invalid query: {}", message))
+}
+
+pub(crate) fn tokenise(query: &str) -> Result<Vec<Token>, io::Error> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err(invalid_query("missing closing quote")),
+                    }
+                }
+                tokens.push(Token::Quoted(phrase));
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|next| !next.is_whitespace() && !"()\"".contains(*next)) {
+                    word.push(next);
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, with a function for each level of precedence
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_if(&mut self, token: Token) -> bool {
+        let found = self.peek() == Some(&token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn parse_or(&mut self) -> Result<BooleanQuery, io::Error> {
+        let mut query = self.parse_and()?;
+        while self.next_if(Token::Or) {
+            query = BooleanQuery::Or(Box::new(query), Box::new(self.parse_and()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<BooleanQuery, io::Error> {
+        let mut query = self.parse_not()?;
+        while self.next_if(Token::And) {
+            query = BooleanQuery::And(Box::new(query), Box::new(self.parse_not()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_not(&mut self) -> Result<BooleanQuery, io::Error> {
+        if self.next_if(Token::Not) {
+            Ok(BooleanQuery::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_term()
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<BooleanQuery, io::Error> {
+        match self.peek().cloned() {
+            Some(Token::Open) => {
+                self.position += 1;
+                let query = self.parse_or()?;
+                if !self.next_if(Token::Close) {
+                    return Err(invalid_query("missing closing parenthesis"));
+                }
+                Ok(query)
+            },
+            Some(Token::Quoted(phrase)) => {
+                self.position += 1;
+                if phrase.is_empty() {
+                    return Err(invalid_query("empty quoted phrase"));
+                }
+                Ok(BooleanQuery::Term(phrase))
+            },
+            Some(Token::Word(_)) => {
+                let mut words = vec![];
+                while let Some(Token::Word(word)) = self.peek() {
+                    words.push(word.clone());
+                    self.position += 1;
+                }
+                Ok(BooleanQuery::Term(words.join(" ")))
+            },
+            Some(token) => Err(invalid_query(&format!("expected a search term but found {:?}", token))),
+            None => Err(invalid_query("expected a search term at the end")),
+        }
+    }
+}
+
+impl BooleanQuery {
+    /// Parse a query such as `king AND sword AND NOT Arthur`
+    pub fn parse(query: &str) -> Result<BooleanQuery, io::Error> {
+        let mut parser = Parser { tokens: tokenise(query)?, position: 0 };
+        let parsed = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(parsed),
+            Some(Token::Close) => Err(invalid_query("unmatched closing parenthesis")),
+            Some(token) => Err(invalid_query(&format!("expected AND or OR but found {:?}", token))),
+        }
+    }
+
+    /// Patterns of all the terms of the query
+    fn terms(&self) -> Vec<&str> {
+        match self {
+            BooleanQuery::Term(pattern) => vec![pattern],
+            BooleanQuery::And(a, b) | BooleanQuery::Or(a, b) => [a.terms(), b.terms()].concat(),
+            BooleanQuery::Not(query) => query.terms(),
+        }
+    }
+
+    /// Patterns of the terms which aren't inside a NOT
+    fn positive_terms(&self) -> Vec<&str> {
+        match self {
+            BooleanQuery::Term(pattern) => vec![pattern],
+            BooleanQuery::And(a, b) | BooleanQuery::Or(a, b) => [a.positive_terms(), b.positive_terms()].concat(),
+            BooleanQuery::Not(_) => vec![],
+        }
+    }
+}
+
+/// How to search for each term, and the units to evaluate a query on
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct BooleanOptions {
+    pub scope: QueryScope,
+    // Maximum errors in the matches of each term
+    pub max_errors: usize,
+    pub ignored_characters: HashMap<char, bool>,
+    pub wildcards: bool,
+}
+
+impl Default for BooleanOptions {
+    fn default() -> Self {
+        BooleanOptions {
+            scope: QueryScope::Sentence,
+            max_errors: 0,
+            ignored_characters: HashMap::new(),
+            wildcards: false,
+        }
+    }
+}
+
+/// A sentence, line or text which satisfies a query
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct BooleanHit {
+    // Index of the sentence or line within its text, or 0 for a whole text
+    pub unit: usize,
+    // The whole of the sentence, line or text
+    pub span: Match,
+    // Matches within it of the terms which aren't inside a NOT, in order
+    // of position
+    pub matches: Vec<Match>,
+}
+
+// Index of a text and of a unit within it
+type Unit = (usize, usize);
+
+fn num_units(text: &Text, scope: QueryScope) -> usize {
+    match scope {
+        QueryScope::Sentence => text.sentence_start_indices.as_ref().map_or(0, Vec::len),
+        QueryScope::Line => text.line_start_indices.len(),
+        QueryScope::Text => 1,
+    }
+}
+
+/// Characters of the unit, not including the line break ending a line
+fn unit_range(text: &Text, scope: QueryScope, unit: usize) -> Range<usize> {
+    let starts = match scope {
+        QueryScope::Sentence => text.sentence_start_indices.as_ref().unwrap(),
+        QueryScope::Line => &text.line_start_indices,
+        QueryScope::Text => return 0 .. text.last_index,
+    };
+    match starts.get(unit + 1) {
+        Some(next_start) if scope == QueryScope::Line => starts[unit] .. next_start - 1,
+        Some(next_start) => starts[unit] .. *next_start,
+        None => starts[unit] .. text.last_index,
+    }
+}
+
+/// Units the match is in
+fn units_of_match(text: &Text, scope: QueryScope, match_obj: &Match) -> Range<usize> {
+    match scope {
+        // Matches never cross sentence boundaries
+        QueryScope::Sentence => {
+            let sentence = text.get_sentence_of_character(match_obj.index_in_str).unwrap();
+            sentence .. sentence + 1
+        },
+        QueryScope::Line => {
+            let last_char = match_obj.index_in_str + match_obj.length.max(1) - 1;
+            match_obj.start_line .. text.get_line_of_character(last_char) + 1
+        },
+        QueryScope::Text => 0 .. 1,
+    }
+}
+
+/// Matches of a single term, found exactly if no errors are allowed
+pub(crate) fn find_term<T: TrieStorage>(trie: &T,
+                                        pattern: &str,
+                                        max_errors: usize,
+                                        ignored_characters: &HashMap<char, bool>,
+                                        wildcards: bool) -> Vec<Match> {
+    if max_errors == 0 && ignored_characters.is_empty() && !wildcards {
+        trie.exact_matches(pattern)
+    } else {
+        trie.edit_distance_matches(pattern, max_errors, ignored_characters.clone(), wildcards)
+    }
+}
+
+struct Evaluator<'a, T: TrieStorage> {
+    trie: &'a T,
+    scope: QueryScope,
+    // Matches of each term in each unit
+    term_units: HashMap<&'a str, HashMap<Unit, Vec<Match>>>,
+}
+
+impl<T: TrieStorage> Evaluator<'_, T> {
+    fn all_units(&self) -> BTreeSet<Unit> {
+        (0 .. self.trie.num_texts())
+            .flat_map(|text_index| {
+                let text = self.trie.get_text(text_index);
+                (0 .. num_units(text, self.scope))
+                    .filter(move |unit| !unit_range(text, self.scope, *unit).is_empty())
+                    .map(move |unit| (text_index, unit))
+            })
+            .collect()
+    }
+
+    fn units(&self, query: &BooleanQuery) -> BTreeSet<Unit> {
+        match query {
+            BooleanQuery::Term(pattern) => self.term_units[pattern.as_str()].keys().cloned().collect(),
+            // Avoid listing every unit when a NOT only narrows down others
+            BooleanQuery::And(a, b) => match (a.as_ref(), b.as_ref()) {
+                (query, BooleanQuery::Not(negated)) | (BooleanQuery::Not(negated), query) =>
+                    self.units(query).difference(&self.units(negated)).cloned().collect(),
+                _ => self.units(a).intersection(&self.units(b)).cloned().collect(),
+            },
+            BooleanQuery::Or(a, b) => self.units(a).union(&self.units(b)).cloned().collect(),
+            BooleanQuery::Not(query) => self.all_units().difference(&self.units(query)).cloned().collect(),
+        }
+    }
+}
+
+/// The sentences, lines or texts which satisfy the query, in order of
+/// position
+pub(crate) fn boolean_search<T: TrieStorage>(trie: &T,
+                                             query: &BooleanQuery,
+                                             options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+    if options.scope == QueryScope::Sentence {
+        if let Some(text_index) = (0 .. trie.num_texts()).find(|text_index| trie.get_text(*text_index).sentence_start_indices.is_none()) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Sentence boundaries of {} weren't recorded in the index",
+                                          trie.get_text(text_index).name)));
+        }
+    }
+
+    let mut evaluator = Evaluator { trie, scope: options.scope, term_units: HashMap::new() };
+    for pattern in query.terms() {
+        if evaluator.term_units.contains_key(pattern) {
+            continue;
+        }
+        let mut units: HashMap<Unit, Vec<Match>> = HashMap::new();
+        for match_obj in find_term(trie, pattern, options.max_errors, &options.ignored_characters, options.wildcards) {
+            let text = trie.get_text(match_obj.text_index);
+            for unit in units_of_match(text, options.scope, &match_obj) {
+                units.entry((match_obj.text_index, unit)).or_default().push(match_obj.clone());
+            }
+        }
+        evaluator.term_units.insert(pattern, units);
+    }
+
+    let positive_terms = query.positive_terms();
+    let hits = evaluator.units(query).into_iter().map(|(text_index, unit)| {
+        let range = unit_range(trie.get_text(text_index), options.scope, unit);
+        let span = trie.match_array_from_leaves(vec![Leaf::new(range.start, text_index)], range.len(), 0).remove(0);
+        let mut matches: Vec<Match> = positive_terms.iter()
+            .filter_map(|pattern| evaluator.term_units[pattern].get(&(text_index, unit)))
+            .flatten()
+            .cloned()
+            .collect();
+        matches.sort_by_key(|match_obj| (match_obj.index_in_str, match_obj.length, match_obj.errors));
+        matches.dedup_by_key(|match_obj| (match_obj.index_in_str, match_obj.length));
+        BooleanHit { unit, span, matches }
+    }).collect();
+    Ok(hits)
+}
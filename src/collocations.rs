@@ -0,0 +1,254 @@
+//! Words found near the matches of a search, ranked by how often they occur
+//! there and by how strongly they are associated with the matches.
+//!
+//! Words are separated by whitespace, with any punctuation at the start or
+//! end of a word removed, and are counted in lower case so that "The" and
+//! "the" are the same collocate. A word partly covered by a match isn't
+//! counted.
+//! The windows either side of a match may cross sentence boundaries, but
+//! not the start or end of its text. If a word occurs in the windows O
+//! times, and E times would be expected by chance given how often it
+//! occurs in the whole index, the association measures are
+//!
+//! | measure   | formula              |
+//! |-----------|----------------------|
+//! | `pmi`     | log2(O / E)          |
+//! | `t_score` | (O - E) / sqrt(O)    |
+//!
+//! where E = (number of matches) × (window words per match) × (word count
+//! in the index) / (number of words in the index). PMI favours rare words
+//! found only near the matches, while the t-score favours frequent ones.
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{Match,Text,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SuffixTrie,TrieAnalysis};
+
+    fn words(collocates: &[Collocate]) -> Vec<(&str, usize, usize, usize)> {
+        collocates.iter()
+            .map(|collocate| (collocate.word.as_str(), collocate.count, collocate.left_count, collocate.right_count))
+            .collect()
+    }
+
+    #[test]
+    fn windows_either_side() {
+        let trie = SuffixTrie::new("the (red) fish swam. a red fishing boat, the red fish");
+        let matches = trie.find_exact("red fish");
+        let options = CollocationOptions { left: 1, right: 2, ..CollocationOptions::default() };
+        // "fishing" is partly covered by a match so isn't counted, and
+        // punctuation is removed
+        assert_eq!(words(&trie.collocations(&matches, &options)),
+                   vec![("the", 2, 1, 1), ("a", 1, 1, 0), ("boat", 1, 0, 1)]);
+
+        let options = CollocationOptions { left: 0, right: 3, min_count: 1, ..CollocationOptions::default() };
+        assert_eq!(words(&trie.collocations(&trie.find_exact("fish "), &options)),
+                   vec![("a", 1, 0, 1), ("red", 1, 0, 1), ("swam", 1, 0, 1)]);
+    }
+
+    #[test]
+    fn ignore_case() {
+        let trie = SuffixTrie::new("The fish swam. the fish ate. THE boat");
+        let options = CollocationOptions { left: 1, right: 0, ..CollocationOptions::default() };
+        let collocates = trie.collocations(&trie.find_exact("fish"), &options);
+        assert_eq!(words(&collocates), vec![("the", 2, 2, 0)]);
+        assert_eq!(collocates[0].corpus_count, 3);
+    }
+
+    #[test]
+    fn association_measures() {
+        let trie = SuffixTrie::new("a b c a b d e f g h");
+        let matches = trie.find_exact("b");
+        let options = CollocationOptions { left: 1, right: 0, sort: CollocationSort::Pmi, ..CollocationOptions::default() };
+        let collocates = trie.collocations(&matches, &options);
+        assert_eq!(collocates.len(), 1);
+        let a = &collocates[0];
+        assert_eq!((a.word.as_str(), a.count, a.corpus_count), ("a", 2, 2));
+        // 2 matches with 1 word of context each, and "a" is 2 of 10 words
+        let expected: f64 = 2.0 * 1.0 * 2.0 / 10.0;
+        assert!((a.pmi - (2.0 / expected).log2()).abs() < 1e-9);
+        assert!((a.t_score - (2.0 - expected) / 2.0f64.sqrt()).abs() < 1e-9);
+    }
+}
+
+/// How to order collocates
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum CollocationSort {
+    /// Most often found near the matches first
+    Frequency,
+    /// Highest pointwise mutual information first
+    Pmi,
+    /// Highest t-score first
+    TScore,
+}
+
+/// Size of the windows either side of each match, and which collocates to
+/// keep
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct CollocationOptions {
+    // Number of words before each match
+    pub left: usize,
+    // Number of words after each match
+    pub right: usize,
+    // Words found fewer times than this near the matches are left out
+    pub min_count: usize,
+    pub sort: CollocationSort,
+    // Keep only this many collocates
+    pub top_k: Option<usize>,
+}
+
+impl Default for CollocationOptions {
+    fn default() -> Self {
+        CollocationOptions {
+            left: 5,
+            right: 5,
+            min_count: 1,
+            sort: CollocationSort::Frequency,
+            top_k: None,
+        }
+    }
+}
+
+/// A word found near the matches
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub struct Collocate {
+    pub word: String,
+    // Occurrences in the windows either side of the matches
+    pub count: usize,
+    pub left_count: usize,
+    pub right_count: usize,
+    // Occurrences in the whole index
+    pub corpus_count: usize,
+    pub pmi: f64,
+    pub t_score: f64,
+}
+
+/// The word in lower case with any punctuation at either end removed, or
+/// None if nothing is left
+fn collocate_word(word: &str) -> Option<String> {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+    if word.is_empty() { None } else { Some(word.to_lowercase()) }
+}
+
+/// Up to num_words words either side of the characters from start to end
+/// of the text, nearest first
+fn words_either_side<T: TrieStorage>(trie: &T,
+                                     text: &Text,
+                                     start: usize,
+                                     end: usize,
+                                     num_words: (usize, usize)) -> (Vec<String>, Vec<String>) {
+    let char_at = |index: usize| trie.get_char(text.offset + index);
+    let is_space = |index: usize| char_at(index).is_whitespace();
+
+    let mut left = vec![];
+    let mut index = start;
+    // Skip any part of a word the match starts in the middle of
+    if index > 0 && index < text.last_index && !is_space(index) {
+        while index > 0 && !is_space(index - 1) {
+            index -= 1;
+        }
+    }
+    while left.len() < num_words.0 && index > 0 {
+        while index > 0 && is_space(index - 1) {
+            index -= 1;
+        }
+        let word_end = index;
+        while index > 0 && !is_space(index - 1) {
+            index -= 1;
+        }
+        let word: String = (index .. word_end).map(char_at).collect();
+        left.extend(collocate_word(&word));
+    }
+
+    let mut right = vec![];
+    let mut index = end;
+    if index > start && index < text.last_index && !is_space(index - 1) {
+        while index < text.last_index && !is_space(index) {
+            index += 1;
+        }
+    }
+    while right.len() < num_words.1 && index < text.last_index {
+        while index < text.last_index && is_space(index) {
+            index += 1;
+        }
+        let word_start = index;
+        while index < text.last_index && !is_space(index) {
+            index += 1;
+        }
+        let word: String = (word_start .. index).map(char_at).collect();
+        right.extend(collocate_word(&word));
+    }
+    (left, right)
+}
+
+/// Number of times each word occurs in the whole index, and the total
+/// number of words
+fn corpus_word_counts<T: TrieStorage>(trie: &T) -> (HashMap<String, usize>, usize) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut total = 0;
+    for text_index in 0 .. trie.num_texts() {
+        let text = trie.get_text(text_index);
+        let contents = trie.owned_from_index(text, 0, text.last_index);
+        for word in contents.split_whitespace().filter_map(collocate_word) {
+            *counts.entry(word).or_insert(0) += 1;
+            total += 1;
+        }
+    }
+    (counts, total)
+}
+
+/// Words found within the windows either side of the matches, with their
+/// association with the matches
+pub(crate) fn collocations<T: TrieStorage>(trie: &T, matches: &[Match], options: &CollocationOptions) -> Vec<Collocate> {
+    let mut window_counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for match_obj in matches.iter() {
+        let text = trie.get_text(match_obj.text_index);
+        let (left, right) = words_either_side(trie, text, match_obj.index_in_str,
+                                              match_obj.index_in_str + match_obj.length,
+                                              (options.left, options.right));
+        for word in left {
+            window_counts.entry(word).or_insert((0, 0)).0 += 1;
+        }
+        for word in right {
+            window_counts.entry(word).or_insert((0, 0)).1 += 1;
+        }
+    }
+
+    let (corpus_counts, total_words) = corpus_word_counts(trie);
+    let window_words = (matches.len() * (options.left + options.right)) as f64;
+    let mut collocates: Vec<Collocate> = window_counts.into_iter()
+        .filter(|(_, (left_count, right_count))| left_count + right_count >= options.min_count)
+        .map(|(word, (left_count, right_count))| {
+            let count = left_count + right_count;
+            let corpus_count = corpus_counts.get(&word).cloned().unwrap_or(count);
+            let expected = window_words * corpus_count as f64 / total_words.max(1) as f64;
+            Collocate {
+                word,
+                count,
+                left_count,
+                right_count,
+                corpus_count,
+                pmi: (count as f64 / expected).log2(),
+                t_score: (count as f64 - expected) / (count as f64).sqrt(),
+            }
+        })
+        .collect();
+
+    collocates.sort_by(|a, b| {
+        let by_measure = match options.sort {
+            CollocationSort::Frequency => b.count.cmp(&a.count),
+            CollocationSort::Pmi => b.pmi.total_cmp(&a.pmi),
+            CollocationSort::TScore => b.t_score.total_cmp(&a.t_score),
+        };
+        by_measure.then_with(|| a.word.cmp(&b.word))
+    });
+    if let Some(top_k) = options.top_k {
+        collocates.truncate(top_k);
+    }
+    collocates
+}
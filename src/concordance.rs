@@ -0,0 +1,159 @@
+//! Keyword-in-context (KWIC) concordances, which show each match on its own
+//! line with a fixed amount of context either side so that the matches
+//! line up in a column.
+use std::cmp::Ordering;
+
+use crate::{Match,SearchIndex};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(left: &str, keyword: &str, right: &str) -> ConcordanceLine {
+        ConcordanceLine {
+            match_obj: Match {
+                text_index: 0,
+                index_in_str: 0,
+                start_line: 0,
+                end_line: 0,
+                start_column: 0,
+                end_column: 0,
+                length: keyword.len(),
+                errors: 0,
+                sentence: None,
+                start_byte: None,
+                end_byte: None,
+            },
+            left: left.to_string(),
+            keyword: keyword.to_string(),
+            right: right.to_string(),
+        }
+    }
+
+    #[test]
+    fn format_pads_short_context() {
+        assert_eq!(line("the ", "fish", " swam").format(6), "  the   fish   swam");
+        assert_eq!(line("", "fish", "").format(3), "     fish  ");
+    }
+
+    #[test]
+    fn context_grows_on_each_side() {
+        let trie = crate::SuffixTrie::new("a\nb\nfish\nc\nd\ne\nf\ng\nh");
+        let matches = trie.find_exact("fish");
+        let lines = concordance(&trie, &matches, 9);
+        // Only the right side is long enough to fill the width
+        assert_eq!((lines[0].left.as_str(), lines[0].right.as_str()), ("a b ", " c d e f "));
+    }
+
+    #[test]
+    fn sort_by_context() {
+        let mut lines = vec![line("a red ", "fish", " ate"), line("a blue ", "fish", " swam"),
+                             line("The Red ", "fish", " Swam")];
+        sort_concordance(&mut lines, ConcordanceSort::Left);
+        let lefts: Vec<&str> = lines.iter().map(|line| line.left.as_str()).collect();
+        assert_eq!(lefts, vec!["a blue ", "a red ", "The Red "]);
+
+        sort_concordance(&mut lines, ConcordanceSort::Right);
+        let rights: Vec<&str> = lines.iter().map(|line| line.right.as_str()).collect();
+        assert_eq!(rights, vec![" ate", " swam", " Swam"]);
+    }
+}
+
+/// How to order the lines of a concordance
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ConcordanceSort {
+    /// In the order of the matches
+    Position,
+    /// Alphabetically by the words to the left, starting with the word
+    /// nearest the match
+    Left,
+    /// Alphabetically by the words to the right
+    Right,
+}
+
+/// A match with a fixed amount of context either side. Line breaks and
+/// tabs are replaced by spaces so that the line can be printed on one line.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ConcordanceLine {
+    pub match_obj: Match,
+    pub left: String,
+    pub keyword: String,
+    pub right: String,
+}
+
+impl ConcordanceLine {
+    /// Lay out the line so that the keyword starts at the same column for
+    /// every line with context of the given width
+    pub fn format(&self, width: usize) -> String {
+        format!("{:>width$}  {}  {}", self.left, self.keyword, self.right, width = width)
+    }
+
+    fn left_sort_key(&self) -> Vec<String> {
+        self.left.to_lowercase().split_whitespace().rev().map(String::from).collect()
+    }
+
+    fn right_sort_key(&self) -> Vec<String> {
+        self.right.to_lowercase().split_whitespace().map(String::from).collect()
+    }
+}
+
+/// Build a concordance of the matches, with width characters of context
+/// either side of each. Matches near the start or end of their text have
+/// less context.
+pub fn concordance(index: &dyn SearchIndex, matches: &[Match], width: usize) -> Vec<ConcordanceLine> {
+    matches.iter().map(|match_obj| {
+        let (before, keyword, after) = context_of_width(index, match_obj, width);
+        let left_start = before.chars().count().saturating_sub(width);
+        ConcordanceLine {
+            match_obj: match_obj.clone(),
+            left: single_line(&before.chars().skip(left_start).collect::<String>()),
+            keyword: single_line(&keyword),
+            right: single_line(&after.chars().take(width).collect::<String>()),
+        }
+    }).collect()
+}
+
+/// Sort the lines of a concordance. The sort is stable, so lines with the
+/// same context stay in the order of their matches.
+pub fn sort_concordance(lines: &mut [ConcordanceLine], sort: ConcordanceSort) {
+    match sort {
+        ConcordanceSort::Position => lines.sort_by(|a, b| compare_positions(&a.match_obj, &b.match_obj)),
+        ConcordanceSort::Left => lines.sort_by_cached_key(|line| line.left_sort_key()),
+        ConcordanceSort::Right => lines.sort_by_cached_key(|line| line.right_sort_key()),
+    }
+}
+
+fn compare_positions(a: &Match, b: &Match) -> Ordering {
+    (a.text_index, a.index_in_str).cmp(&(b.text_index, b.index_in_str))
+}
+
+/// Get the strings of the match with at least width characters of context
+/// either side, unless the text starts or ends first.
+fn context_of_width(index: &dyn SearchIndex, match_obj: &Match, width: usize) -> (String, String, String) {
+    let (before, keyword, after) = index.get_strings_of_match(match_obj, 1);
+    let before = grow_context(before, width, |lines| index.get_strings_of_match(match_obj, lines).0);
+    let after = grow_context(after, width, |lines| index.get_strings_of_match(match_obj, lines).2);
+    (before, keyword, after)
+}
+
+/// Fetch the context on one side of a match a number of lines at a time,
+/// doubling the number of lines until there are width characters or the
+/// text has run out.
+fn grow_context<F>(mut context: String, width: usize, fetch: F) -> String
+    where F: Fn(usize) -> String {
+    let mut context_lines = 1;
+    while context.chars().count() < width {
+        context_lines *= 2;
+        let more = fetch(context_lines);
+        if more.len() == context.len() {
+            // Reached the start or end of the text
+            break;
+        }
+        context = more;
+    }
+    context
+}
+
+fn single_line(string: &str) -> String {
+    string.chars().map(|c| if c == '\n' || c == '\r' || c == '\t' { ' ' } else { c }).collect()
+}
@@ -0,0 +1,207 @@
+//! Substrings which are distinctive of one group of texts compared with
+//! another, such as the works of one author compared with another's.
+//!
+//! Each node of the trie is scored from the number of leaves below it from
+//! texts of each group, using Dunning's log-likelihood ratio to compare the
+//! rates at which its string occurs in the two groups. The rates are
+//! relative to the number of characters in each group. Texts in neither
+//! group are left out, as are strings made only of whitespace, which
+//! reflect the layout of the texts rather than their language.
+use std::io;
+use std::io::{Error,ErrorKind};
+
+use serde::Serialize;
+
+use crate::reuse::text_index_of;
+use crate::stats::per_10k_chars;
+use crate::{Leaf,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SuffixTrie,TrieAnalysis};
+
+    #[test]
+    fn log_likelihood_scores() {
+        assert_eq!(log_likelihood(0, 100, 0, 100), 0.0);
+        // Equal rates are not distinctive, however often they occur
+        assert!(log_likelihood(10, 1000, 20, 2000).abs() < 1e-9);
+        // Worked example: expected counts are 15 in each group
+        let expected = 2.0 * (20.0 * (20.0f64 / 15.0).ln() + 10.0 * (10.0f64 / 15.0).ln());
+        assert!((log_likelihood(20, 1000, 10, 1000) - expected).abs() < 1e-9);
+        assert_eq!(log_likelihood(20, 1000, 10, 1000), log_likelihood(10, 1000, 20, 1000));
+    }
+
+    #[test]
+    fn distinctive_substrings() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "thee and thou, thee and thou");
+        trie.add_sentences_from_text("second", "you and you and you");
+        trie.add_sentences_from_text("third", "thee");
+        let options = DistinctiveOptions { min_length: 3, max_length: 4, min_count: 2, top_k: None };
+        let phrases = trie.find_distinctive_phrases(&["first"], &["second"], &options).unwrap();
+
+        let strings: Vec<(&str, Subcorpus, usize, usize)> = phrases.iter().take(3)
+            .map(|phrase| (phrase.string.as_str(), phrase.favours, phrase.count_a, phrase.count_b))
+            .collect();
+        assert_eq!(strings, vec![("you", Subcorpus::B, 0, 3), (" you", Subcorpus::B, 0, 2), ("d yo", Subcorpus::B, 0, 2)]);
+        let thou = phrases.iter().find(|phrase| phrase.string == "thou").unwrap();
+        assert_eq!((thou.favours, thou.count_a, thou.count_b), (Subcorpus::A, 2, 0));
+        // " and" occurs twice in each text, and "thee" only twice in the
+        // first group since the third text is in neither
+        let and = phrases.iter().find(|phrase| phrase.string == " and").unwrap();
+        assert_eq!((and.count_a, and.count_b), (2, 2));
+        assert!(phrases.iter().all(|phrase| phrase.string != "thee" || phrase.count_a == 2));
+        assert!(phrases.windows(2).all(|pair| pair[0].log_likelihood >= pair[1].log_likelihood));
+
+        assert!(trie.find_distinctive_phrases(&["first"], &["first"], &options).is_err());
+        assert!(trie.find_distinctive_phrases(&["first"], &["missing"], &options).is_err());
+    }
+}
+
+/// Which of the two groups of texts being compared
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum Subcorpus {
+    A,
+    B,
+}
+
+/// Which substrings to score, and which to keep
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct DistinctiveOptions {
+    pub min_length: usize,
+    pub max_length: usize,
+    // Substrings occurring fewer times than this in the two groups
+    // together are left out
+    pub min_count: usize,
+    // Keep only this many of the most distinctive substrings
+    pub top_k: Option<usize>,
+}
+
+impl Default for DistinctiveOptions {
+    fn default() -> Self {
+        DistinctiveOptions {
+            min_length: 3,
+            max_length: 30,
+            min_count: 5,
+            top_k: None,
+        }
+    }
+}
+
+/// A substring occurring at a higher rate in one group of texts than in
+/// the other
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub struct DistinctivePhrase {
+    pub string: String,
+    // The group with the higher rate
+    pub favours: Subcorpus,
+    pub count_a: usize,
+    pub count_b: usize,
+    pub per_10k_chars_a: f64,
+    pub per_10k_chars_b: f64,
+    pub log_likelihood: f64,
+}
+
+/// Dunning's log-likelihood ratio comparing count_a occurrences in size_a
+/// characters with count_b occurrences in size_b characters. The higher
+/// the score, the less likely the difference in rates is to be chance.
+pub(crate) fn log_likelihood(count_a: usize, size_a: usize, count_b: usize, size_b: usize) -> f64 {
+    let total_count = (count_a + count_b) as f64;
+    let total_size = (size_a + size_b) as f64;
+    if total_count == 0.0 || total_size == 0.0 {
+        return 0.0;
+    }
+    let term = |count: usize, size: usize| {
+        let expected = size as f64 * total_count / total_size;
+        if count == 0 { 0.0 } else { count as f64 * (count as f64 / expected).ln() }
+    };
+    2.0 * (term(count_a, size_a) + term(count_b, size_b))
+}
+
+/// Leaves below a node from texts of each group
+#[derive(Clone,Copy,Debug,Default)]
+struct GroupCounts {
+    a: usize,
+    b: usize,
+    // Any leaf below the node, for reading its string
+    leaf: Option<Leaf>,
+}
+
+fn group_indices<T: TrieStorage>(trie: &T, names: &[&str]) -> Result<Vec<usize>, io::Error> {
+    names.iter().map(|name| text_index_of(trie, name)).collect()
+}
+
+/// Substrings which occur at a significantly different rate in the texts
+/// of group_a than in those of group_b, most distinctive first
+pub(crate) fn distinctive_phrases<T: TrieStorage>(trie: &T,
+                                                  group_a: &[&str],
+                                                  group_b: &[&str],
+                                                  options: &DistinctiveOptions) -> Result<Vec<DistinctivePhrase>, io::Error> {
+    let mut groups: Vec<Option<Subcorpus>> = vec![None; trie.num_texts()];
+    let mut sizes = [0, 0];
+    for (group, names) in [(Subcorpus::A, group_a), (Subcorpus::B, group_b)] {
+        for text_index in group_indices(trie, names)? {
+            if groups[text_index].is_some_and(|other| other != group) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("{} is in both groups", trie.get_text(text_index).name)));
+            }
+            if groups[text_index].is_none() {
+                sizes[group as usize] += trie.get_text(text_index).last_index;
+            }
+            groups[text_index] = Some(group);
+        }
+    }
+    let [size_a, size_b] = sizes;
+
+    let mut phrases = vec![];
+    trie.fold_post_order(|node_index, depth, child_counts: Vec<GroupCounts>| {
+        let mut counts = GroupCounts::default();
+        let mut own_leaves = vec![];
+        trie.extend_with_leaves(node_index, &mut own_leaves);
+        for leaf in own_leaves {
+            match groups[leaf.text_index] {
+                Some(Subcorpus::A) => counts.a += 1,
+                Some(Subcorpus::B) => counts.b += 1,
+                None => continue,
+            }
+            counts.leaf = Some(leaf);
+        }
+        for child in child_counts {
+            counts.a += child.a;
+            counts.b += child.b;
+            counts.leaf = counts.leaf.or(child.leaf);
+        }
+
+        // Every string ending on the edge into this node has the same
+        // counts, so only the longest one allowed is scored
+        let (_, edge_length) = trie.get_edge(node_index);
+        let length = depth.min(options.max_length);
+        if node_index != 0 && length >= options.min_length && length > depth - edge_length
+            && counts.a + counts.b >= options.min_count.max(1) {
+            let rate_a = per_10k_chars(counts.a, size_a);
+            let rate_b = per_10k_chars(counts.b, size_b);
+            let leaf = counts.leaf.unwrap();
+            let string = trie.owned_from_index(trie.get_text(leaf.text_index), leaf.index_in_str, length);
+            if !string.trim().is_empty() {
+                phrases.push(DistinctivePhrase {
+                    string,
+                    favours: if rate_a >= rate_b { Subcorpus::A } else { Subcorpus::B },
+                    count_a: counts.a,
+                    count_b: counts.b,
+                    per_10k_chars_a: rate_a,
+                    per_10k_chars_b: rate_b,
+                    log_likelihood: log_likelihood(counts.a, size_a, counts.b, size_b),
+                });
+            }
+        }
+        counts
+    });
+
+    phrases.sort_by(|a, b| b.log_likelihood.total_cmp(&a.log_likelihood).then_with(|| a.string.cmp(&b.string)));
+    if let Some(top_k) = options.top_k {
+        phrases.truncate(top_k);
+    }
+    Ok(phrases)
+}
@@ -0,0 +1,124 @@
+//! Export of matches for use by other tools, as JSON Lines or CSV.
+//!
+//! Each match is written as a record with the fields
+//!
+//! | field          | meaning                                                    |
+//! |----------------|------------------------------------------------------------|
+//! | `text_name`    | name of the text containing the match                      |
+//! | `start_line`   | line the match starts on, counting from 0                  |
+//! | `end_line`     | line the match ends on, counting from 0                    |
+//! | `start_column` | character the match starts at within its line, from 0      |
+//! | `end_column`   | character just after the match within its end line         |
+//! | `offset`       | character the match starts at within its text, from 0      |
+//! | `start_byte`   | byte offset of the start of the match in the original file |
+//! | `end_byte`     | byte offset just after the match in the original file      |
+//! | `length`       | number of characters in the match                          |
+//! | `errors`       | number of errors in the match                              |
+//! | `matched`      | the matched part of the text                               |
+//! | `before`       | text before the match, from the start of the context       |
+//! | `after`        | text after the match, to the end of the context            |
+//!
+//! Characters are counted in the normalised text stored in the index. The
+//! newline ending the context after the match is left out. The byte offsets
+//! are left empty (null in JSON) for indexes which don't record them.
+//! JSON Lines output has one JSON object per line. CSV output has a header
+//! row naming the fields, followed by one row per match.
+use std::io;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{Match,SearchIndex};
+
+/// Description of a match which can be understood without the index
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct MatchRecord {
+    pub text_name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub start_column: usize,
+    pub end_column: usize,
+    pub offset: usize,
+    pub start_byte: Option<usize>,
+    pub end_byte: Option<usize>,
+    pub length: usize,
+    pub errors: usize,
+    pub matched: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl MatchRecord {
+    /// Describe a match found in the given index, including the given
+    /// number of lines of context either side of the match
+    pub fn new(index: &dyn SearchIndex, match_obj: &Match, context_lines: usize) -> Self {
+        let text_name = index.get_text_info().swap_remove(match_obj.text_index).name;
+        MatchRecord::with_text_name(text_name, index, match_obj, context_lines)
+    }
+
+    fn with_text_name(text_name: String,
+                      index: &dyn SearchIndex,
+                      match_obj: &Match,
+                      context_lines: usize) -> Self {
+        let (before, matched, mut after) = index.get_strings_of_match(match_obj, context_lines);
+        if after.ends_with('\n') {
+            after.pop();
+        }
+        MatchRecord {
+            text_name,
+            start_line: match_obj.start_line,
+            end_line: match_obj.end_line,
+            start_column: match_obj.start_column,
+            end_column: match_obj.end_column,
+            offset: match_obj.index_in_str,
+            start_byte: match_obj.start_byte,
+            end_byte: match_obj.end_byte,
+            length: match_obj.length,
+            errors: match_obj.errors,
+            matched,
+            before,
+            after,
+        }
+    }
+}
+
+/// Write each match as a JSON object on its own line
+pub fn write_json_lines<W: Write>(writer: &mut W,
+                                  index: &dyn SearchIndex,
+                                  matches: &[Match],
+                                  context_lines: usize) -> Result<(), io::Error> {
+    for record in records(index, matches, context_lines) {
+        serde_json::to_writer(&mut *writer, &record)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Write the matches as CSV, with a header row
+pub fn write_csv<W: Write>(writer: &mut W,
+                           index: &dyn SearchIndex,
+                           matches: &[Match],
+                           context_lines: usize) -> Result<(), io::Error> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    if matches.is_empty() {
+        // Serializing records writes the header, so write it explicitly
+        // when there are no records
+        csv_writer.write_record(["text_name", "start_line", "end_line", "start_column", "end_column",
+                                 "offset", "start_byte", "end_byte", "length", "errors",
+                                 "matched", "before", "after"])?;
+    }
+    for record in records(index, matches, context_lines) {
+        csv_writer.serialize(record)?;
+    }
+    csv_writer.flush()
+}
+
+fn records<'a>(index: &'a dyn SearchIndex,
+               matches: &'a [Match],
+               context_lines: usize) -> impl Iterator<Item = MatchRecord> + 'a {
+    let text_names = index.get_text_names();
+    matches.iter().map(move |match_obj| {
+        MatchRecord::with_text_name(text_names[match_obj.text_index].to_string(),
+                                    index, match_obj, context_lines)
+    })
+}
@@ -0,0 +1,546 @@
+//! Versioned on-disk format for suffix tries.
+//!
+//! A saved index is laid out as
+//!
+//! | magic number | format version | header | payload |
+//!
+//! The magic number and version have a fixed layout so that any version of
+//! the library can recognise the file. The header records the build options,
+//! where the contents of the texts are stored and a checksum of the payload,
+//! which is the bincode encoding of the SuffixTrie itself.
+//!
+//! The contents of the texts can be kept outside the index (see TextStore),
+//! in which case the payload is saved without them and they are read back
+//! in from the store when they are first needed, rather than when the index
+//! is loaded. Loading the index still checks that the files of the store
+//! haven't changed. A blob file with a relative path is kept next to the
+//! index, and the paths of source files are recorded relative to the index
+//! so that it can be used from any directory.
+//!
+//! Files written before the format was versioned (e.g. by older versions
+//! of the save_to_file example) are just the bincode encoding of the
+//! SuffixTrie, and are treated as version 0. Indexes saved before version 3
+//! don't record the byte offsets of characters in the original texts,
+//! those saved before version 4 don't record where sentences start, and
+//! those saved before version 5 find their source files from the names of
+//! the texts, relative to the current directory.
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader,BufWriter,Error,ErrorKind,Read,Write};
+use std::path::{Component,Path,PathBuf};
+
+use log::{info,warn};
+use serde::{Serialize,Deserialize};
+
+use crate::{BuildOptions,ByteOffsets,SubTrie,SuffixTrie,Text};
+use crate::storage::{CharStorage,ExternalChars};
+
+const MAGIC: &[u8; 8] = b"SPYGLASS";
+
+/// Version of the on-disk format written by this version of the library
+pub const FORMAT_VERSION: u32 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_legacy_version_0() {
+        utilities::init_testing();
+        let trie = SuffixTrie::new("abcabdabe");
+        // Version 0 was the trie without its build options
+        let legacy = (&trie.str_storage, &trie.node_storage, legacy_texts(&trie));
+        let encoded = bincode::serialize(&legacy).unwrap();
+
+        let decoded = SuffixTrie::load_from_reader(&encoded[..]).unwrap();
+        assert_eq!(decoded.get_options(), &BuildOptions::default());
+        assert_eq!(decoded.len(), trie.len());
+        assert_eq!(decoded.find_exact("ab"), trie.find_exact("ab"));
+    }
+
+    #[test]
+    fn header_round_trip() {
+        utilities::init_testing();
+        let trie = SuffixTrie::new("abcabdabe");
+        let mut encoded: Vec<u8> = vec![];
+        trie.save_to_writer(&mut encoded).unwrap();
+
+        let header = IndexHeader::read_from(&mut &encoded[..]).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(&header.options, trie.get_options());
+        assert_eq!(header.text_store, TextStore::Embedded);
+    }
+
+    #[test]
+    fn load_version_1() {
+        utilities::init_testing();
+        let trie = SuffixTrie::new("abcabdabe");
+        let payload = bincode::serialize(&(&trie.str_storage, &trie.node_storage,
+                                           legacy_texts(&trie), &trie.options)).unwrap();
+        let header = HeaderV1 {
+            options: trie.options.clone(),
+            payload_length: payload.len() as u64,
+            checksum: crc32fast::hash(&payload),
+        };
+        let mut encoded: Vec<u8> = MAGIC.to_vec();
+        encoded.extend(&1u32.to_le_bytes());
+        encoded.extend(bincode::serialize(&header).unwrap());
+        encoded.extend(payload);
+
+        let decoded = SuffixTrie::load_from_reader(&encoded[..]).unwrap();
+        assert_eq!(decoded.len(), trie.len());
+        assert_eq!(decoded.find_exact("ab"), trie.find_exact("ab"));
+        // Older versions didn't record byte offsets or sentences
+        assert_eq!(decoded.find_exact("ab")[0].start_byte, None);
+        assert_eq!(decoded.find_exact("ab")[0].sentence, None);
+        assert_eq!(trie.find_exact("ab")[0].start_byte, Some(0));
+        assert_eq!(trie.find_exact("ab")[0].sentence, Some(0));
+    }
+
+    #[test]
+    fn paths_relative_to_index() {
+        assert_eq!(relative_path(Path::new("resources/tests"), Path::new("resources/tests/simple/small.txt")).unwrap(),
+                   Path::new("simple/small.txt"));
+        assert_eq!(relative_path(Path::new("src"), Path::new("resources/tests/simple/small.txt")).unwrap(),
+                   Path::new("../resources/tests/simple/small.txt"));
+        assert_eq!(relative_path(Path::new(""), Path::new("./resources/tests/simple/small.txt")).unwrap(),
+                   Path::new("resources/tests/simple/small.txt"));
+    }
+
+    fn legacy_texts(trie: &SuffixTrie) -> Vec<LegacyTextV2> {
+        trie.texts.iter().map(|text| LegacyTextV2 {
+            name: text.name.to_string(),
+            line_start_indices: text.line_start_indices.clone(),
+            last_index: text.last_index,
+            offset: text.offset,
+        }).collect()
+    }
+}
+
+/// Where the contents of the texts in a saved index are kept
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum TextStore {
+    /// Inside the index file
+    Embedded,
+    /// In the files the texts were added from. The name of each text must
+    /// be the path of the file it was read from when the index is saved.
+    SourceFiles,
+    /// In a separate file at the given path, which is relative to the
+    /// directory of the index
+    BlobFile(String),
+}
+
+/// Information stored at the start of a saved index, which can be read
+/// without loading the whole trie
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct IndexHeader {
+    pub version: u32,
+    pub options: BuildOptions,
+    pub text_store: TextStore,
+    // Checksums of the contents of the texts if they are stored outside
+    // the index: one per source file, or one for the blob file
+    pub text_checksums: Vec<u32>,
+    // Paths of the source files the texts are stored in, relative to the
+    // directory of the index. Empty for indexes saved before version 5.
+    pub source_paths: Vec<String>,
+    pub payload_length: u64,
+    pub checksum: u32,
+}
+
+#[derive(Serialize,Deserialize)]
+struct HeaderV1 {
+    options: BuildOptions,
+    payload_length: u64,
+    checksum: u32,
+}
+
+#[derive(Serialize,Deserialize)]
+struct HeaderV2 {
+    options: BuildOptions,
+    text_store: TextStore,
+    text_checksums: Vec<u32>,
+    payload_length: u64,
+    checksum: u32,
+}
+
+#[derive(Serialize,Deserialize)]
+struct HeaderV5 {
+    options: BuildOptions,
+    text_store: TextStore,
+    text_checksums: Vec<u32>,
+    source_paths: Vec<String>,
+    payload_length: u64,
+    checksum: u32,
+}
+
+/// Borrowed version of SuffixTrie, with the same encoding, so that it can
+/// be saved without the contents of its texts
+#[derive(Serialize)]
+struct SuffixTrieRef<'a> {
+    str_storage: &'a CharStorage,
+    node_storage: &'a [SubTrie],
+    texts: &'a [Text],
+    options: &'a BuildOptions,
+}
+
+/// Layout of Text before byte offsets were recorded, in versions 0 to 2
+#[derive(Serialize,Deserialize)]
+pub(crate) struct LegacyTextV2 {
+    name: String,
+    line_start_indices: Vec<usize>,
+    last_index: usize,
+    offset: usize,
+}
+
+impl From<LegacyTextV2> for Text {
+    fn from(legacy: LegacyTextV2) -> Self {
+        Text {
+            name: legacy.name,
+            line_start_indices: legacy.line_start_indices,
+            last_index: legacy.last_index,
+            offset: legacy.offset,
+            byte_offsets: None,
+            sentence_start_indices: None,
+        }
+    }
+}
+
+/// Layout of Text before sentence starts were recorded, in version 3
+#[derive(Serialize,Deserialize)]
+pub(crate) struct LegacyTextV3 {
+    name: String,
+    line_start_indices: Vec<usize>,
+    last_index: usize,
+    offset: usize,
+    byte_offsets: Option<ByteOffsets>,
+}
+
+impl From<LegacyTextV3> for Text {
+    fn from(legacy: LegacyTextV3) -> Self {
+        Text {
+            name: legacy.name,
+            line_start_indices: legacy.line_start_indices,
+            last_index: legacy.last_index,
+            offset: legacy.offset,
+            byte_offsets: legacy.byte_offsets,
+            sentence_start_indices: None,
+        }
+    }
+}
+
+/// Layout of SuffixTrie before build options were recorded
+#[derive(Serialize,Deserialize)]
+struct LegacySuffixTrieV0 {
+    str_storage: Vec<char>,
+    node_storage: Vec<SubTrie>,
+    texts: Vec<LegacyTextV2>,
+}
+
+impl From<LegacySuffixTrieV0> for SuffixTrie {
+    fn from(legacy: LegacySuffixTrieV0) -> Self {
+        SuffixTrie {
+            str_storage: CharStorage::InMemory(legacy.str_storage),
+            node_storage: legacy.node_storage,
+            texts: legacy.texts.into_iter().map(Text::from).collect(),
+            options: BuildOptions::default(),
+        }
+    }
+}
+
+/// Layout of SuffixTrie in versions 1 to 3, which only differ in the
+/// layout of their texts
+#[derive(Serialize,Deserialize)]
+struct LegacySuffixTrie<T> {
+    str_storage: Vec<char>,
+    node_storage: Vec<SubTrie>,
+    texts: Vec<T>,
+    options: BuildOptions,
+}
+
+impl<T: Into<Text>> From<LegacySuffixTrie<T>> for SuffixTrie {
+    fn from(legacy: LegacySuffixTrie<T>) -> Self {
+        SuffixTrie {
+            str_storage: CharStorage::InMemory(legacy.str_storage),
+            node_storage: legacy.node_storage,
+            texts: legacy.texts.into_iter().map(Into::into).collect(),
+            options: legacy.options,
+        }
+    }
+}
+
+pub(crate) fn invalid_data<E>(error: E) -> Error
+    where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+impl IndexHeader {
+    /// Read the header from the start of a saved index
+    pub fn read(path: &str) -> Result<IndexHeader, io::Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        IndexHeader::read_from(&mut reader)
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> Result<IndexHeader, io::Error> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a spyglass index: missing magic number"));
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        match version {
+            1 => {
+                let header: HeaderV1 = bincode::deserialize_from(reader).map_err(invalid_data)?;
+                Ok(IndexHeader {
+                    version,
+                    options: header.options,
+                    text_store: TextStore::Embedded,
+                    text_checksums: vec![],
+                    source_paths: vec![],
+                    payload_length: header.payload_length,
+                    checksum: header.checksum,
+                })
+            },
+            2 ..= 4 => {
+                let header: HeaderV2 = bincode::deserialize_from(reader).map_err(invalid_data)?;
+                Ok(IndexHeader {
+                    version,
+                    options: header.options,
+                    text_store: header.text_store,
+                    text_checksums: header.text_checksums,
+                    source_paths: vec![],
+                    payload_length: header.payload_length,
+                    checksum: header.checksum,
+                })
+            },
+            5 => {
+                let header: HeaderV5 = bincode::deserialize_from(reader).map_err(invalid_data)?;
+                Ok(IndexHeader {
+                    version,
+                    options: header.options,
+                    text_store: header.text_store,
+                    text_checksums: header.text_checksums,
+                    source_paths: header.source_paths,
+                    payload_length: header.payload_length,
+                    checksum: header.checksum,
+                })
+            },
+            _ => Err(invalid_data(format!("Unsupported index format version {} (newest supported is {})",
+                                          version, FORMAT_VERSION))),
+        }
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let header = HeaderV5 {
+            options: self.options.clone(),
+            text_store: self.text_store.clone(),
+            text_checksums: self.text_checksums.clone(),
+            source_paths: self.source_paths.clone(),
+            payload_length: self.payload_length,
+            checksum: self.checksum,
+        };
+        bincode::serialize_into(writer, &header).map_err(invalid_data)
+    }
+}
+
+impl SuffixTrie {
+    /// Save the suffix trie to the given path in the current format
+    pub fn save(&self, path: &str) -> Result<(), io::Error> {
+        self.save_with_text_store(path, &TextStore::Embedded)
+    }
+
+    /// Save the suffix trie to the given path, keeping the contents of its
+    /// texts in the given store
+    pub fn save_with_text_store(&self, path: &str, text_store: &TextStore) -> Result<(), io::Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_index(&mut writer, text_store, index_dir(path))?;
+        writer.flush()
+    }
+
+    pub fn save_to_writer<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        self.write_index(writer, &TextStore::Embedded, Path::new(""))
+    }
+
+    fn write_index<W: Write>(&self, writer: &mut W, text_store: &TextStore, index_dir: &Path) -> Result<(), io::Error> {
+        let (text_checksums, source_paths) = match text_store {
+            TextStore::Embedded => (vec![], vec![]),
+            TextStore::SourceFiles => self.checksum_source_files(index_dir)?,
+            TextStore::BlobFile(blob_path) => {
+                let contents: String = self.str_storage.iter().collect();
+                fs::write(index_dir.join(blob_path), &contents)?;
+                (vec![crc32fast::hash(contents.as_bytes())], vec![])
+            },
+        };
+        let no_texts = CharStorage::default();
+        let str_storage = match text_store {
+            TextStore::Embedded => &self.str_storage,
+            _ => &no_texts,
+        };
+        let payload = bincode::serialize(&SuffixTrieRef {
+            str_storage,
+            node_storage: &self.node_storage,
+            texts: &self.texts,
+            options: &self.options,
+        }).map_err(invalid_data)?;
+
+        let header = IndexHeader {
+            version: FORMAT_VERSION,
+            options: self.options.clone(),
+            text_store: text_store.clone(),
+            text_checksums,
+            source_paths,
+            payload_length: payload.len() as u64,
+            checksum: crc32fast::hash(&payload),
+        };
+        header.write_to(writer)?;
+        writer.write_all(&payload)
+    }
+
+    /// Load a suffix trie saved by save, or by older versions of the library
+    pub fn load(path: &str) -> Result<SuffixTrie, io::Error> {
+        let reader = BufReader::new(File::open(path)?);
+        info!("Loading index from {}", path);
+        SuffixTrie::load_in_dir(reader, index_dir(path))
+    }
+
+    /// Load a suffix trie from a reader. A blob file it keeps its texts in
+    /// is found relative to the current directory.
+    pub fn load_from_reader<R: Read>(reader: R) -> Result<SuffixTrie, io::Error> {
+        SuffixTrie::load_in_dir(reader, Path::new(""))
+    }
+
+    /// Check the store of an index saved with its texts outside the index
+    /// file, returning an error if any of its files which haven't been read
+    /// yet are missing or have changed since the index was saved. Loading
+    /// the index checks this, so this is only needed to check that none
+    /// have changed since. A search will panic if a file can't be read.
+    pub fn verify_text_store(&self) -> Result<(), io::Error> {
+        self.str_storage.verify()
+    }
+
+    fn load_in_dir<R: Read>(mut reader: R, index_dir: &Path) -> Result<SuffixTrie, io::Error> {
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+
+        if !contents.starts_with(MAGIC) {
+            warn!("No magic number found, attempting to read index as format version 0");
+            let legacy: LegacySuffixTrieV0 = bincode::deserialize(&contents).map_err(|e| {
+                invalid_data(format!("Not a spyglass index: no magic number and not a version 0 index ({})", e))
+            })?;
+            return Ok(SuffixTrie::from(legacy));
+        }
+
+        let mut remaining = &contents[..];
+        let header = IndexHeader::read_from(&mut remaining)?;
+        if remaining.len() as u64 != header.payload_length {
+            return Err(invalid_data(format!("Index is truncated or corrupt: expected {} bytes of data, found {}",
+                                            header.payload_length, remaining.len())));
+        }
+        let checksum = crc32fast::hash(remaining);
+        if checksum != header.checksum {
+            return Err(invalid_data(format!("Index is corrupt: checksum is {:08x}, expected {:08x}",
+                                            checksum, header.checksum)));
+        }
+
+        let mut suffix_trie: SuffixTrie = match header.version {
+            1 | 2 => {
+                let legacy: LegacySuffixTrie<LegacyTextV2> = bincode::deserialize(remaining).map_err(invalid_data)?;
+                SuffixTrie::from(legacy)
+            },
+            3 => {
+                let legacy: LegacySuffixTrie<LegacyTextV3> = bincode::deserialize(remaining).map_err(invalid_data)?;
+                SuffixTrie::from(legacy)
+            },
+            _ => bincode::deserialize(remaining).map_err(invalid_data)?,
+        };
+        if suffix_trie.options != header.options {
+            return Err(invalid_data("Index is corrupt: build options in header don't match the index"));
+        }
+        match &header.text_store {
+            TextStore::Embedded => {},
+            TextStore::SourceFiles => suffix_trie.open_source_files(&header, index_dir)?,
+            TextStore::BlobFile(blob_path) => {
+                let checksum = match header.text_checksums[..] {
+                    [checksum] => checksum,
+                    _ => return Err(invalid_data("Index is corrupt: wrong number of blob file checksums")),
+                };
+                let blob_path = index_dir.join(blob_path);
+                suffix_trie.str_storage = CharStorage::External(vec![ExternalChars::new(0, blob_path, checksum, None)]);
+            },
+        }
+        suffix_trie.verify_text_store()?;
+        Ok(suffix_trie)
+    }
+
+    /// Check that each text can be read back from the file it was added
+    /// from, returning the checksums of the files and their paths relative
+    /// to the directory of the index
+    fn checksum_source_files(&self, index_dir: &Path) -> Result<(Vec<u32>, Vec<String>), io::Error> {
+        let mut checksums = vec![];
+        let mut paths = vec![];
+        let mut expected_offset = 0;
+        for text in self.texts.iter() {
+            if text.offset != expected_offset {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      "Storage contains removed texts, call compact_str_storage before saving"));
+            }
+            expected_offset += text.last_index;
+
+            let contents = fs::read(&text.name)?;
+            let chars = self.options.normalised_chars(&String::from_utf8_lossy(&contents));
+            if !chars.iter().copied().eq(self.str_storage.chars(text.offset .. text.offset + text.last_index)) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("Text {} doesn't match the contents of the file with that name",
+                                              text.name)));
+            }
+            checksums.push(crc32fast::hash(&contents));
+            paths.push(relative_path(index_dir, Path::new(&text.name))?.to_string_lossy().into_owned());
+        }
+        Ok((checksums, paths))
+    }
+
+    /// Find the file each text was added from, to be read when its text is
+    /// first needed
+    fn open_source_files(&mut self, header: &IndexHeader, index_dir: &Path) -> Result<(), io::Error> {
+        if header.text_checksums.len() != self.texts.len() {
+            return Err(invalid_data("Index is corrupt: wrong number of source file checksums"));
+        }
+        // Older indexes only have the names of the texts to go on
+        let paths: Vec<PathBuf> = if header.version < 5 {
+            self.texts.iter().map(|text| PathBuf::from(&text.name)).collect()
+        } else if header.source_paths.len() == self.texts.len() {
+            header.source_paths.iter().map(|path| index_dir.join(path)).collect()
+        } else {
+            return Err(invalid_data("Index is corrupt: wrong number of source file paths"));
+        };
+        let mut files = vec![];
+        for ((text, checksum), path) in self.texts.iter().zip(&header.text_checksums).zip(paths) {
+            files.push(ExternalChars::new(text.offset, path, *checksum, Some(self.options.clone())));
+        }
+        self.str_storage = CharStorage::External(files);
+        Ok(())
+    }
+}
+
+/// Directory that the paths of a blob file or source files saved with the
+/// index at the given path are relative to
+fn index_dir(path: &str) -> &Path {
+    Path::new(path).parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// Path of an existing file relative to a directory, going up out of the
+/// directory with `..` as needed
+fn relative_path(dir: &Path, path: &Path) -> Result<PathBuf, io::Error> {
+    let dir = fs::canonicalize(if dir.as_os_str().is_empty() { Path::new(".") } else { dir })?;
+    let path = fs::canonicalize(path)?;
+    let shared = dir.components().zip(path.components()).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = dir.components().skip(shared).map(|_| Component::ParentDir).collect();
+    relative.extend(path.components().skip(shared));
+    Ok(relative)
+}
+
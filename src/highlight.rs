@@ -0,0 +1,116 @@
+//! Rendering of matches with the errors found by aligning them with the
+//! pattern marked, either with ANSI colours for terminals or with brackets
+//! for plain text.
+use crate::alignment::{AlignedChar,Edit};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use crate::alignment::align_pattern;
+
+    #[test]
+    fn brackets() {
+        let alignment = align_pattern("sentxnces", "sentnce\ns", &HashMap::new(), false);
+        assert_eq!(render_alignment(&alignment, HighlightStyle::Brackets), "sent<x>nce{\n}s");
+        let alignment = align_pattern("sentxnce", "sentence", &HashMap::new(), false);
+        assert_eq!(render_alignment(&alignment, HighlightStyle::Brackets), "sent[e]nce");
+        assert_eq!(render_alignment(&alignment, HighlightStyle::Plain), "sentence");
+    }
+
+    #[test]
+    fn ansi() {
+        let alignment = align_pattern("sxnt", "sent\nence", &HashMap::new(), false);
+        assert_eq!(render_alignment(&alignment, HighlightStyle::Ansi),
+                   "\x1b[1ms\x1b[0m\x1b[1;31me\x1b[0m\x1b[1mnt\x1b[0m\n\x1b[1;33mence\x1b[0m");
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// How the errors in a match should be marked
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum HighlightStyle {
+    /// Bold for matching characters, red for mismatches, yellow for
+    /// insertions and underlined cyan for characters of the pattern missing
+    /// from the text
+    Ansi,
+    /// Mismatches in square brackets, insertions in braces and characters of
+    /// the pattern missing from the text in angle brackets, e.g. "sent[e]nce"
+    /// for the pattern "sentxnce"
+    Brackets,
+    /// Just the text of the match
+    Plain,
+}
+
+impl HighlightStyle {
+    fn ansi_code(edit: Edit) -> &'static str {
+        match edit {
+            Edit::Match => "\x1b[1m",
+            Edit::Mismatch => "\x1b[1;31m",
+            Edit::Insertion => "\x1b[1;33m",
+            Edit::Deletion => "\x1b[4;36m",
+        }
+    }
+
+    fn brackets(edit: Edit) -> (&'static str, &'static str) {
+        match edit {
+            Edit::Match => ("", ""),
+            Edit::Mismatch => ("[", "]"),
+            Edit::Insertion => ("{", "}"),
+            Edit::Deletion => ("<", ">"),
+        }
+    }
+}
+
+/// Render the text of a match with its errors marked. Newlines are never
+/// inside ANSI escape codes, so the result can be split into lines.
+pub fn render_alignment(alignment: &[AlignedChar], style: HighlightStyle) -> String {
+    let mut rendered = String::new();
+    match style {
+        HighlightStyle::Plain => {
+            for aligned in alignment.iter().filter(|aligned| aligned.edit != Edit::Deletion) {
+                rendered.push(aligned.c);
+            }
+        },
+        HighlightStyle::Brackets => {
+            // Consecutive characters with the same edit share brackets
+            let mut previous = Edit::Match;
+            for aligned in alignment.iter() {
+                if aligned.edit != previous {
+                    rendered.push_str(HighlightStyle::brackets(previous).1);
+                    rendered.push_str(HighlightStyle::brackets(aligned.edit).0);
+                    previous = aligned.edit;
+                }
+                rendered.push(aligned.c);
+            }
+            rendered.push_str(HighlightStyle::brackets(previous).1);
+        },
+        HighlightStyle::Ansi => {
+            let mut current = None;
+            for aligned in alignment.iter() {
+                if aligned.c == '\n' {
+                    if current.take().is_some() {
+                        rendered.push_str(ANSI_RESET);
+                    }
+                    rendered.push('\n');
+                    continue;
+                }
+                if current != Some(aligned.edit) {
+                    if current.is_some() {
+                        rendered.push_str(ANSI_RESET);
+                    }
+                    rendered.push_str(HighlightStyle::ansi_code(aligned.edit));
+                    current = Some(aligned.edit);
+                }
+                rendered.push(aligned.c);
+            }
+            if current.is_some() {
+                rendered.push_str(ANSI_RESET);
+            }
+        },
+    }
+    rendered
+}
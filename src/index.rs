@@ -0,0 +1,304 @@
+//! Common interface to the different kinds of saved index, so that tools can
+//! search whichever kind they are given.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use serde::Serialize;
+
+use crate::{collocations,distinctive,mapped,ngrams,repeats,reuse,segmented,words};
+use crate::{BooleanHit,BooleanOptions,BooleanQuery,BuildOptions,Collocate,CollocationOptions,DistinctiveOptions,DistinctivePhrase,HighScoringPair,MappedSuffixTrie,Match,NgramOptions,NgramTable,ProximityOptions,ProximityQuery,Repeat,ReuseGraph,SeedOptions,SegmentedIndex,SharedPassage,SharedSubstring,SuffixTrie,Text,WordIndex};
+
+/// Summary of a text in an index
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct TextInfo {
+    pub name: String,
+    pub num_chars: usize,
+    pub num_lines: usize,
+}
+
+impl From<&Text> for TextInfo {
+    fn from(text: &Text) -> Self {
+        TextInfo {
+            name: text.name.to_string(),
+            num_chars: text.last_index,
+            num_lines: text.line_start_indices.len(),
+        }
+    }
+}
+
+/// The kinds of index which can be saved to disk
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum IndexKind {
+    /// Saved by SuffixTrie::save
+    Standard,
+    /// Saved by SuffixTrie::save_mapped
+    Mapped,
+    /// Created by SegmentedIndex::create
+    Segmented,
+    /// Saved by WordIndex::save
+    Words,
+}
+
+/// Searches and text retrieval supported by every kind of index
+pub trait SearchIndex: Send + Sync {
+    fn find_exact(&self, pattern: &str) -> Vec<Match>;
+    /// Number of exact matches of the pattern in each text, in the order
+    /// of get_text_info
+    fn count_exact(&self, pattern: &str) -> Vec<usize>;
+    fn find_edit_distance_ignore(&self,
+                                 pattern: &str,
+                                 max_errors: usize,
+                                 ignored_characters: HashMap<char, bool>) -> Vec<Match>;
+    fn find_wildcard_ignore(&self,
+                            pattern: &str,
+                            max_errors: usize,
+                            ignored_characters: HashMap<char, bool>) -> Vec<Match>;
+    fn get_strings_of_match(&self,
+                            match_obj: &Match,
+                            context_lines: usize) -> (String, String, String);
+    fn get_sentences_of_match(&self,
+                              match_obj: &Match,
+                              context_sentences: usize) -> (String, String, String);
+    fn get_text_info(&self) -> Vec<TextInfo>;
+    fn get_options(&self) -> &BuildOptions;
+    /// Match of the given length at a position in a text, with its lines
+    /// and columns worked out, or None if it doesn't lie within the text
+    fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match>;
+    /// The sentences, lines or texts satisfying a boolean combination of
+    /// search terms
+    fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error>;
+    /// Spans where the terms of a proximity query are near each other
+    fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match>;
+
+    fn find_edit_distance(&self, pattern: &str, max_errors: usize) -> Vec<Match> {
+        self.find_edit_distance_ignore(pattern, max_errors, HashMap::new())
+    }
+
+    fn find_wildcard(&self, pattern: &str, max_errors: usize) -> Vec<Match> {
+        self.find_wildcard_ignore(pattern, max_errors, HashMap::new())
+    }
+
+    fn get_text_names(&self) -> Vec<String> {
+        self.get_text_info().into_iter().map(|text| text.name).collect()
+    }
+
+    /// Whether searches match whole words rather than characters, so the
+    /// errors of a match can't be marked character by character
+    fn matches_words(&self) -> bool {
+        false
+    }
+}
+
+macro_rules! impl_search_index {
+    ($index_type:ty) => {
+        impl SearchIndex for $index_type {
+            fn find_exact(&self, pattern: &str) -> Vec<Match> {
+                <$index_type>::find_exact(self, pattern)
+            }
+
+            fn count_exact(&self, pattern: &str) -> Vec<usize> {
+                <$index_type>::count_exact(self, pattern)
+            }
+
+            fn find_edit_distance_ignore(&self,
+                                         pattern: &str,
+                                         max_errors: usize,
+                                         ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+                <$index_type>::find_edit_distance_ignore(self, pattern, max_errors, ignored_characters)
+            }
+
+            fn find_wildcard_ignore(&self,
+                                    pattern: &str,
+                                    max_errors: usize,
+                                    ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+                <$index_type>::find_wildcard_ignore(self, pattern, max_errors, ignored_characters)
+            }
+
+            fn get_strings_of_match(&self,
+                                    match_obj: &Match,
+                                    context_lines: usize) -> (String, String, String) {
+                <$index_type>::get_strings_of_match(self, match_obj, context_lines)
+            }
+
+            fn get_sentences_of_match(&self,
+                                      match_obj: &Match,
+                                      context_sentences: usize) -> (String, String, String) {
+                <$index_type>::get_sentences_of_match(self, match_obj, context_sentences)
+            }
+
+            fn get_text_info(&self) -> Vec<TextInfo> {
+                <$index_type>::get_text_info(self)
+            }
+
+            fn get_options(&self) -> &BuildOptions {
+                <$index_type>::get_options(self)
+            }
+
+            fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+                <$index_type>::get_match_at(self, text_index, index_in_str, length)
+            }
+
+            fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+                <$index_type>::boolean_search(self, query, options)
+            }
+
+            fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+                <$index_type>::find_near(self, query, options)
+            }
+        }
+    }
+}
+
+impl_search_index!(SuffixTrie);
+impl_search_index!(SegmentedIndex);
+
+/// Analyses which walk the whole trie of an index, so are only supported by
+/// the kinds of index made of a single trie
+pub trait TrieAnalysis {
+    /// The longest strings occurring more than once in the texts. There
+    /// may be several of the same length.
+    fn longest_repeats(&self) -> Vec<Repeat>;
+    /// Strings of at least min_length characters occurring more than once
+    /// which can't be extended without losing an occurrence, longest first
+    fn maximal_repeats(&self, min_length: usize) -> Vec<Repeat>;
+    /// Maximal repeats of at least min_length characters which aren't part
+    /// of any other maximal repeat, longest first
+    fn supermaximal_repeats(&self, min_length: usize) -> Vec<Repeat>;
+    /// Substrings of at least min_length characters shared by the two
+    /// texts which can't be extended in either direction, in order of
+    /// their start in text_a
+    fn find_shared_substrings(&self,
+                              text_a: &str,
+                              text_b: &str,
+                              min_length: usize) -> Result<Vec<SharedSubstring>, io::Error>;
+    /// Passages of text_a borrowed from text_b, found by merging shared
+    /// substrings of at least min_length characters which are at most
+    /// max_gap characters apart in both texts
+    fn find_shared_passages(&self,
+                            text_a: &str,
+                            text_b: &str,
+                            min_length: usize,
+                            max_gap: usize) -> Result<Vec<SharedPassage>, io::Error>;
+    /// Approximate text reuse between the two texts, found by extending
+    /// exact shared seeds with banded alignment, best scoring first
+    fn find_high_scoring_pairs(&self,
+                               text_a: &str,
+                               text_b: &str,
+                               options: &SeedOptions) -> Result<Vec<HighScoringPair>, io::Error>;
+    /// Passages shared between every pair of texts, found in a single walk
+    /// of the trie
+    fn reuse_graph(&self, min_length: usize, max_gap: usize) -> ReuseGraph;
+    /// Frequency table of the character or word n-grams across all the
+    /// texts, counted from the leaves below each n-gram
+    fn ngram_frequencies(&self, options: &NgramOptions) -> NgramTable;
+    /// Frequency table of the n-grams of each text
+    fn ngram_frequencies_per_text(&self, options: &NgramOptions) -> Vec<NgramTable>;
+    /// Substrings occurring at a significantly different rate in the texts
+    /// of one group than in the other, most distinctive first
+    fn find_distinctive_phrases(&self,
+                                group_a: &[&str],
+                                group_b: &[&str],
+                                options: &DistinctiveOptions) -> Result<Vec<DistinctivePhrase>, io::Error>;
+    /// Words found within a number of words either side of the matches,
+    /// with their association with the matches
+    fn collocations(&self, matches: &[Match], options: &CollocationOptions) -> Vec<Collocate>;
+}
+
+macro_rules! impl_trie_analysis {
+    ($index_type:ty) => {
+        impl TrieAnalysis for $index_type {
+            fn longest_repeats(&self) -> Vec<Repeat> {
+                repeats::longest_repeats(self)
+            }
+
+            fn maximal_repeats(&self, min_length: usize) -> Vec<Repeat> {
+                repeats::maximal_repeats(self, min_length)
+            }
+
+            fn supermaximal_repeats(&self, min_length: usize) -> Vec<Repeat> {
+                repeats::supermaximal_repeats(self, min_length)
+            }
+
+            fn find_shared_substrings(&self,
+                                      text_a: &str,
+                                      text_b: &str,
+                                      min_length: usize) -> Result<Vec<SharedSubstring>, io::Error> {
+                let (text_a, text_b) = (reuse::text_index_of(self, text_a)?, reuse::text_index_of(self, text_b)?);
+                Ok(reuse::shared_substrings(self, text_a, text_b, min_length))
+            }
+
+            fn find_shared_passages(&self,
+                                    text_a: &str,
+                                    text_b: &str,
+                                    min_length: usize,
+                                    max_gap: usize) -> Result<Vec<SharedPassage>, io::Error> {
+                let (text_a, text_b) = (reuse::text_index_of(self, text_a)?, reuse::text_index_of(self, text_b)?);
+                Ok(reuse::shared_passages(self, text_a, text_b, min_length, max_gap))
+            }
+
+            fn find_high_scoring_pairs(&self,
+                                       text_a: &str,
+                                       text_b: &str,
+                                       options: &SeedOptions) -> Result<Vec<HighScoringPair>, io::Error> {
+                let (text_a, text_b) = (reuse::text_index_of(self, text_a)?, reuse::text_index_of(self, text_b)?);
+                Ok(reuse::high_scoring_pairs(self, text_a, text_b, options))
+            }
+
+            fn reuse_graph(&self, min_length: usize, max_gap: usize) -> ReuseGraph {
+                reuse::reuse_graph(self, min_length, max_gap)
+            }
+
+            fn ngram_frequencies(&self, options: &NgramOptions) -> NgramTable {
+                ngrams::ngram_frequencies(self, options)
+            }
+
+            fn ngram_frequencies_per_text(&self, options: &NgramOptions) -> Vec<NgramTable> {
+                ngrams::ngram_frequencies_per_text(self, options)
+            }
+
+            fn find_distinctive_phrases(&self,
+                                        group_a: &[&str],
+                                        group_b: &[&str],
+                                        options: &DistinctiveOptions) -> Result<Vec<DistinctivePhrase>, io::Error> {
+                distinctive::distinctive_phrases(self, group_a, group_b, options)
+            }
+
+            fn collocations(&self, matches: &[Match], options: &CollocationOptions) -> Vec<Collocate> {
+                collocations::collocations(self, matches, options)
+            }
+        }
+    }
+}
+
+impl_trie_analysis!(SuffixTrie);
+impl_trie_analysis!(MappedSuffixTrie);
+
+/// Work out what kind of index is saved at the given path
+pub fn detect_index_kind(path: &str) -> Result<IndexKind, io::Error> {
+    let mut magic = vec![];
+    File::open(path)?.take(8).read_to_end(&mut magic)?;
+
+    if magic == mapped::MAGIC {
+        Ok(IndexKind::Mapped)
+    } else if magic == segmented::MAGIC {
+        Ok(IndexKind::Segmented)
+    } else if magic == words::MAGIC {
+        Ok(IndexKind::Words)
+    } else {
+        // Includes older indexes saved without a magic number
+        Ok(IndexKind::Standard)
+    }
+}
+
+/// Open an index of any kind saved at the given path
+pub fn load_index(path: &str) -> Result<Box<dyn SearchIndex>, io::Error> {
+    let index: Box<dyn SearchIndex> = match detect_index_kind(path)? {
+        IndexKind::Standard => Box::new(SuffixTrie::load(path)?),
+        IndexKind::Mapped => Box::new(MappedSuffixTrie::open(path)?),
+        IndexKind::Segmented => Box::new(SegmentedIndex::open(path)?),
+        IndexKind::Words => Box::new(WordIndex::open(path)?),
+    };
+    Ok(index)
+}
@@ -7,9 +7,51 @@ use std::collections::HashMap;
 use std::str::Chars;
 
 use deunicode;
+use deunicode::AsciiChars;
 use log::{info,warn,debug,error};
 use serde::{Serialize,Deserialize};
 
+mod alignment;
+mod boolean;
+mod collocations;
+mod concordance;
+mod distinctive;
+mod export;
+mod format;
+mod highlight;
+mod index;
+mod mapped;
+mod ngrams;
+mod offsets;
+mod proximity;
+mod repeats;
+mod reuse;
+mod segmented;
+mod stats;
+mod storage;
+mod words;
+
+pub use alignment::{align_match,align_pattern,count_errors,extend_alignment,AlignedChar,Edit,Extension,ExtensionScoring};
+pub use boolean::{BooleanHit,BooleanOptions,BooleanQuery,QueryScope};
+pub use collocations::{Collocate,CollocationOptions,CollocationSort};
+pub use concordance::{concordance,sort_concordance,ConcordanceLine,ConcordanceSort};
+pub use distinctive::{DistinctiveOptions,DistinctivePhrase,Subcorpus};
+pub use export::{write_csv,write_json_lines,MatchRecord};
+pub use format::{IndexHeader,TextStore,FORMAT_VERSION};
+pub use highlight::{render_alignment,HighlightStyle};
+pub use index::{detect_index_kind,load_index,IndexKind,SearchIndex,TextInfo,TrieAnalysis};
+pub use mapped::{MappedSuffixTrie,MAPPED_FORMAT_VERSION};
+pub use ngrams::{write_ngram_csv,NgramCount,NgramOptions,NgramTable,NgramUnit};
+pub use proximity::{DistanceUnit,ProximityOptions,ProximityQuery};
+pub use repeats::Repeat;
+pub use reuse::{merge_shared_substrings,HighScoringPair,ReuseEdge,ReuseGraph,SeedOptions,SharedPassage,SharedSubstring,SharedSubstrings};
+pub use segmented::{SegmentedIndex,SEGMENTED_FORMAT_VERSION};
+pub use stats::{LineRangeStats,SearchStats,TextStats};
+pub use words::{WordIndex,WORDS_FORMAT_VERSION};
+
+use offsets::ByteOffsets;
+use storage::CharStorage;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,6 +100,89 @@ mod tests {
         assert!(expected.is_superset(&actual));
     }
 
+    #[test]
+    fn test_merge_size() {
+        utilities::init_testing();
+        let first = "ABCDE.<<STOP>>abcabdabe";
+        let second = "ABXDE.<<STOP>>abcab<<STOP>>xyz";
+
+        let mut expected = SuffixTrie::empty();
+        expected.add_sentences_from_text("first", first);
+        expected.add_sentences_from_text("second", second);
+
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", first);
+        let mut other = SuffixTrie::empty();
+        other.add_sentences_from_text("second", second);
+        let merged = trie.merge(other).unwrap();
+        println!("Result is {:#?}", merged);
+
+        assert_eq!(merged.len(), expected.len());
+        assert_eq!(merged.str_storage, expected.str_storage);
+        for node in merged.node_storage.iter() {
+            assert!(node.edge_length > 0 || node.node_index == 0);
+        }
+    }
+
+    #[test]
+    fn test_remove_text_size() {
+        utilities::init_testing();
+        let first = "ABCDE.<<STOP>>abcabdabe";
+        let second = "ABXDE.<<STOP>>abcab<<STOP>>xyz";
+        let third = "abXab.<<STOP>>ABCDE";
+
+        let mut expected = SuffixTrie::empty();
+        expected.add_sentences_from_text("first", first);
+        expected.add_sentences_from_text("third", third);
+
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", first);
+        trie.add_sentences_from_text("second", second);
+        trie.add_sentences_from_text("third", third);
+        trie.remove_text("second").unwrap();
+        println!("Result is {:#?}", trie);
+        assert_eq!(trie.len(), expected.len());
+        assert!(trie.str_storage.len() > expected.str_storage.len());
+
+        trie.compact_str_storage();
+        println!("Result is {:#?}", trie);
+        assert_eq!(trie.len(), expected.len());
+        assert_eq!(trie.str_storage, expected.str_storage);
+        helper_test_all_leaves_present(&trie);
+
+        assert!(trie.remove_text("second").is_err());
+    }
+
+    fn helper_test_all_leaves_present(trie: &SuffixTrie) {
+        let mut expected: HashSet<(usize, usize)> = HashSet::new();
+        for (text_index, text) in trie.texts.iter().enumerate() {
+            for index_in_str in 0..text.last_index {
+                expected.insert((text_index, index_in_str));
+            }
+        }
+        let mut actual: HashSet<(usize, usize)> = HashSet::new();
+        for node in trie.node_storage.iter() {
+            for leaf in node.leaf_children.iter() {
+                assert!(actual.insert((leaf.text_index, leaf.index_in_str)));
+            }
+        }
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_normalise_with_offsets() {
+        let string = "father’s xxÆlfred\nxx…he\tends";
+        for normaliser in [Normaliser::Deunicode, Normaliser::DeunicodeLowercase] {
+            let (normalised, offsets) = normaliser.normalise_with_offsets(string);
+            assert_eq!(normalised, normaliser.normalise(string));
+            assert_eq!(offsets.len(), normalised.len());
+        }
+        let (_, offsets) = Normaliser::Deunicode.normalise_with_offsets(string);
+        assert_eq!(offsets[6], (6, 3));
+        assert_eq!(offsets[7], (9, 1));
+        assert_eq!(&offsets[11 .. 14], &[(13, 2), (13, 2), (15, 1)]);
+    }
+
     #[test]
     fn line_number_calculation() {
         utilities::init_testing();
@@ -87,6 +212,82 @@ mod tests {
 }
 
 const SINGLE_WILDCARD: char = '?';
+const MULTI_WILDCARD: char = '*';
+const DEFAULT_SENTENCE_SPLITTER: &str = "<<STOP>>";
+
+/// Normalisation applied to both the texts and the search patterns
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum Normaliser {
+    /// Transliterate to ASCII e.g. 'Æ' becomes "AE"
+    Deunicode,
+    /// Transliterate to ASCII and convert to lower case
+    DeunicodeLowercase,
+}
+
+impl Normaliser {
+    pub fn normalise(&self, string: &str) -> String {
+        match self {
+            Normaliser::Deunicode => deunicode::deunicode(string),
+            Normaliser::DeunicodeLowercase => deunicode::deunicode(string).to_lowercase(),
+        }
+    }
+
+    /// Normalise the string in the same way as normalise, also returning
+    /// the byte offset and length in the string of the character each
+    /// normalised character came from
+    fn normalise_with_offsets(&self, string: &str) -> (String, Vec<(usize, usize)>) {
+        let mut normalised = String::with_capacity(string.len());
+        let mut offsets = Vec::with_capacity(string.len());
+        // deunicode copies the ASCII characters at the start of the string
+        // unchanged, and transliterates everything from the first
+        // non-ASCII character onwards (which removes control characters)
+        let ascii_prefix_length = string.bytes().take_while(|byte| *byte < 0x7F).count();
+        for ((byte_offset, c), ascii) in string.char_indices().zip(string.ascii_chars()) {
+            let ascii = if byte_offset < ascii_prefix_length {
+                &string[byte_offset .. byte_offset + 1]
+            } else {
+                // deunicode replaces characters it can't transliterate with this
+                ascii.unwrap_or("[?]")
+            };
+            normalised.push_str(ascii);
+            offsets.extend(std::iter::repeat_n((byte_offset, c.len_utf8()), ascii.len()));
+        }
+        if *self == Normaliser::DeunicodeLowercase {
+            normalised.make_ascii_lowercase();
+        }
+        (normalised, offsets)
+    }
+}
+
+/// Options controlling how texts are added to a suffix trie. These are
+/// recorded in saved indexes since searches must be normalised in the
+/// same way as the texts were.
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct BuildOptions {
+    pub normaliser: Normaliser,
+    // Texts are split into sentences at each occurrence of this string,
+    // and matches never cross sentence boundaries
+    pub sentence_splitter: String,
+}
+
+impl BuildOptions {
+    /// Characters stored for the given text contents, i.e. its normalised
+    /// sentences with the splitters removed
+    fn normalised_chars(&self, contents: &str) -> Vec<char> {
+        contents.split(self.sentence_splitter.as_str())
+            .flat_map(|sentence| self.normaliser.normalise(sentence).chars().collect::<Vec<char>>())
+            .collect()
+    }
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            normaliser: Normaliser::Deunicode,
+            sentence_splitter: DEFAULT_SENTENCE_SPLITTER.to_string(),
+        }
+    }
+}
 
 #[derive(Clone,Debug,Eq,Serialize,Deserialize)]
 pub struct Match {
@@ -94,8 +295,21 @@ pub struct Match {
     pub index_in_str: usize,
     pub start_line: usize,
     pub end_line: usize,
+    // Column of the first character of the match within start_line, and of
+    // the character just after the match within end_line. Columns count
+    // characters of the normalised text, starting from 0.
+    pub start_column: usize,
+    pub end_column: usize,
     pub length: usize,
     pub errors: usize,
+    // Index of the sentence containing the match within its text, unknown
+    // for indexes saved before sentence boundaries were recorded
+    pub sentence: Option<usize>,
+    // Byte offsets in the original file of the start of the match and just
+    // after its end. These are unknown for indexes saved before they were
+    // recorded.
+    pub start_byte: Option<usize>,
+    pub end_byte: Option<usize>,
 }
 
 impl Ord for Match {
@@ -157,13 +371,17 @@ impl Leaf {
     }
 }
 
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Clone,Debug,Serialize,Deserialize)]
 struct Text {
     name: String,
     // Indices of the starts of lines
     line_start_indices: Vec<usize>,
     last_index: usize,
     offset: usize,
+    // Positions of the characters in the original text, if known
+    byte_offsets: Option<ByteOffsets>,
+    // Indices of the starts of sentences, if known
+    sentence_start_indices: Option<Vec<usize>>,
 }
 
 impl Text {
@@ -173,9 +391,34 @@ impl Text {
             line_start_indices: vec![0],
             last_index: 0,
             offset,
+            byte_offsets: Some(ByteOffsets::default()),
+            sentence_start_indices: Some(vec![]),
         }
     }
 
+    fn get_sentence_of_character(&self, char_index: usize) -> Option<usize> {
+        self.sentence_start_indices.as_ref().map(|sentence_start_indices| {
+            // Empty sentences share their start index with the next
+            // sentence, so take the last sentence starting at or before
+            // the character
+            sentence_start_indices.partition_point(|start| *start <= char_index).saturating_sub(1)
+        })
+    }
+
+    /// Whether the character is the first of its sentence. Only the start
+    /// of the text is known to start a sentence for texts saved before
+    /// sentence starts were recorded.
+    fn starts_sentence(&self, char_index: usize) -> bool {
+        match &self.sentence_start_indices {
+            Some(sentence_start_indices) => char_index == 0 || sentence_start_indices.binary_search(&char_index).is_ok(),
+            None => char_index == 0,
+        }
+    }
+
+    fn get_column_of_character(&self, char_index: usize, line_index: usize) -> usize {
+        char_index - self.line_start_indices[line_index]
+    }
+
     fn char_before_line(&self, char_index: usize, line_index: usize) -> bool {
         let is_before_line;
         if line_index == self.line_start_indices.len() {
@@ -294,15 +537,17 @@ struct WorkingMatch {
 #[derive(Debug,Serialize,Deserialize)]
 pub struct SuffixTrie {
     // Place to store entire string - keeps ownership simple
-    str_storage: Vec<char>,
+    str_storage: CharStorage,
     // Place to store all the nodes
     node_storage: Vec<SubTrie>,
     // Information about each of the texts (e.g. files) included in
     // the Suffix Trie
     texts: Vec<Text>,
+    // Options used when adding texts to the Suffix Trie
+    options: BuildOptions,
 }
 
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Clone,Debug,Serialize,Deserialize)]
 struct SubTrie {
     // Index of this node in the overall array
     node_index: usize,
@@ -332,19 +577,24 @@ impl SuffixTrie {
     pub fn new(string: &str) -> Self {
         let mut suffix_trie = SuffixTrie::empty();
         suffix_trie.texts.push(Text::new("first text", 0));
-        suffix_trie.add_string_suffixes(string, 0, 0);
+        suffix_trie.add_string_suffixes(string, 0, 0, 0);
         suffix_trie
     }
 
     /// New empty suffix trie
     pub fn empty() -> Self {
+        SuffixTrie::empty_with_options(BuildOptions::default())
+    }
+
+    /// New empty suffix trie which will add texts using the given options
+    pub fn empty_with_options(options: BuildOptions) -> Self {
         let root_node = SubTrie::empty(0, 0, 0);
-        let suffix_trie = SuffixTrie {
-            str_storage: vec![],
+        SuffixTrie {
+            str_storage: CharStorage::default(),
             node_storage: vec![root_node],
             texts: vec![],
-        };
-        suffix_trie
+            options,
+        }
     }
 
     /// New suffix trie containing the suffixes of each sentence from
@@ -363,16 +613,18 @@ impl SuffixTrie {
 
 
     pub fn add_sentences_from_text(&mut self, text_name: &str, contents: &str) {
-        let sentences: Vec<&str> = contents.split("<<STOP>>").collect();
+        let sentences: Vec<&str> = contents.split(self.options.sentence_splitter.as_str()).collect();
 
         let offset = self.str_storage.len();
         self.texts.push(Text::new(text_name, offset));
         let text_index = self.texts.len() - 1;
 
         let mut sentence_start = 0;
+        let mut sentence_start_byte = 0;
         for sentence in sentences {
-            let num_chars = self.add_string_suffixes(sentence, sentence_start, text_index);
+            let num_chars = self.add_string_suffixes(sentence, sentence_start, sentence_start_byte, text_index);
             sentence_start += num_chars;
+            sentence_start_byte += sentence.len() + self.options.sentence_splitter.len();
         }
     }
 
@@ -380,7 +632,12 @@ impl SuffixTrie {
     /// each file in the given directory
     pub fn from_directory(path: &str) -> Result<SuffixTrie, io::Error> {
         let mut suffix_trie = SuffixTrie::empty();
+        suffix_trie.add_directory(path)?;
+        Ok(suffix_trie)
+    }
 
+    /// Add each file in the given directory, in order of their paths
+    pub fn add_directory(&mut self, path: &str) -> Result<(), io::Error> {
         let files = fs::read_dir(path)?;
         let mut paths: Vec<String> = vec![];
 
@@ -397,20 +654,250 @@ impl SuffixTrie {
         paths.sort();
 
         for path in paths {
-            suffix_trie.add_file(&path)?
+            self.add_file(&path)?
         }
 
-        Ok(suffix_trie)
+        Ok(())
+    }
+
+    /// Combine two suffix tries into one containing the texts of both.
+    /// The texts of other are placed after the texts of this trie, so
+    /// searches give the same results as a trie built from the texts of
+    /// this trie followed by the texts of other.
+    /// Both tries must have been built with the same options.
+    pub fn merge(mut self, other: SuffixTrie) -> Result<SuffixTrie, io::Error> {
+        self.merge_from(&other)?;
+        Ok(self)
+    }
+
+    /// Add the texts of other to this trie in the same way as merge,
+    /// leaving other as it is. This trie is unchanged if they can't be
+    /// merged.
+    pub(crate) fn merge_from(&mut self, other: &SuffixTrie) -> Result<(), io::Error> {
+        if self.options != other.options {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Cannot merge tries built with different options: {:?} and {:?}",
+                                          self.options, other.options)));
+        }
+        let str_offset = self.str_storage.len();
+        let text_offset = self.texts.len();
+
+        // Rebase the other trie so that it refers to its strings and texts
+        // once they have been appended to ours
+        self.str_storage.to_mut().extend(other.str_storage.iter());
+        for text in other.texts.iter() {
+            self.texts.push(Text { offset: text.offset + str_offset, ..text.clone() });
+        }
+        let mut node_storage = other.node_storage.clone();
+        for node in node_storage.iter_mut() {
+            node.edge_start_index += str_offset;
+            for leaf in node.leaf_children.iter_mut() {
+                leaf.text_index += text_offset;
+            }
+        }
+
+        self.merge_node_contents(&node_storage, 0, 0);
+        Ok(())
+    }
+
+    /// Add the leaves of the other node to our node, and queue up each of
+    /// the other node's children to be merged in below our node.
+    /// Each entry is (our parent node, other node, number of characters of
+    /// the other node's edge already present above our parent node).
+    fn merge_node_contents(&mut self,
+                           other_nodes: &[SubTrie],
+                           node_index: usize,
+                           other_index: usize) {
+        let mut to_merge: Vec<(usize, usize, usize)> = vec![];
+        self.queue_merge_of_children(other_nodes, node_index, other_index, &mut to_merge);
+
+        while let Some((parent_index, other_index, consumed)) = to_merge.pop() {
+            let other_node = &other_nodes[other_index];
+            let other_start = other_node.edge_start_index + consumed;
+            let other_length = other_node.edge_length - consumed;
+            let edge = self.str_storage[other_start];
+
+            let merged_index = match self.get_node(parent_index).get_child_index(edge) {
+                Some(child_index) => {
+                    let child_index = *child_index;
+                    let child = self.get_node(child_index);
+                    let child_start = child.edge_start_index;
+                    let child_length = child.edge_length;
+
+                    // The first character is known to agree, find how much
+                    // more of the two edges is shared
+                    let max_shared = cmp::min(child_length, other_length);
+                    let mut shared_length = 1;
+                    while shared_length < max_shared &&
+                        self.str_storage[child_start + shared_length] == self.str_storage[other_start + shared_length] {
+                        shared_length += 1;
+                    }
+                    debug!("Merging node {} below {}, shares {} characters with existing node {}",
+                           other_index, parent_index, shared_length, child_index);
+
+                    if shared_length < child_length {
+                        self.split_edge(child_index, shared_length);
+                    }
+                    if shared_length < other_length {
+                        // The rest of the other edge must be merged in below
+                        // the (possibly newly split) existing node
+                        to_merge.push((child_index, other_index, consumed + shared_length));
+                        continue;
+                    }
+                    child_index
+                },
+                None => {
+                    debug!("No existing edge {} below {}, adding node for {}", edge, parent_index, other_index);
+                    self.add_node(parent_index, edge, other_start, other_length)
+                },
+            };
+            self.queue_merge_of_children(other_nodes, merged_index, other_index, &mut to_merge);
+        }
+    }
+
+    fn queue_merge_of_children(&mut self,
+                               other_nodes: &[SubTrie],
+                               node_index: usize,
+                               other_index: usize,
+                               to_merge: &mut Vec<(usize, usize, usize)>) {
+        let other_node = &other_nodes[other_index];
+        self.get_node_mut(node_index).leaf_children.extend(&other_node.leaf_children);
+        for other_child in other_node.children.values() {
+            to_merge.push((node_index, *other_child, 0));
+        }
+    }
+
+    /// Remove the text with the given name from the suffix trie.
+    /// The characters of the text are kept in storage until
+    /// compact_str_storage is called.
+    pub fn remove_text(&mut self, text_name: &str) -> Result<(), io::Error> {
+        let removed_index = self.get_text_index(text_name)?;
+        info!("Removing text {} with index {}", text_name, removed_index);
+        self.texts.remove(removed_index);
+
+        for node in self.node_storage.iter_mut() {
+            node.leaf_children.retain(|leaf| leaf.text_index != removed_index);
+            for leaf in node.leaf_children.iter_mut() {
+                if leaf.text_index > removed_index {
+                    leaf.text_index -= 1;
+                }
+            }
+        }
+        self.rebuild_node_storage();
+        Ok(())
+    }
+
+    /// Replace the contents of the text with the given name. The new
+    /// version of the text is placed after all other texts.
+    pub fn replace_text(&mut self, text_name: &str, contents: &str) -> Result<(), io::Error> {
+        self.remove_text(text_name)?;
+        self.add_sentences_from_text(text_name, contents);
+        Ok(())
+    }
+
+    /// Drop any characters from storage that no longer belong to a text
+    /// e.g. after calling remove_text
+    pub fn compact_str_storage(&mut self) {
+        let mut str_storage = Vec::new();
+        for text in self.texts.iter_mut() {
+            let start = text.offset;
+            text.offset = str_storage.len();
+            str_storage.extend(self.str_storage.chars(start .. start + text.last_index));
+        }
+        info!("Compacted storage from {} to {} characters",
+              self.str_storage.len(),
+              str_storage.len());
+        self.str_storage = CharStorage::InMemory(str_storage);
+        self.rebuild_node_storage();
+    }
+
+    fn get_text_index(&self, text_name: &str) -> Result<usize, io::Error> {
+        match self.texts.iter().position(|text| text.name == text_name) {
+            Some(text_index) => Ok(text_index),
+            None => Err(Error::new(ErrorKind::NotFound,
+                                   format!("No text named {}", text_name))),
+        }
+    }
+
+    /// Rebuild the nodes of the trie, dropping any nodes which no longer
+    /// have leaf descendants and collapsing nodes which are left with
+    /// a single child and no leaves of their own.
+    /// The edges of all nodes are pointed at the text of one of their leaf
+    /// descendants, so that they don't depend on any removed text.
+    fn rebuild_node_storage(&mut self) {
+        let old_nodes = std::mem::replace(&mut self.node_storage,
+                                          vec![SubTrie::empty(0, 0, 0)]);
+
+        // Find a leaf below each node, working up from the bottom of the trie
+        let mut ordered: Vec<usize> = vec![];
+        let mut to_process: Vec<usize> = vec![0];
+        while let Some(index) = to_process.pop() {
+            ordered.push(index);
+            to_process.extend(old_nodes[index].children.values());
+        }
+        let mut representative_leaves: Vec<Option<Leaf>> = vec![None; old_nodes.len()];
+        for index in ordered.into_iter().rev() {
+            let node = &old_nodes[index];
+            representative_leaves[index] = node.leaf_children.first().cloned().or_else(|| {
+                node.children.values().filter_map(|child| representative_leaves[*child]).next()
+            });
+        }
+
+        // Each entry is (new parent node, length of string above the new
+        // parent, old node, length of collapsed edges above the old node)
+        let mut to_rebuild: Vec<(usize, usize, usize, usize)> = vec![];
+        self.get_node_mut(0).leaf_children = old_nodes[0].leaf_children.clone();
+        for child in old_nodes[0].children.values() {
+            to_rebuild.push((0, 0, *child, 0));
+        }
+
+        while let Some((parent_index, parent_depth, old_index, collapsed_length)) = to_rebuild.pop() {
+            let leaf = match representative_leaves[old_index] {
+                Some(leaf) => leaf,
+                // Nothing left below this node
+                None => continue,
+            };
+            let old_node = &old_nodes[old_index];
+            let edge_length = collapsed_length + old_node.edge_length;
+            let live_children: Vec<usize> = old_node.children.values()
+                .filter(|child| representative_leaves[**child].is_some())
+                .cloned()
+                .collect();
+
+            if old_node.leaf_children.is_empty() && live_children.len() == 1 {
+                debug!("Collapsing node {} into its only child", old_index);
+                to_rebuild.push((parent_index, parent_depth, live_children[0], edge_length));
+                continue;
+            }
+
+            let edge_start_index = self.texts[leaf.text_index].offset + leaf.index_in_str + parent_depth;
+            let edge = self.str_storage[edge_start_index];
+            let node_index = self.add_node(parent_index, edge, edge_start_index, edge_length);
+            self.get_node_mut(node_index).leaf_children = old_node.leaf_children.clone();
+            for child in live_children {
+                to_rebuild.push((node_index, parent_depth + edge_length, child, 0));
+            }
+        }
+        debug!("Rebuilt trie from {} nodes to {} nodes", old_nodes.len(), self.node_storage.len());
     }
 
     /// Add the suffixes of a string to the suffix trie
     fn add_string_suffixes(&mut self,
                            string: &str,
                            start_index: usize,
+                           start_byte: usize,
                            text_index: usize) -> usize{
         let mut num_chars = 0;
-        let ascii_string = deunicode::deunicode(string);
-        self.str_storage.extend(ascii_string.chars());
+        let (ascii_string, char_offsets) = self.options.normaliser.normalise_with_offsets(string);
+        self.str_storage.to_mut().extend(ascii_string.chars());
+        if let Some(sentence_start_indices) = self.texts[text_index].sentence_start_indices.as_mut() {
+            sentence_start_indices.push(start_index);
+        }
+        if let Some(byte_offsets) = self.texts[text_index].byte_offsets.as_mut() {
+            for (index, (byte_offset, byte_length)) in char_offsets.into_iter().enumerate() {
+                byte_offsets.push(start_index + index, start_byte + byte_offset, byte_length);
+            }
+        }
 
         for (index, c) in ascii_string.char_indices() {
             num_chars += 1;
@@ -529,52 +1016,6 @@ impl SuffixTrie {
         child_index
     }
 
-    fn consume_all_shared_length(&self,
-                                 parent_index: usize,
-                                 string_iterator: &mut Chars) -> EdgeMatch {
-        let ancestor = self.get_node(parent_index);
-        let ancestor_start = ancestor.edge_start_index;
-        let ancestor_length = ancestor.edge_length;
-
-        let mut edge_match = EdgeMatch {
-            overlap_type: EdgeMatchKind::WholeMatch,
-            shared_length: ancestor_length,
-        };
-
-        // Run through character by character until we find the place
-        // where these strings diverge
-        // Start at the second character of the existing edge, and the next
-        // character of our edge
-        let mut index_in_edge = 1;
-        let mut edges_agree = true;
-        while index_in_edge < ancestor_length && edges_agree {
-            // Get next character of our string and compare to next
-            // character of existing edge
-            if let Some(c) = string_iterator.next() {
-                let index = ancestor_start + index_in_edge;
-                let ancestor_c = self.str_storage[index];
-                debug!("Next character of suffix is {}, next ancestor character is {}", c, ancestor_c);
-
-                if c != ancestor_c {
-                    edge_match = EdgeMatch {
-                        overlap_type: EdgeMatchKind::Diverge(c),
-                        shared_length: index_in_edge,
-                    };
-                    edges_agree = false
-                }
-            } else {
-                edge_match = EdgeMatch {
-                    overlap_type: EdgeMatchKind::EarlyStop,
-                    shared_length: index_in_edge,
-                };
-                edges_agree = false;
-            }
-            index_in_edge += 1;
-        }
-
-        edge_match
-    }
-
     fn insert_within_edge(&mut self,
                           parent_index: usize,
                           string_iterator: &mut Chars,
@@ -632,26 +1073,255 @@ impl SuffixTrie {
                                      max_errors: usize,
                                      ignored_characters: HashMap<char, bool>)
         -> Vec<Match> {
-            let mut matcher = SuffixTrieEditMatcher::new(max_errors,
-                                                         ignored_characters);
-            matcher.find_edit_distance_ignore(&self, pattern)
+            self.edit_distance_matches(pattern, max_errors, ignored_characters, false)
         }
 
+    /// Find matches of a pattern where SINGLE_WILDCARD ('?') matches any
+    /// character and MULTI_WILDCARD ('*') matches zero or more word characters
+    pub fn find_wildcard(&self, pattern: &str, max_errors: usize) -> Vec<Match> {
+        self.find_wildcard_ignore(pattern, max_errors, HashMap::new())
+    }
+
+    pub fn find_wildcard_ignore(&self,
+                                pattern: &str,
+                                max_errors: usize,
+                                ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.edit_distance_matches(pattern, max_errors, ignored_characters, true)
+    }
+
     /// Find all exact matches of the given pattern
     pub fn find_exact(&self, pattern: &str) -> Vec<Match> {
-        let mut parent: &SubTrie = self.get_node(0);
-        let ascii_pattern = deunicode::deunicode(pattern);
+        self.exact_matches(pattern)
+    }
+
+    /// Count the exact matches of the pattern in each text. This is much
+    /// cheaper than finding the matches when their positions aren't needed.
+    pub fn count_exact(&self, pattern: &str) -> Vec<usize> {
+        self.exact_counts(pattern)
+    }
+
+    /// The sentences, lines or texts satisfying a boolean combination of
+    /// search terms
+    pub fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+        boolean::boolean_search(self, query, options)
+    }
+
+    /// Spans from a match of one term of the query to a match of the other
+    /// within the distance allowed
+    pub fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+        proximity::find_near(self, query, options)
+    }
+
+    /// Match of the given length at a position in a text, with its lines,
+    /// columns and sentence, or None if it doesn't lie within the text
+    pub fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+        self.match_at(text_index, index_in_str, length)
+    }
+
+    fn len(&self) -> usize {
+        self.node_storage.len()
+    }
+
+    pub fn get_strings_of_match(&self,
+                                match_obj: &Match,
+                                context_lines: usize) -> (String, String, String) {
+        self.strings_of_match(match_obj, context_lines)
+    }
+
+    /// Get the text of the match with the given number of sentences
+    /// either side, or lines for indexes which don't record sentences
+    pub fn get_sentences_of_match(&self,
+                                  match_obj: &Match,
+                                  context_sentences: usize) -> (String, String, String) {
+        self.sentences_of_match(match_obj, context_sentences)
+    }
+
+    fn _unsafe_add_child_to_parent(&mut self,
+                                   edge: char,
+                                   parent_index: usize,
+                                   child_index: usize) {
+        let parent: &mut SubTrie = self.get_node_mut(parent_index);
+        // Shouldn't be called if the edge already exists
+        assert!(! parent.children.contains_key(&edge));
+
+        parent.children.insert(edge, child_index);
+    }
+
+    pub fn get_options(&self) -> &BuildOptions {
+        &self.options
+    }
+
+    pub fn get_text_info(&self) -> Vec<TextInfo> {
+        self.texts.iter().map(TextInfo::from).collect()
+    }
+
+    pub fn num_texts(&self) -> usize {
+        self.texts.len()
+    }
+
+    pub fn get_text_names(&self) -> Vec<String> {
+        let mut text_names: Vec<String> = vec![];
+        for text in self.texts.iter() {
+            text_names.push(text.name.to_string());
+        }
+        text_names
+    }
+}
+
+impl TrieStorage for SuffixTrie {
+    fn get_edge(&self, node_index: usize) -> (usize, usize) {
+        let node = self.get_node(node_index);
+        (node.edge_start_index, node.edge_length)
+    }
+
+    fn get_child(&self, node_index: usize, edge: char) -> Option<usize> {
+        self.get_node(node_index).get_child_index(edge).cloned()
+    }
+
+    fn get_children(&self, node_index: usize) -> Vec<(char, usize)> {
+        self.get_node(node_index).children.iter().map(|(edge, child)| (*edge, *child)).collect()
+    }
+
+    fn extend_with_leaves(&self, node_index: usize, leaves: &mut Vec<Leaf>) {
+        leaves.extend(&self.get_node(node_index).leaf_children);
+    }
+
+    fn get_char(&self, index: usize) -> char {
+        self.str_storage[index]
+    }
+
+    fn get_text(&self, text_index: usize) -> &Text {
+        &self.texts[text_index]
+    }
+
+    fn num_texts(&self) -> usize {
+        self.texts.len()
+    }
+
+    fn build_options(&self) -> &BuildOptions {
+        &self.options
+    }
+}
+
+/// Read access to the nodes and strings of a suffix trie. Searches are
+/// written in terms of this so that they can run on a SuffixTrie in memory
+/// or on a MappedSuffixTrie read in place from disk.
+trait TrieStorage: Sized {
+    /// Index in the string storage where the edge leading to the node starts,
+    /// and the length of the edge
+    fn get_edge(&self, node_index: usize) -> (usize, usize);
+    fn get_child(&self, node_index: usize, edge: char) -> Option<usize>;
+    fn get_children(&self, node_index: usize) -> Vec<(char, usize)>;
+    fn extend_with_leaves(&self, node_index: usize, leaves: &mut Vec<Leaf>);
+    fn get_char(&self, index: usize) -> char;
+    fn get_text(&self, text_index: usize) -> &Text;
+    fn num_texts(&self) -> usize;
+    fn build_options(&self) -> &BuildOptions;
+
+    fn consume_all_shared_length(&self,
+                                 parent_index: usize,
+                                 string_iterator: &mut Chars) -> EdgeMatch {
+        let (ancestor_start, ancestor_length) = self.get_edge(parent_index);
+
+        let mut edge_match = EdgeMatch {
+            overlap_type: EdgeMatchKind::WholeMatch,
+            shared_length: ancestor_length,
+        };
+
+        // Run through character by character until we find the place
+        // where these strings diverge
+        // Start at the second character of the existing edge, and the next
+        // character of our edge
+        let mut index_in_edge = 1;
+        let mut edges_agree = true;
+        while index_in_edge < ancestor_length && edges_agree {
+            // Get next character of our string and compare to next
+            // character of existing edge
+            if let Some(c) = string_iterator.next() {
+                let index = ancestor_start + index_in_edge;
+                let ancestor_c = self.get_char(index);
+                debug!("Next character of suffix is {}, next ancestor character is {}", c, ancestor_c);
+
+                if c != ancestor_c {
+                    edge_match = EdgeMatch {
+                        overlap_type: EdgeMatchKind::Diverge(c),
+                        shared_length: index_in_edge,
+                    };
+                    edges_agree = false
+                }
+            } else {
+                edge_match = EdgeMatch {
+                    overlap_type: EdgeMatchKind::EarlyStop,
+                    shared_length: index_in_edge,
+                };
+                edges_agree = false;
+            }
+            index_in_edge += 1;
+        }
+
+        edge_match
+    }
+
+    fn edit_distance_matches(&self,
+                             pattern: &str,
+                             max_errors: usize,
+                             ignored_characters: HashMap<char, bool>,
+                             wildcards: bool) -> Vec<Match> {
+        let mut matcher = SuffixTrieEditMatcher::new(max_errors,
+                                                     ignored_characters,
+                                                     wildcards);
+        matcher.find_edit_distance_ignore(self, pattern)
+    }
+
+    fn exact_matches(&self, pattern: &str) -> Vec<Match> {
+        let ascii_pattern = self.build_options().normaliser.normalise(pattern);
+        let mut matches = Vec::new();
+        if let Some(parent_index) = self.exact_match_node(&ascii_pattern) {
+            let leaves = self.get_all_leaf_descendants(parent_index);
+            info!("Found {} leaves below parent {}",
+                  leaves.len(),
+                  parent_index);
+            matches = self.match_array_from_leaves(leaves, ascii_pattern.len(), 0);
+            matches.sort();
+        }
+        info!("Found {} matches", matches.len());
+        matches
+    }
+
+    /// Count the exact matches of the pattern in each text, without
+    /// working out where they are
+    fn exact_counts(&self, pattern: &str) -> Vec<usize> {
+        let ascii_pattern = self.build_options().normaliser.normalise(pattern);
+        let mut counts = vec![0; self.num_texts()];
+        if let Some(parent_index) = self.exact_match_node(&ascii_pattern) {
+            let mut leaves = Vec::new();
+            let mut to_process: Vec<usize> = vec![parent_index];
+            while let Some(index) = to_process.pop() {
+                self.extend_with_leaves(index, &mut leaves);
+                for leaf in leaves.drain(..) {
+                    counts[leaf.text_index] += 1;
+                }
+                to_process.extend(self.get_children(index).iter().map(|(_, child)| *child));
+            }
+        }
+        counts
+    }
+
+    /// Find the node below which every leaf is an exact match of the
+    /// normalised pattern, if there are any matches
+    fn exact_match_node(&self, ascii_pattern: &str) -> Option<usize> {
+        let mut parent_index = 0;
         let mut string_iterator = ascii_pattern.chars();
 
         let mut found_mismatch = false;
         while let Some(c) = &string_iterator.next() {
-            if let Some(child_index) = parent.get_child_index(*c) {
-                let edge_match = self.consume_all_shared_length(*child_index,
+            if let Some(child_index) = self.get_child(parent_index, *c) {
+                let edge_match = self.consume_all_shared_length(child_index,
                                                                 &mut string_iterator);
                 match edge_match.overlap_type {
                     EdgeMatchKind::WholeMatch =>  {
                         // Continue iterating
-                        parent = self.get_node(*child_index);
+                        parent_index = child_index;
                     },
                     EdgeMatchKind::Diverge(_) => {
                         found_mismatch = true;
@@ -663,8 +1333,8 @@ impl SuffixTrie {
                         // match).
                         // Set up parent node, but since've we're out of characters
                         // we shouldn't end up iterating more
-                        parent = self.get_node(*child_index);
-                        assert!(!&string_iterator.next().is_some())
+                        parent_index = child_index;
+                        assert!(string_iterator.next().is_none())
                     }
                 }
             } else {
@@ -674,34 +1344,52 @@ impl SuffixTrie {
             }
         }
 
-        let mut matches = Vec::new();
-        if !found_mismatch {
-            let leaves = self.get_all_leaf_descendants(parent.node_index);
-            info!("Found {} leaves below parent {}",
-                  leaves.len(),
-                  parent.node_index);
-            matches = self.match_array_from_leaves(leaves, ascii_pattern.len(), 0);
-            matches.sort();
+        if found_mismatch {
+            None
+        } else {
+            Some(parent_index)
         }
-        info!("Found {} matches", matches.len());
-        matches
     }
 
-    fn len(&self) -> usize {
-        self.node_storage.len()
+    /// Visit every node after its children, calling visit with the node,
+    /// the length of the string ending at it and the values returned for
+    /// its children, in no particular order. Returns the value for the root.
+    fn fold_post_order<V, F>(&self, mut visit: F) -> V
+        where F: FnMut(usize, usize, Vec<V>) -> V {
+        // Values of the visited nodes whose parents haven't been visited yet
+        let mut values: Vec<V> = vec![];
+        // Nodes are visited twice: once to queue their children and again,
+        // with their number of children, after their children have been
+        // visited
+        let mut to_process: Vec<(usize, usize, Option<usize>)> = vec![(0, 0, None)];
+        while let Some((node_index, depth, num_children)) = to_process.pop() {
+            match num_children {
+                None => {
+                    let children = self.get_children(node_index);
+                    to_process.push((node_index, depth, Some(children.len())));
+                    for (_, child) in children {
+                        let (_, edge_length) = self.get_edge(child);
+                        to_process.push((child, depth + edge_length, None));
+                    }
+                },
+                Some(num_children) => {
+                    let child_values = values.split_off(values.len() - num_children);
+                    values.push(visit(node_index, depth, child_values));
+                },
+            }
+        }
+        values.pop().unwrap()
     }
 
     fn get_all_leaf_descendants(&self, node_index: usize) -> Vec<Leaf> {
         let mut leaves = Vec::new();
         let mut to_process: Vec<usize> = vec![node_index];
         while let Some(index) = to_process.pop() {
-            let node = self.get_node(index);
-            leaves.extend(&node.leaf_children);
-            let children: Vec<usize> = node.children.values().cloned().collect();
-            to_process.extend(&children);
+            self.extend_with_leaves(index, &mut leaves);
+            to_process.extend(self.get_children(index).iter().map(|(_, child)| *child));
         }
         leaves.sort();
-        leaves.clone()
+        leaves
     }
 
     fn match_array_from_leaves(&self,
@@ -711,16 +1399,23 @@ impl SuffixTrie {
         let mut matches = vec![];
 
         for leaf in leaves.iter() {
-            let text = &self.texts[leaf.text_index];
+            let text = self.get_text(leaf.text_index);
             let (start_line, end_line) = text.get_lines_of_substring(leaf.index_in_str,
                                                                      length);
+            let byte_range = text.byte_offsets.as_ref()
+                .map(|byte_offsets| byte_offsets.byte_range_of_substring(leaf.index_in_str, length));
             let match_obj = Match {
                 text_index: leaf.text_index,
                 index_in_str: leaf.index_in_str,
                 start_line,
                 end_line,
+                start_column: text.get_column_of_character(leaf.index_in_str, start_line),
+                end_column: text.get_column_of_character(leaf.index_in_str + length, end_line),
                 length,
                 errors,
+                sentence: text.get_sentence_of_character(leaf.index_in_str),
+                start_byte: byte_range.map(|(start_byte, _)| start_byte),
+                end_byte: byte_range.map(|(_, end_byte)| end_byte),
             };
             matches.push(match_obj);
         }
@@ -728,6 +1423,19 @@ impl SuffixTrie {
         matches
     }
 
+    /// Match of the given length at a position in a text, or None if it
+    /// doesn't lie within the text
+    fn match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+        if text_index >= self.num_texts() || length == 0 {
+            return None;
+        }
+        let end = index_in_str.checked_add(length)?;
+        if end > self.get_text(text_index).last_index {
+            return None;
+        }
+        self.match_array_from_leaves(vec![Leaf::new(index_in_str, text_index)], length, 0).pop()
+    }
+
     fn owned_lines_after(&self,
                          text: &Text,
                          line_index: usize,
@@ -766,13 +1474,13 @@ impl SuffixTrie {
                         length: usize) -> String {
         let start = index_in_str + text.offset;
         let end = start + length;
-        (self.str_storage[start .. end]).iter().cloned().collect::<String>()
+        (start .. end).map(|index| self.get_char(index)).collect::<String>()
     }
 
-    pub fn get_strings_of_match(&self,
-                                match_obj: &Match,
-                                context_lines: usize) -> (String, String, String) {
-        let text = &self.texts[match_obj.text_index];
+    fn strings_of_match(&self,
+                        match_obj: &Match,
+                        context_lines: usize) -> (String, String, String) {
+        let text = self.get_text(match_obj.text_index);
         let matching = self.owned_from_index(text,
                                              match_obj.index_in_str,
                                              match_obj.length);
@@ -787,23 +1495,30 @@ impl SuffixTrie {
         (before, matching, after)
     }
 
-    fn _unsafe_add_child_to_parent(&mut self,
-                                   edge: char,
-                                   parent_index: usize,
-                                   child_index: usize) {
-        let parent: &mut SubTrie = self.get_node_mut(parent_index);
-        // Shouldn't be called if the edge already exists
-        assert!(! parent.children.contains_key(&edge));
-
-        parent.children.insert(edge, child_index);
-    }
+    fn sentences_of_match(&self,
+                          match_obj: &Match,
+                          context_sentences: usize) -> (String, String, String) {
+        let text = self.get_text(match_obj.text_index);
+        let sentence_start_indices = match &text.sentence_start_indices {
+            Some(sentence_start_indices) => sentence_start_indices,
+            None => return self.strings_of_match(match_obj, context_sentences),
+        };
+        let match_end = match_obj.index_in_str + match_obj.length;
+        // Matches of searches stay within a sentence, but proximity matches
+        // and those rebuilt from a position may cross into later sentences
+        let first_sentence = text.get_sentence_of_character(match_obj.index_in_str).unwrap();
+        let last_char_index = if match_obj.length == 0 { match_obj.index_in_str } else { match_end - 1 };
+        let last_sentence = text.get_sentence_of_character(last_char_index).unwrap();
+        let start_char_index = sentence_start_indices[first_sentence.saturating_sub(context_sentences)];
+        let end_char_index = match sentence_start_indices.get(last_sentence + context_sentences + 1) {
+            Some(next_sentence_start) => cmp::max(*next_sentence_start, match_end),
+            None => cmp::max(text.last_index, match_end),
+        };
 
-    pub fn get_text_names(&self) -> Vec<String> {
-        let mut text_names: Vec<String> = vec![];
-        for text in self.texts.iter() {
-            text_names.push(text.name.to_string());
-        }
-        text_names
+        let before = self.owned_from_index(text, start_char_index, match_obj.index_in_str - start_char_index);
+        let matching = self.owned_from_index(text, match_obj.index_in_str, match_obj.length);
+        let after = self.owned_from_index(text, match_end, end_char_index - match_end);
+        (before, matching, after)
     }
 }
 
@@ -896,16 +1611,21 @@ struct SuffixTrieEditMatcher {
     matches_next_gen: WorkingMatchesSet,
     ignored_characters: HashMap<char, bool>,
     max_errors: usize,
+    // Whether SINGLE_WILDCARD and MULTI_WILDCARD in the pattern are wildcards
+    // or are to be matched literally
+    wildcards: bool,
 }
 
 impl SuffixTrieEditMatcher {
     fn new(max_errors: usize,
-           ignored_characters: HashMap<char, bool>) -> Self {
+           ignored_characters: HashMap<char, bool>,
+           wildcards: bool) -> Self {
         SuffixTrieEditMatcher {
             matches_this_gen: WorkingMatchesSet::only_root_node(),
             matches_next_gen: WorkingMatchesSet::empty(),
             ignored_characters,
             max_errors,
+            wildcards,
         }
     }
 
@@ -954,6 +1674,8 @@ impl SuffixTrieEditMatcher {
             // If the character is in the list of ignorable characters this doesn't add an error
         } else if self.ignored_characters.contains_key(pattern_char) {
             // If the character is in the list of ignorable characters this doesn't add an error
+        } else if self.wildcards && *pattern_char == SINGLE_WILDCARD {
+            // The single wildcard matches any character without adding an error
         } else {
             // Else this is a mismatch - increment the error counter
             errors_after_match += 1;
@@ -964,25 +1686,47 @@ impl SuffixTrieEditMatcher {
                                  existing_match.length + 1);
     }
 
+    /// Process the multi-character wildcard, which matches any run of zero
+    /// or more word characters without adding an error
+    fn add_after_multi_wildcard<T: TrieStorage>(&mut self, suffix_trie: &T) {
+        while let Some(parent_match) = self.matches_this_gen.next() {
+            // Match no characters: the wildcard is consumed without moving on
+            self.add_next_generation(parent_match.errors,
+                                     parent_match.starting_char,
+                                     parent_match.length);
+            // Match one more word character, adding to this generation so
+            // that the wildcard can continue to match further characters
+            let children = self.generation_after_char_dict(suffix_trie,
+                                                           parent_match.starting_char);
+            for (edge, child) in children.iter() {
+                if edge.is_alphanumeric() {
+                    self.add_this_generation(parent_match.errors,
+                                             *child,
+                                             parent_match.length + 1);
+                }
+            }
+        }
+    }
+
     fn go_to_next_generation(&mut self) {
         self.matches_this_gen = self.matches_next_gen.clone();
         self.matches_next_gen = WorkingMatchesSet::empty();
     }
 
-    fn generation_after_char_dict(&self,
-                                  suffix_trie: &SuffixTrie,
-                                  char_location: CharLocation) -> HashMap<char, CharLocation> {
-        let this_node = suffix_trie.get_node(char_location.node_index);
+    fn generation_after_char_dict<T: TrieStorage>(&self,
+                                                  suffix_trie: &T,
+                                                  char_location: CharLocation) -> HashMap<char, CharLocation> {
+        let (edge_start_index, edge_length) = suffix_trie.get_edge(char_location.node_index);
         let mut result = HashMap::new();
-        if char_location.index_in_edge + 1 >= this_node.edge_length {
+        if char_location.index_in_edge + 1 >= edge_length {
             // This char is at the end of the string of its node, so children
             // of the char are the children of the node itself
-            for (edge, child_index) in this_node.children.iter() {
+            for (edge, child_index) in suffix_trie.get_children(char_location.node_index) {
                 let child_location = CharLocation {
-                    node_index: *child_index,
+                    node_index: child_index,
                     index_in_edge: 0,
                 };
-                result.insert(*edge, child_location);
+                result.insert(edge, child_location);
             }
             debug!("Children of location {:?} are children of the node", char_location);
         } else {
@@ -993,7 +1737,7 @@ impl SuffixTrieEditMatcher {
                 node_index: char_location.node_index,
                 index_in_edge: new_edge_start_index,
             };
-            let edge = suffix_trie.str_storage[this_node.edge_start_index + new_edge_start_index].clone();
+            let edge = suffix_trie.get_char(edge_start_index + new_edge_start_index);
             result.insert(edge, child_location);
             debug!("Only child of location {:?} is the next character in the edge of the node", char_location);
         }
@@ -1001,32 +1745,36 @@ impl SuffixTrieEditMatcher {
         return result
     }
 
-    fn find_edit_distance_ignore(&mut self,
-                                 suffix_trie: &SuffixTrie,
-                                 pattern: &str)
+    fn find_edit_distance_ignore<T: TrieStorage>(&mut self,
+                                                 suffix_trie: &T,
+                                                 pattern: &str)
         -> Vec<Match> {
-            let ascii_pattern = deunicode::deunicode(pattern);
+            let ascii_pattern = suffix_trie.build_options().normaliser.normalise(pattern);
 
             // Keep track of matches and how many errors they have so far
             for c in ascii_pattern.chars() {
                 debug!("Matching char: {}", c);
                 debug!("Matching nodes: {:#?}", self);
-                while let Some(parent_match) = self.matches_this_gen.next() {
-                    debug!("Parent match: {:?}", parent_match);
-                    let children = self.generation_after_char_dict(suffix_trie,
-                                                                   parent_match.starting_char);
-                    for (edge, child) in children.iter() {
-                        debug!("Considering child {}", edge);
-                        self.add_after_mismatch(parent_match,
-                                                *child,
-                                                &c,
-                                                &edge);
-                        self.add_after_pattern_delete(parent_match);
-                        self.add_after_text_delete(parent_match,
-                                                   *child);
+                if self.wildcards && c == MULTI_WILDCARD {
+                    self.add_after_multi_wildcard(suffix_trie);
+                } else {
+                    while let Some(parent_match) = self.matches_this_gen.next() {
+                        debug!("Parent match: {:?}", parent_match);
+                        let children = self.generation_after_char_dict(suffix_trie,
+                                                                       parent_match.starting_char);
+                        for (edge, child) in children.iter() {
+                            debug!("Considering child {}", edge);
+                            self.add_after_mismatch(parent_match,
+                                                    *child,
+                                                    &c,
+                                                    &edge);
+                            self.add_after_pattern_delete(parent_match);
+                            self.add_after_text_delete(parent_match,
+                                                       *child);
+                        }
+                        debug!("Left this gen {:#?}", self.matches_this_gen);
+                        debug!("Left next gen: {:#?}", self.matches_next_gen);
                     }
-                    debug!("Left this gen {:#?}", self.matches_this_gen);
-                    debug!("Left next gen: {:#?}", self.matches_next_gen);
                 }
                 if self.matches_next_gen.is_empty() {
                     // There are no partial matches
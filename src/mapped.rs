@@ -0,0 +1,320 @@
+//! Flat on-disk layout for suffix tries which can be memory-mapped and
+//! searched in place, so that opening even a large index is almost instant.
+//!
+//! The file is laid out as
+//!
+//! | magic number | version | section sizes | chars | nodes | children | leaves | texts | options |
+//!
+//! All integers are little-endian. The chars, nodes, children and leaves
+//! sections are arrays of fixed-size records so they can be indexed
+//! directly. The children of each node are stored contiguously, sorted by
+//! the first character of their edge. Only the (small) texts and options
+//! sections are deserialised when the index is opened.
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter,Write};
+
+use log::info;
+use memmap2::Mmap;
+
+use crate::{boolean,proximity,BooleanHit,BooleanOptions,BooleanQuery,BuildOptions,Leaf,Match,ProximityOptions,ProximityQuery,SearchIndex,SuffixTrie,Text,TextInfo,TrieStorage};
+use crate::format::{invalid_data,LegacyTextV2,LegacyTextV3};
+
+pub(crate) const MAGIC: &[u8; 8] = b"SPYGLMAP";
+
+/// Version of the memory-mapped layout written by this version of the library.
+/// Version 1 didn't record the byte offsets of characters in the original
+/// texts and versions 1 and 2 didn't record sentence starts, but they can
+/// still be opened.
+pub const MAPPED_FORMAT_VERSION: u32 = 3;
+
+// Magic number, version, then the number of chars, nodes, children and
+// leaves and the length in bytes of the texts and options sections
+const HEADER_SIZE: usize = 8 + 4 + 6 * 8;
+// Each character as a u32
+const CHAR_SIZE: usize = 4;
+// Edge start, edge length, first child, number of children, first leaf,
+// number of leaves
+const NODE_SIZE: usize = 6 * 8;
+// Edge character as a u32, then the index of the child node
+const CHILD_SIZE: usize = 4 + 8;
+// Text index, index in text
+const LEAF_SIZE: usize = 2 * 8;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset .. offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(bytes[offset .. offset + 8].try_into().unwrap()) as usize
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: usize) -> Result<(), io::Error> {
+    writer.write_all(&(value as u64).to_le_bytes())
+}
+
+/// Suffix trie searched in place in a memory-mapped file written by
+/// SuffixTrie::save_mapped
+pub struct MappedSuffixTrie {
+    mmap: Mmap,
+    chars_start: usize,
+    nodes_start: usize,
+    children_start: usize,
+    leaves_start: usize,
+    texts: Vec<Text>,
+    options: BuildOptions,
+}
+
+impl SuffixTrie {
+    /// Save the suffix trie in the flat layout used by MappedSuffixTrie
+    pub fn save_mapped(&self, path: &str) -> Result<(), io::Error> {
+        let texts = bincode::serialize(&self.texts).map_err(invalid_data)?;
+        let options = bincode::serialize(&self.options).map_err(invalid_data)?;
+        let num_children: usize = self.node_storage.iter().map(|node| node.children.len()).sum();
+        let num_leaves: usize = self.node_storage.iter().map(|node| node.leaf_children.len()).sum();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&MAPPED_FORMAT_VERSION.to_le_bytes())?;
+        for size in &[self.str_storage.len(), self.node_storage.len(), num_children,
+                      num_leaves, texts.len(), options.len()] {
+            write_u64(&mut writer, *size)?;
+        }
+
+        for c in self.str_storage.iter() {
+            writer.write_all(&(c as u32).to_le_bytes())?;
+        }
+
+        let mut first_child = 0;
+        let mut first_leaf = 0;
+        for node in self.node_storage.iter() {
+            write_u64(&mut writer, node.edge_start_index)?;
+            write_u64(&mut writer, node.edge_length)?;
+            write_u64(&mut writer, first_child)?;
+            write_u64(&mut writer, node.children.len())?;
+            write_u64(&mut writer, first_leaf)?;
+            write_u64(&mut writer, node.leaf_children.len())?;
+            first_child += node.children.len();
+            first_leaf += node.leaf_children.len();
+        }
+
+        for node in self.node_storage.iter() {
+            let mut children: Vec<(&char, &usize)> = node.children.iter().collect();
+            children.sort();
+            for (edge, child_index) in children {
+                writer.write_all(&(*edge as u32).to_le_bytes())?;
+                write_u64(&mut writer, *child_index)?;
+            }
+        }
+
+        for node in self.node_storage.iter() {
+            for leaf in node.leaf_children.iter() {
+                write_u64(&mut writer, leaf.text_index)?;
+                write_u64(&mut writer, leaf.index_in_str)?;
+            }
+        }
+
+        writer.write_all(&texts)?;
+        writer.write_all(&options)?;
+        writer.flush()
+    }
+}
+
+impl MappedSuffixTrie {
+    /// Open an index written by SuffixTrie::save_mapped.
+    /// The file must not be modified while it is open.
+    pub fn open(path: &str) -> Result<MappedSuffixTrie, io::Error> {
+        info!("Mapping index from {}", path);
+        let file = File::open(path)?;
+        // Safety: the map is only read from, and callers are required not
+        // to modify the file while it is mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_SIZE || &mmap[0 .. 8] != MAGIC {
+            return Err(invalid_data("Not a memory-mapped spyglass index: missing magic number"));
+        }
+        let version = read_u32(&mmap, 8);
+        if version == 0 || version > MAPPED_FORMAT_VERSION {
+            return Err(invalid_data(format!("Unsupported memory-mapped index version {} (newest supported is {})",
+                                            version, MAPPED_FORMAT_VERSION)));
+        }
+        let sizes: Vec<usize> = (0..6).map(|i| read_u64(&mmap, 12 + 8 * i)).collect();
+        let (num_chars, num_nodes, num_children, num_leaves, texts_length, options_length) =
+            (sizes[0], sizes[1], sizes[2], sizes[3], sizes[4], sizes[5]);
+
+        // The sizes are untrusted, so each section must be checked to fit
+        // in the file without overflowing
+        let corrupt = || invalid_data("Memory-mapped index is truncated or corrupt: sections don't fit in the file");
+        let section_end = |start: usize, count: usize, record_size: usize| {
+            count.checked_mul(record_size).and_then(|length| length.checked_add(start))
+                .filter(|end| *end <= mmap.len())
+                .ok_or_else(corrupt)
+        };
+        let chars_start = HEADER_SIZE;
+        let nodes_start = section_end(chars_start, num_chars, CHAR_SIZE)?;
+        let children_start = section_end(nodes_start, num_nodes, NODE_SIZE)?;
+        let leaves_start = section_end(children_start, num_children, CHILD_SIZE)?;
+        let texts_start = section_end(leaves_start, num_leaves, LEAF_SIZE)?;
+        let options_start = section_end(texts_start, texts_length, 1)?;
+        let end = section_end(options_start, options_length, 1)?;
+        if end != mmap.len() || num_nodes == 0 {
+            return Err(invalid_data(format!("Memory-mapped index is truncated or corrupt: expected {} bytes, found {}",
+                                            end, mmap.len())));
+        }
+
+        let texts_section = &mmap[texts_start .. options_start];
+        let texts: Vec<Text> = match version {
+            1 => {
+                let legacy: Vec<LegacyTextV2> = bincode::deserialize(texts_section).map_err(invalid_data)?;
+                legacy.into_iter().map(Text::from).collect()
+            },
+            2 => {
+                let legacy: Vec<LegacyTextV3> = bincode::deserialize(texts_section).map_err(invalid_data)?;
+                legacy.into_iter().map(Text::from).collect()
+            },
+            _ => bincode::deserialize(texts_section).map_err(invalid_data)?,
+        };
+        let options: BuildOptions = bincode::deserialize(&mmap[options_start ..]).map_err(invalid_data)?;
+        for text in texts.iter() {
+            if text.offset.checked_add(text.last_index).is_none_or(|text_end| text_end > num_chars) {
+                return Err(invalid_data(format!("Memory-mapped index is corrupt: text {} is outside the characters",
+                                                text.name)));
+            }
+        }
+
+        Ok(MappedSuffixTrie {
+            mmap,
+            chars_start,
+            nodes_start,
+            children_start,
+            leaves_start,
+            texts,
+            options,
+        })
+    }
+
+    fn node_field(&self, node_index: usize, field: usize) -> usize {
+        read_u64(&self.mmap, self.nodes_start + node_index * NODE_SIZE + field * 8)
+    }
+
+    fn child_record(&self, child_number: usize) -> (char, usize) {
+        let offset = self.children_start + child_number * CHILD_SIZE;
+        let edge = std::char::from_u32(read_u32(&self.mmap, offset)).expect("Corrupt index: invalid character");
+        (edge, read_u64(&self.mmap, offset + 4))
+    }
+}
+
+impl TrieStorage for MappedSuffixTrie {
+    fn get_edge(&self, node_index: usize) -> (usize, usize) {
+        (self.node_field(node_index, 0), self.node_field(node_index, 1))
+    }
+
+    fn get_child(&self, node_index: usize, edge: char) -> Option<usize> {
+        // Children are sorted by edge, so binary search for this one
+        let mut lower = self.node_field(node_index, 2);
+        let mut upper = lower + self.node_field(node_index, 3);
+        while lower < upper {
+            let middle = lower + (upper - lower) / 2;
+            let (middle_edge, child_index) = self.child_record(middle);
+            if middle_edge == edge {
+                return Some(child_index);
+            } else if middle_edge < edge {
+                lower = middle + 1;
+            } else {
+                upper = middle;
+            }
+        }
+        None
+    }
+
+    fn get_children(&self, node_index: usize) -> Vec<(char, usize)> {
+        let first_child = self.node_field(node_index, 2);
+        let num_children = self.node_field(node_index, 3);
+        (first_child .. first_child + num_children).map(|child| self.child_record(child)).collect()
+    }
+
+    fn extend_with_leaves(&self, node_index: usize, leaves: &mut Vec<Leaf>) {
+        let first_leaf = self.node_field(node_index, 4);
+        let num_leaves = self.node_field(node_index, 5);
+        for leaf_number in first_leaf .. first_leaf + num_leaves {
+            let offset = self.leaves_start + leaf_number * LEAF_SIZE;
+            leaves.push(Leaf::new(read_u64(&self.mmap, offset + 8),
+                                  read_u64(&self.mmap, offset)));
+        }
+    }
+
+    fn get_char(&self, index: usize) -> char {
+        let c = read_u32(&self.mmap, self.chars_start + index * CHAR_SIZE);
+        std::char::from_u32(c).expect("Corrupt index: invalid character")
+    }
+
+    fn get_text(&self, text_index: usize) -> &Text {
+        &self.texts[text_index]
+    }
+
+    fn num_texts(&self) -> usize {
+        self.texts.len()
+    }
+
+    fn build_options(&self) -> &BuildOptions {
+        &self.options
+    }
+}
+
+impl SearchIndex for MappedSuffixTrie {
+    fn find_exact(&self, pattern: &str) -> Vec<Match> {
+        self.exact_matches(pattern)
+    }
+
+    fn count_exact(&self, pattern: &str) -> Vec<usize> {
+        self.exact_counts(pattern)
+    }
+
+    fn find_edit_distance_ignore(&self,
+                                 pattern: &str,
+                                 max_errors: usize,
+                                 ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.edit_distance_matches(pattern, max_errors, ignored_characters, false)
+    }
+
+    fn find_wildcard_ignore(&self,
+                            pattern: &str,
+                            max_errors: usize,
+                            ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.edit_distance_matches(pattern, max_errors, ignored_characters, true)
+    }
+
+    fn get_strings_of_match(&self,
+                            match_obj: &Match,
+                            context_lines: usize) -> (String, String, String) {
+        self.strings_of_match(match_obj, context_lines)
+    }
+
+    fn get_sentences_of_match(&self,
+                              match_obj: &Match,
+                              context_sentences: usize) -> (String, String, String) {
+        self.sentences_of_match(match_obj, context_sentences)
+    }
+
+    fn get_text_info(&self) -> Vec<TextInfo> {
+        self.texts.iter().map(TextInfo::from).collect()
+    }
+
+    fn get_options(&self) -> &BuildOptions {
+        &self.options
+    }
+
+    fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+        self.match_at(text_index, index_in_str, length)
+    }
+
+    fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+        boolean::boolean_search(self, query, options)
+    }
+
+    fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+        proximity::find_near(self, query, options)
+    }
+}
@@ -0,0 +1,236 @@
+//! Frequency tables of the character n-grams and word n-grams of the texts
+//! in a suffix trie.
+//!
+//! Each n-gram is found by walking down the trie until n characters or n
+//! words have been read, and is counted by the leaves below that point, so
+//! each occurrence is never looked at on its own. Words are separated by
+//! whitespace, and word n-grams are shown with a single space between the
+//! words whatever whitespace separated them in the text. Like matches,
+//! n-grams never cross sentence boundaries.
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::repeats::left_char;
+use crate::{Leaf,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SuffixTrie,TrieAnalysis};
+
+    fn counts(table: &NgramTable) -> Vec<(&str, usize)> {
+        table.counts.iter().map(|count| (count.ngram.as_str(), count.count)).collect()
+    }
+
+    fn options(unit: NgramUnit, n: usize) -> NgramOptions {
+        NgramOptions { unit, n, ..NgramOptions::default() }
+    }
+
+    #[test]
+    fn character_ngrams() {
+        let trie = SuffixTrie::new("the cat sat on the mat");
+        let table = trie.ngram_frequencies(&NgramOptions { min_count: 2, ..options(NgramUnit::Character, 2) });
+        assert_eq!(counts(&table), vec![("at", 3), ("e ", 2), ("he", 2), ("t ", 2), ("th", 2)]);
+        let table = trie.ngram_frequencies(&NgramOptions { top_k: Some(1), ..options(NgramUnit::Character, 3) });
+        assert_eq!(counts(&table), vec![("at ", 2)]);
+    }
+
+    #[test]
+    fn word_ngrams() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "bathe the  cat\nthe cat<<STOP>>the");
+        let table = trie.ngram_frequencies(&options(NgramUnit::Word, 1));
+        assert_eq!(counts(&table), vec![("the", 3), ("cat", 2), ("bathe", 1)]);
+        let table = trie.ngram_frequencies(&options(NgramUnit::Word, 2));
+        assert_eq!(counts(&table), vec![("the cat", 2), ("bathe the", 1), ("cat the", 1)]);
+        let table = trie.ngram_frequencies(&options(NgramUnit::Word, 5));
+        assert_eq!(counts(&table), vec![("bathe the cat the cat", 1)]);
+        assert!(trie.ngram_frequencies(&options(NgramUnit::Word, 6)).counts.is_empty());
+    }
+
+    #[test]
+    fn csv() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "a b a");
+        trie.add_sentences_from_text("second", "b");
+        let tables = trie.ngram_frequencies_per_text(&options(NgramUnit::Word, 1));
+        let mut csv = vec![];
+        write_ngram_csv(&mut csv, &tables).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "text_name,ngram,count\nfirst,a,2\nfirst,b,1\nsecond,b,1\n");
+    }
+}
+
+/// Whether n-grams are made of characters or of words
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum NgramUnit {
+    Character,
+    Word,
+}
+
+/// Which n-grams to count, and which to keep in a table
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct NgramOptions {
+    pub unit: NgramUnit,
+    pub n: usize,
+    // N-grams occurring fewer times than this are left out
+    pub min_count: usize,
+    // Keep only this many of the most frequent n-grams
+    pub top_k: Option<usize>,
+}
+
+impl Default for NgramOptions {
+    fn default() -> Self {
+        NgramOptions {
+            unit: NgramUnit::Character,
+            n: 3,
+            min_count: 1,
+            top_k: None,
+        }
+    }
+}
+
+/// Number of occurrences of an n-gram
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct NgramCount {
+    pub ngram: String,
+    pub count: usize,
+}
+
+/// N-grams with their counts, most frequent first, in a single text or
+/// across all the texts if text_name is None
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct NgramTable {
+    pub text_name: Option<String>,
+    pub counts: Vec<NgramCount>,
+}
+
+impl NgramTable {
+    fn new(text_name: Option<String>, counts: Vec<(String, usize)>, options: &NgramOptions) -> Self {
+        let mut counts: Vec<NgramCount> = counts.into_iter()
+            .filter(|(_, count)| *count > 0 && *count >= options.min_count)
+            .map(|(ngram, count)| NgramCount { ngram, count })
+            .collect();
+        counts.sort_by(|a, b| (b.count, &a.ngram).cmp(&(a.count, &b.ngram)));
+        if let Some(top_k) = options.top_k {
+            counts.truncate(top_k);
+        }
+        NgramTable { text_name, counts }
+    }
+}
+
+/// Write the tables as CSV, with a header row and a row for each n-gram of
+/// each table. The text name is left empty for tables of all the texts.
+pub fn write_ngram_csv<W: Write>(writer: &mut W, tables: &[NgramTable]) -> Result<(), io::Error> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(["text_name", "ngram", "count"])?;
+    for table in tables.iter() {
+        let text_name = table.text_name.as_deref().unwrap_or("");
+        for count in table.counts.iter() {
+            csv_writer.write_record([text_name, &count.ngram, &count.count.to_string()])?;
+        }
+    }
+    csv_writer.flush()
+}
+
+/// Words read so far along a path down the trie
+#[derive(Clone,Debug)]
+struct WordPath {
+    ngram: String,
+    // Number of words ended so far
+    words: usize,
+    in_space: bool,
+}
+
+/// Count the n-grams in each text, giving a count for each text for each
+/// n-gram
+fn count_ngrams<T: TrieStorage>(trie: &T, unit: NgramUnit, n: usize) -> HashMap<String, Vec<usize>> {
+    let mut counts: HashMap<String, Vec<usize>> = HashMap::new();
+    if n == 0 {
+        return counts;
+    }
+    let mut add_leaves = |ngram: String, leaves: Vec<Leaf>| {
+        let text_counts = counts.entry(ngram).or_insert_with(|| vec![0; trie.num_texts()]);
+        for leaf in leaves.iter() {
+            // Word n-grams must start at the start of a word
+            if unit == NgramUnit::Character || left_char(trie, leaf).is_none_or(char::is_whitespace) {
+                text_counts[leaf.text_index] += 1;
+            }
+        }
+    };
+
+    let mut to_process = vec![(0, WordPath { ngram: String::new(), words: 0, in_space: false })];
+    while let Some((node_index, path)) = to_process.pop() {
+        'children: for (_, child) in trie.get_children(node_index) {
+            let (edge_start, edge_length) = trie.get_edge(child);
+            let mut path = path.clone();
+            for c in (edge_start .. edge_start + edge_length).map(|index| trie.get_char(index)) {
+                match unit {
+                    NgramUnit::Character => {
+                        path.ngram.push(c);
+                        if path.ngram.chars().count() == n {
+                            add_leaves(path.ngram, trie.get_all_leaf_descendants(child));
+                            continue 'children;
+                        }
+                    },
+                    NgramUnit::Word if c.is_whitespace() => {
+                        if path.ngram.is_empty() {
+                            // N-grams start with a word
+                            continue 'children;
+                        }
+                        if !path.in_space {
+                            path.words += 1;
+                            path.in_space = true;
+                        }
+                        if path.words == n {
+                            // Every leaf below continues with whitespace
+                            // after the last word
+                            add_leaves(path.ngram, trie.get_all_leaf_descendants(child));
+                            continue 'children;
+                        }
+                    },
+                    NgramUnit::Word => {
+                        if path.in_space {
+                            path.ngram.push(' ');
+                            path.in_space = false;
+                        }
+                        path.ngram.push(c);
+                    },
+                }
+            }
+
+            // Leaves of the child itself end at the end of a sentence, which
+            // also ends a word
+            if unit == NgramUnit::Word && !path.in_space && path.words + 1 == n {
+                let mut own_leaves = vec![];
+                trie.extend_with_leaves(child, &mut own_leaves);
+                add_leaves(path.ngram.clone(), own_leaves);
+            }
+            to_process.push((child, path));
+        }
+    }
+    counts
+}
+
+/// Frequency table of the n-grams across all the texts
+pub(crate) fn ngram_frequencies<T: TrieStorage>(trie: &T, options: &NgramOptions) -> NgramTable {
+    let counts = count_ngrams(trie, options.unit, options.n).into_iter()
+        .map(|(ngram, text_counts)| (ngram, text_counts.iter().sum()))
+        .collect();
+    NgramTable::new(None, counts, options)
+}
+
+/// Frequency table of the n-grams of each text, with the filters applied to
+/// each text separately
+pub(crate) fn ngram_frequencies_per_text<T: TrieStorage>(trie: &T, options: &NgramOptions) -> Vec<NgramTable> {
+    let counts = count_ngrams(trie, options.unit, options.n);
+    (0 .. trie.num_texts()).map(|text_index| {
+        let text_counts = counts.iter()
+            .map(|(ngram, text_counts)| (ngram.clone(), text_counts[text_index]))
+            .collect();
+        NgramTable::new(Some(trie.get_text(text_index).name.clone()), text_counts, options)
+    }).collect()
+}
@@ -0,0 +1,86 @@
+//! Mapping from characters of the normalised text stored in a suffix trie
+//! back to their positions in the original text.
+//!
+//! Normalisation can turn one original character into several (e.g. 'Æ'
+//! becomes "AE") or none, multi-byte characters become single bytes and
+//! sentence splitters are removed. Most texts are mostly ASCII though, so
+//! rather than storing the position of every character only checkpoints
+//! are stored, at characters whose position can't be predicted from the
+//! character before.
+use serde::{Serialize,Deserialize};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_only_where_needed() {
+        // Original text "aÆb<<STOP>>c", normalised to "aAEbc"
+        let mut byte_offsets = ByteOffsets::default();
+        for (index, (byte_offset, byte_length)) in [(0, 1), (1, 2), (1, 2), (3, 1), (12, 1)].iter().enumerate() {
+            byte_offsets.push(index, *byte_offset, *byte_length);
+        }
+        assert_eq!(byte_offsets.checkpoints.len(), 3);
+
+        let ranges: Vec<(usize, usize)> = (0..5).map(|index| byte_offsets.byte_range(index)).collect();
+        assert_eq!(ranges, vec![(0, 1), (1, 3), (1, 3), (3, 4), (12, 13)]);
+        assert_eq!(byte_offsets.byte_range_of_substring(1, 2), (1, 3));
+        assert_eq!(byte_offsets.byte_range_of_substring(2, 3), (1, 13));
+        assert_eq!(byte_offsets.byte_range_of_substring(4, 0), (12, 12));
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize)]
+struct Checkpoint {
+    // Index of the character in the normalised text
+    index: usize,
+    // Position and length in bytes of the original character it came from
+    byte_offset: usize,
+    byte_length: usize,
+}
+
+/// Byte offsets in the original text of each character in the normalised
+/// text. Characters between checkpoints are assumed to be consecutive
+/// single-byte characters following on from the previous checkpoint.
+#[derive(Clone,Debug,Default,PartialEq,Eq,Serialize,Deserialize)]
+pub(crate) struct ByteOffsets {
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl ByteOffsets {
+    /// Record the position of the next character of the normalised text.
+    /// Characters must be added in order.
+    pub(crate) fn push(&mut self, index: usize, byte_offset: usize, byte_length: usize) {
+        if self.byte_range(index) != (byte_offset, byte_offset + byte_length) {
+            self.checkpoints.push(Checkpoint { index, byte_offset, byte_length });
+        }
+    }
+
+    /// Start and end byte offsets of the original character which the
+    /// character at this index of the normalised text came from
+    pub(crate) fn byte_range(&self, index: usize) -> (usize, usize) {
+        let following = self.checkpoints.partition_point(|checkpoint| checkpoint.index <= index);
+        let start = match following.checked_sub(1).map(|previous| self.checkpoints[previous]) {
+            None => index,
+            Some(checkpoint) if checkpoint.index == index => {
+                return (checkpoint.byte_offset, checkpoint.byte_offset + checkpoint.byte_length);
+            },
+            Some(checkpoint) => {
+                checkpoint.byte_offset + checkpoint.byte_length + (index - checkpoint.index - 1)
+            },
+        };
+        (start, start + 1)
+    }
+
+    /// Byte offsets in the original text of the start and end of the
+    /// substring of the normalised text with the given start and length
+    pub(crate) fn byte_range_of_substring(&self, start_index: usize, length: usize) -> (usize, usize) {
+        let (start_byte, _) = self.byte_range(start_index);
+        if length == 0 {
+            (start_byte, start_byte)
+        } else {
+            let (_, end_byte) = self.byte_range(start_index + length - 1);
+            (start_byte, end_byte)
+        }
+    }
+}
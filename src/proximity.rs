@@ -0,0 +1,268 @@
+//! Proximity searches, finding where two terms occur close to each other,
+//! such as `knight NEAR/5 lady` or `knight BEFORE/5 lady`.
+//!
+//! Each term is searched for on its own, and the sorted matches of the two
+//! terms in each text are joined, so the terms may be in different
+//! sentences but never in different texts. The distance between two matches
+//! is the number of characters, or of whole words, after the end of the
+//! first and before the start of the second, and the matches mustn't
+//! overlap. `NEAR/k` allows the terms in either order and `BEFORE/k` only
+//! with the first term first. Words are separated by whitespace, and only
+//! count if they contain a letter or digit.
+use std::collections::{BTreeMap,HashMap};
+use std::io;
+
+use serde::Serialize;
+
+use crate::boolean::{find_term,invalid_query,tokenise,Token};
+use crate::{Leaf,Match,Text,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SuffixTrie;
+
+    fn spans(trie: &SuffixTrie, query: &str, options: &ProximityOptions) -> Vec<String> {
+        let mut matches = trie.find_near(&ProximityQuery::parse(query).unwrap(), options);
+        matches.sort_by_key(|match_obj| (match_obj.text_index, match_obj.index_in_str));
+        matches.iter().map(|match_obj| trie.get_strings_of_match(match_obj, 0).1).collect()
+    }
+
+    #[test]
+    fn parse_queries() {
+        assert_eq!(ProximityQuery::parse("round table NEAR/5 \"holy grail\"").unwrap(),
+                   ProximityQuery { first: "round table".to_string(), second: "holy grail".to_string(), distance: 5, ordered: false });
+        assert_eq!(ProximityQuery::parse("knight BEFORE/0 lady").unwrap(),
+                   ProximityQuery { first: "knight".to_string(), second: "lady".to_string(), distance: 0, ordered: true });
+        for invalid in ["knight lady", "knight NEAR/ lady", "knight NEAR/x lady", "NEAR/5 lady", "knight NEAR/5",
+                        "a NEAR/1 b NEAR/1 c", "(knight) NEAR/5 lady", "knight AND lady"] {
+            assert!(ProximityQuery::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn words_between() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "the knight saw a lady - and the lady<<STOP>>saw the knight");
+        let options = ProximityOptions::default();
+        // The terms may be in different sentences
+        assert_eq!(spans(&trie, "knight NEAR/2 lady", &options), vec!["knight saw a lady", "ladysaw the knight"]);
+        assert_eq!(spans(&trie, "knight BEFORE/2 lady", &options), vec!["knight saw a lady"]);
+        assert_eq!(spans(&trie, "lady BEFORE/2 knight", &options), vec!["ladysaw the knight"]);
+        // The dash isn't a word
+        assert_eq!(spans(&trie, "lady NEAR/2 lady", &options), vec!["lady - and the lady"]);
+        assert!(spans(&trie, "knight NEAR/1 lady", &options).is_empty());
+        assert_eq!(spans(&trie, "saw NEAR/5 saw", &options), vec!["saw a lady - and the ladysaw"]);
+    }
+
+    #[test]
+    fn characters_between() {
+        let trie = SuffixTrie::new("knight and lady, ladyknight");
+        let options = ProximityOptions { unit: DistanceUnit::Characters, ..ProximityOptions::default() };
+        assert_eq!(spans(&trie, "knight NEAR/5 lady", &options), vec!["knight and lady", "ladyknight"]);
+        assert_eq!(spans(&trie, "knight BEFORE/4 lady", &options), Vec::<String>::new());
+
+        let options = ProximityOptions { max_errors: 1, ..options };
+        let matches = trie.find_near(&ProximityQuery::parse("knigt BEFORE/5 lady").unwrap(), &options);
+        // Errors are allowed in each term, and the best match comes first
+        let matches: Vec<(usize, usize, usize)> = matches.iter()
+            .map(|match_obj| (match_obj.index_in_str, match_obj.length, match_obj.errors))
+            .collect();
+        assert_eq!(matches, vec![(0, 15, 1), (0, 14, 2)]);
+    }
+}
+
+/// A search for two terms close to each other
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ProximityQuery {
+    pub first: String,
+    pub second: String,
+    // Most characters or words allowed between the matches of the terms
+    pub distance: usize,
+    // Whether the first term must come before the second
+    pub ordered: bool,
+}
+
+impl ProximityQuery {
+    /// Parse a query such as `knight NEAR/5 lady` or `knight BEFORE/5 lady`.
+    /// As in boolean queries, words with no operator between them form a
+    /// single phrase, and phrases may be quoted.
+    pub fn parse(query: &str) -> Result<ProximityQuery, io::Error> {
+        let mut terms = vec![String::new()];
+        let mut operator = None;
+        for token in tokenise(query)? {
+            let word = match token {
+                Token::Word(word) => word,
+                Token::Quoted(phrase) if terms.last().unwrap().is_empty() && !phrase.is_empty() => {
+                    terms.last_mut().unwrap().push_str(&phrase);
+                    continue;
+                },
+                Token::Quoted(_) => return Err(invalid_query("a quoted phrase must be a whole term")),
+                _ => return Err(invalid_query("AND, OR, NOT and parentheses can't be used with NEAR or BEFORE")),
+            };
+            let distance = word.strip_prefix("NEAR/").map(|distance| (distance, false))
+                .or_else(|| word.strip_prefix("BEFORE/").map(|distance| (distance, true)));
+            match distance {
+                Some((distance, ordered)) if operator.is_none() => {
+                    let distance = distance.parse::<usize>()
+                        .map_err(|_| invalid_query(&format!("{} needs a number of characters or words", word)))?;
+                    operator = Some((distance, ordered));
+                    terms.push(String::new());
+                },
+                Some(_) => return Err(invalid_query("only one NEAR or BEFORE is allowed")),
+                None => {
+                    let term = terms.last_mut().unwrap();
+                    if !term.is_empty() {
+                        term.push(' ');
+                    }
+                    term.push_str(&word);
+                },
+            }
+        }
+
+        match (operator, terms.as_slice()) {
+            (Some((distance, ordered)), [first, second]) if !first.is_empty() && !second.is_empty() =>
+                Ok(ProximityQuery { first: first.clone(), second: second.clone(), distance, ordered }),
+            (Some(_), _) => Err(invalid_query("expected a search term either side of NEAR or BEFORE")),
+            (None, _) => Err(invalid_query("expected NEAR/k or BEFORE/k between two search terms")),
+        }
+    }
+}
+
+/// What the distance between the terms is measured in
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize)]
+pub enum DistanceUnit {
+    Words,
+    Characters,
+}
+
+/// How to measure the distance between the terms, and how to search for
+/// each term
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ProximityOptions {
+    pub unit: DistanceUnit,
+    // Maximum errors in the matches of each term
+    pub max_errors: usize,
+    pub ignored_characters: HashMap<char, bool>,
+    pub wildcards: bool,
+}
+
+impl Default for ProximityOptions {
+    fn default() -> Self {
+        ProximityOptions {
+            unit: DistanceUnit::Words,
+            max_errors: 0,
+            ignored_characters: HashMap::new(),
+            wildcards: false,
+        }
+    }
+}
+
+/// Measures the distance from the end of one match to the start of a later
+/// one within a text
+enum Gap {
+    Characters,
+    // Starts of the words of the text, and the indices just after their ends
+    Words(Vec<usize>, Vec<usize>),
+}
+
+impl Gap {
+    fn new<T: TrieStorage>(trie: &T, text: &Text, unit: DistanceUnit) -> Self {
+        if unit == DistanceUnit::Characters {
+            return Gap::Characters;
+        }
+        let (mut starts, mut ends) = (vec![], vec![]);
+        let mut word_start = None;
+        let mut has_alphanumeric = false;
+        for index in 0 ..= text.last_index {
+            let c = if index < text.last_index { trie.get_char(text.offset + index) } else { ' ' };
+            // Sentences aren't always separated by whitespace in the index
+            let ends_word = c.is_whitespace() || text.starts_sentence(index);
+            if let (true, Some(start)) = (ends_word, word_start) {
+                if has_alphanumeric {
+                    starts.push(start);
+                    ends.push(index);
+                }
+                word_start = None;
+            }
+            match (c.is_whitespace(), word_start) {
+                (false, None) => {
+                    word_start = Some(index);
+                    has_alphanumeric = c.is_alphanumeric();
+                },
+                (false, Some(_)) => has_alphanumeric |= c.is_alphanumeric(),
+                (true, _) => {},
+            }
+        }
+        Gap::Words(starts, ends)
+    }
+
+    fn between(&self, first_end: usize, second_start: usize) -> usize {
+        match self {
+            Gap::Characters => second_start - first_end,
+            // Words are in order and don't overlap, so those starting after
+            // the first end and ending before the second start are a run
+            Gap::Words(starts, ends) => ends.partition_point(|end| *end <= second_start)
+                .saturating_sub(starts.partition_point(|start| *start < first_end)),
+        }
+    }
+}
+
+/// Add a span for each match of first followed within the distance by a
+/// match of second. Both must be sorted by position.
+fn join(first: &[Match], second: &[Match], distance: usize, gap: &Gap, spans: &mut Vec<(usize, usize, usize)>) {
+    for a in first.iter() {
+        let a_end = a.index_in_str + a.length;
+        let after = second.partition_point(|b| b.index_in_str < a_end);
+        // Later matches can only be further away
+        for b in second[after ..].iter().take_while(|b| gap.between(a_end, b.index_in_str) <= distance) {
+            spans.push((a.index_in_str, b.index_in_str + b.length - a.index_in_str, a.errors + b.errors));
+        }
+    }
+}
+
+/// Matches of each text, sorted by position
+fn matches_by_text(matches: Vec<Match>) -> BTreeMap<usize, Vec<Match>> {
+    let mut by_text: BTreeMap<usize, Vec<Match>> = BTreeMap::new();
+    for match_obj in matches {
+        by_text.entry(match_obj.text_index).or_default().push(match_obj);
+    }
+    for matches in by_text.values_mut() {
+        matches.sort_by_key(|match_obj| (match_obj.index_in_str, match_obj.length));
+    }
+    by_text
+}
+
+/// Spans from the start of a match of one term to the end of a nearby match
+/// of the other, with the total errors of the two matches
+pub(crate) fn find_near<T: TrieStorage>(trie: &T, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+    let find = |pattern: &str| {
+        matches_by_text(find_term(trie, pattern, options.max_errors, &options.ignored_characters, options.wildcards))
+    };
+    let first = find(&query.first);
+    let second = find(&query.second);
+
+    let mut matches = vec![];
+    for (text_index, first_matches) in first.iter() {
+        let second_matches = match second.get(text_index) {
+            Some(second_matches) => second_matches,
+            None => continue,
+        };
+        let gap = Gap::new(trie, trie.get_text(*text_index), options.unit);
+        let mut spans = vec![];
+        join(first_matches, second_matches, query.distance, &gap, &mut spans);
+        if !query.ordered {
+            join(second_matches, first_matches, query.distance, &gap, &mut spans);
+        }
+
+        // The same span may be found from several pairs of matches
+        spans.sort();
+        spans.dedup_by_key(|(start, length, _)| (*start, *length));
+        for (start, length, errors) in spans {
+            matches.extend(trie.match_array_from_leaves(vec![Leaf::new(start, *text_index)], length, errors));
+        }
+    }
+    matches.sort();
+    matches
+}
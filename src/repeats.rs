@@ -0,0 +1,198 @@
+//! Repeated substrings of the texts in a suffix trie, found by walking its
+//! internal nodes.
+//!
+//! Each node of the trie stands for the string spelled out by the edges
+//! from the root to the node, which occurs once for each leaf below it. A
+//! repeat is *maximal* if it can't be extended to the left or right without
+//! losing an occurrence, i.e. its occurrences are followed by at least two
+//! different characters and preceded by at least two different characters.
+//! A maximal repeat is *supermaximal* if it isn't contained in any other
+//! maximal repeat, which is the case when each of its occurrences is
+//! preceded by a different character and followed by a different character.
+//!
+//! Since matches never cross sentence boundaries, the start and end of a
+//! sentence count as a different character each time they occur.
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{Leaf,Match,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SuffixTrie,TrieAnalysis};
+
+    fn strings(repeats: &[Repeat]) -> Vec<(&str, usize)> {
+        repeats.iter().map(|repeat| (repeat.string.as_str(), repeat.count())).collect()
+    }
+
+    #[test]
+    fn repeats_of_simple_string() {
+        // The classic example: "abc" and "bcd" are supermaximal, "bc" is
+        // maximal but contained in both
+        let trie = SuffixTrie::new("xabcyabcwbcdzbcd");
+        assert_eq!(strings(&trie.longest_repeats()), vec![("abc", 2), ("bcd", 2)]);
+        assert_eq!(strings(&trie.maximal_repeats(2)), vec![("abc", 2), ("bcd", 2), ("bc", 4)]);
+        assert_eq!(strings(&trie.supermaximal_repeats(1)), vec![("abc", 2), ("bcd", 2)]);
+    }
+
+    #[test]
+    fn sentence_boundaries_are_unique() {
+        // "ab" is preceded by the start of the sentence each time, so it is
+        // left maximal even though it is always preceded by nothing
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "ab<<STOP>>ab");
+        assert_eq!(strings(&trie.maximal_repeats(1)), vec![("ab", 2)]);
+        assert_eq!(strings(&trie.supermaximal_repeats(1)), vec![("ab", 2)]);
+    }
+}
+
+/// A string occurring more than once in the texts, with its occurrences
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct Repeat {
+    pub string: String,
+    pub occurrences: Vec<Match>,
+}
+
+impl Repeat {
+    pub fn count(&self) -> usize {
+        self.occurrences.len()
+    }
+}
+
+/// Characters preceding the occurrences of a string
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum LeftContext {
+    // Every occurrence is preceded by this character
+    Same(char),
+    // Occurrences are preceded by different characters, or by the start of
+    // a sentence
+    Diverse,
+}
+
+impl LeftContext {
+    fn merge(self, other: Option<LeftContext>) -> LeftContext {
+        match other {
+            None => self,
+            Some(other) if other == self => self,
+            Some(_) => LeftContext::Diverse,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug)]
+struct NodeSummary {
+    num_leaves: usize,
+    // None if there are no leaves below the node
+    left: Option<LeftContext>,
+}
+
+/// A maximal repeat found while walking the trie, before its occurrences
+/// are looked up
+#[derive(Clone,Copy,Debug)]
+struct Candidate {
+    node_index: usize,
+    length: usize,
+    supermaximal: bool,
+}
+
+/// Character preceding the leaf, or None if it starts a sentence
+pub(crate) fn left_char<T: TrieStorage>(trie: &T, leaf: &Leaf) -> Option<char> {
+    let text = trie.get_text(leaf.text_index);
+    if text.starts_sentence(leaf.index_in_str) {
+        None
+    } else {
+        Some(trie.get_char(text.offset + leaf.index_in_str - 1))
+    }
+}
+
+/// Whether the leaves are each preceded by a different character
+fn distinct_left_chars<T: TrieStorage>(trie: &T, leaves: &[Leaf]) -> bool {
+    let mut seen = HashSet::new();
+    leaves.iter().all(|leaf| match left_char(trie, leaf) {
+        None => true,
+        Some(c) => seen.insert(c),
+    })
+}
+
+/// Walk the trie from the leaves upwards, finding the nodes which are
+/// maximal repeats of at least min_length characters
+fn maximal_candidates<T: TrieStorage>(trie: &T, min_length: usize) -> Vec<Candidate> {
+    let mut candidates = vec![];
+    trie.fold_post_order(|node_index, depth, child_summaries: Vec<NodeSummary>| {
+        let mut own_leaves = vec![];
+        trie.extend_with_leaves(node_index, &mut own_leaves);
+
+        let mut summary = NodeSummary {
+            num_leaves: own_leaves.len() + child_summaries.iter().map(|child| child.num_leaves).sum::<usize>(),
+            left: None,
+        };
+        for leaf in own_leaves.iter() {
+            let left = match left_char(trie, leaf) {
+                Some(c) => LeftContext::Same(c),
+                None => LeftContext::Diverse,
+            };
+            summary.left = Some(left.merge(summary.left));
+        }
+        for child in child_summaries.iter() {
+            summary.left = match (summary.left, child.left) {
+                (Some(left), other) => Some(left.merge(other)),
+                (None, other) => other,
+            };
+        }
+
+        // Each leaf of the node itself ends at the end of a sentence, so
+        // is followed by a different character to every other occurrence
+        let right_maximal = own_leaves.len() + child_summaries.len() >= 2;
+        let left_maximal = summary.left == Some(LeftContext::Diverse);
+        if node_index != 0 && depth >= min_length && summary.num_leaves >= 2 && right_maximal && left_maximal {
+            let supermaximal = child_summaries.iter().all(|child| child.num_leaves == 1)
+                && distinct_left_chars(trie, &trie.get_all_leaf_descendants(node_index));
+            candidates.push(Candidate { node_index, length: depth, supermaximal });
+        }
+        summary
+    });
+    candidates
+}
+
+/// Look up the occurrences of the candidates, returning the longest
+/// repeats first, then the most frequent
+fn repeats_of_candidates<T: TrieStorage>(trie: &T, candidates: &[Candidate]) -> Vec<Repeat> {
+    let mut repeats: Vec<Repeat> = candidates.iter().map(|candidate| {
+        let leaves = trie.get_all_leaf_descendants(candidate.node_index);
+        let mut occurrences = trie.match_array_from_leaves(leaves, candidate.length, 0);
+        occurrences.sort();
+        let first = &occurrences[0];
+        let string = trie.owned_from_index(trie.get_text(first.text_index), first.index_in_str, candidate.length);
+        Repeat { string, occurrences }
+    }).collect();
+    repeats.sort_by(|a, b| {
+        (b.string.chars().count(), b.count(), &a.string).cmp(&(a.string.chars().count(), a.count(), &b.string))
+    });
+    repeats
+}
+
+/// The longest strings occurring more than once
+pub(crate) fn longest_repeats<T: TrieStorage>(trie: &T) -> Vec<Repeat> {
+    let candidates = maximal_candidates(trie, 1);
+    let longest = candidates.iter().map(|candidate| candidate.length).max().unwrap_or(0);
+    let longest_candidates: Vec<Candidate> = candidates.into_iter()
+        .filter(|candidate| candidate.length == longest)
+        .collect();
+    repeats_of_candidates(trie, &longest_candidates)
+}
+
+/// Maximal repeats of at least min_length characters
+pub(crate) fn maximal_repeats<T: TrieStorage>(trie: &T, min_length: usize) -> Vec<Repeat> {
+    repeats_of_candidates(trie, &maximal_candidates(trie, min_length))
+}
+
+/// Supermaximal repeats of at least min_length characters
+pub(crate) fn supermaximal_repeats<T: TrieStorage>(trie: &T, min_length: usize) -> Vec<Repeat> {
+    let candidates: Vec<Candidate> = maximal_candidates(trie, min_length).into_iter()
+        .filter(|candidate| candidate.supermaximal)
+        .collect();
+    repeats_of_candidates(trie, &candidates)
+}
@@ -0,0 +1,654 @@
+//! Detection of text reuse: passages which one text shares with another.
+//!
+//! Shared material is found as *maximal pairs*: a position in each text
+//! where the same string starts, which can't be extended to the left or the
+//! right. These are found by walking the trie once, pairing up leaves from
+//! different branches below each node deep enough to be of interest.
+//! Shared substrings are then merged into passages, since borrowed passages
+//! are usually broken up by small changes and by sentence boundaries.
+//!
+//! Approximate reuse, such as paraphrase or changed spelling, is found in
+//! the same way as BLAST finds similar sequences: shared substrings of a
+//! fixed length are used as seeds, which are extended in both directions by
+//! aligning the texts either side of them for as long as the alignment
+//! scores well. The extended seeds are high-scoring segment pairs.
+//!
+//! Reuse across a whole corpus is summarised as a graph with an edge
+//! between each pair of texts sharing passages. It is found with a single
+//! walk of the trie, collecting the shared substrings of every pair of
+//! texts at once, rather than comparing each pair of texts separately.
+use std::collections::HashMap;
+use std::io;
+use std::io::{Error,ErrorKind,Write};
+
+use serde::Serialize;
+
+use crate::alignment::{count_errors,extend_alignment,extend_alignment_by,AlignedChar,Edit,ExtensionScoring};
+use crate::repeats::left_char;
+use crate::{Leaf,Match,TrieStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substring(start_a: usize, start_b: usize, length: usize) -> SharedSubstring {
+        SharedSubstring { start_a, start_b, length }
+    }
+
+    #[test]
+    fn merge_nearby_substrings() {
+        let substrings = vec![substring(0, 100, 10), substring(12, 111, 5), substring(50, 10, 8),
+                              substring(18, 118, 4), substring(40, 300, 6)];
+        let passages = merge_shared_substrings(substrings, 3);
+        let spans: Vec<(usize, usize, usize, usize, usize)> = passages.iter()
+            .map(|passage| (passage.start_a(), passage.end_a(), passage.start_b(), passage.end_b(), passage.shared_length()))
+            .collect();
+        assert_eq!(spans, vec![(0, 22, 100, 122, 19), (40, 46, 300, 306, 6), (50, 58, 10, 18, 8)]);
+    }
+
+    #[test]
+    fn repeats_within_a_text() {
+        let text = "abcdxabcdyabcdzbcd";
+        let trie = crate::SuffixTrie::new(text);
+        // Every pair of positions which can't be extended, earlier first
+        let chars: Vec<char> = text.chars().collect();
+        let mut expected = vec![];
+        for i in 0 .. chars.len() {
+            for j in i + 1 .. chars.len() {
+                let length = chars[i ..].iter().zip(chars[j ..].iter()).take_while(|(x, y)| x == y).count();
+                if length >= 3 && (i == 0 || chars[i - 1] != chars[j - 1]) {
+                    expected.push(substring(i, j, length));
+                }
+            }
+        }
+        assert_eq!(shared_substrings(&trie, 0, 0, 3), expected);
+        assert_eq!(expected.len(), 6);
+    }
+
+    #[test]
+    fn count_covered_characters_once() {
+        assert_eq!(covered_length(vec![(10, 20), (0, 5), (15, 25), (12, 14)].into_iter()), 20);
+        assert_eq!(covered_length(std::iter::empty()), 0);
+    }
+
+    fn graph() -> ReuseGraph {
+        ReuseGraph {
+            texts: vec!["a.txt".to_string(), "b \"quoted\".txt".to_string(), "c.txt".to_string()],
+            edges: vec![ReuseEdge { text_a: 0, text_b: 1, passages: 2, shared_length: 50 },
+                        ReuseEdge { text_a: 1, text_b: 2, passages: 1, shared_length: 20 }],
+        }
+    }
+
+    #[test]
+    fn reuse_matrices() {
+        let graph = graph();
+        assert_eq!(graph.passage_counts(), vec![vec![0, 2, 0], vec![2, 0, 1], vec![0, 1, 0]]);
+        assert_eq!(graph.shared_lengths(), vec![vec![0, 50, 0], vec![50, 0, 20], vec![0, 20, 0]]);
+    }
+
+    #[test]
+    fn reuse_exports() {
+        let mut csv = vec![];
+        graph().write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), concat!(
+            "text_a,text_b,passages,shared_length\n",
+            "a.txt,\"b \"\"quoted\"\".txt\",2,50\n",
+            "\"b \"\"quoted\"\".txt\",c.txt,1,20\n"));
+
+        let mut dot = vec![];
+        graph().write_dot(&mut dot).unwrap();
+        assert_eq!(String::from_utf8(dot).unwrap(), concat!(
+            "graph reuse {\n",
+            "    \"a.txt\";\n",
+            "    \"b \\\"quoted\\\".txt\";\n",
+            "    \"c.txt\";\n",
+            "    \"a.txt\" -- \"b \\\"quoted\\\".txt\" [passages=2, shared_length=50, label=\"2 / 50\"];\n",
+            "    \"b \\\"quoted\\\".txt\" -- \"c.txt\" [passages=1, shared_length=20, label=\"1 / 20\"];\n",
+            "}\n"));
+    }
+}
+
+/// A string starting at start_a in the first text and start_b in the
+/// second, counting characters of the normalised texts from 0
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Serialize)]
+pub struct SharedSubstring {
+    pub start_a: usize,
+    pub start_b: usize,
+    pub length: usize,
+}
+
+/// Shared substrings close enough together in both texts to be treated
+/// as a single borrowed passage, in order of their start in the first text
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct SharedSubstrings {
+    pub pieces: Vec<SharedSubstring>,
+}
+
+impl SharedSubstrings {
+    pub fn start_a(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.start_a).min().unwrap_or(0)
+    }
+
+    pub fn end_a(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.start_a + piece.length).max().unwrap_or(0)
+    }
+
+    pub fn start_b(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.start_b).min().unwrap_or(0)
+    }
+
+    pub fn end_b(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.start_b + piece.length).max().unwrap_or(0)
+    }
+
+    /// Number of characters of the first text covered by the pieces
+    pub fn shared_length(&self) -> usize {
+        let mut covered = 0;
+        let mut covered_to = 0;
+        for piece in self.pieces.iter() {
+            let start = piece.start_a.max(covered_to);
+            let end = piece.start_a + piece.length;
+            if end > start {
+                covered += end - start;
+                covered_to = end;
+            }
+        }
+        covered
+    }
+
+    /// Whether the piece continues the passage, starting no more than
+    /// max_gap characters after its end in both texts, without moving
+    /// further than max_gap off its diagonal
+    fn continued_by(&self, piece: &SharedSubstring, max_gap: usize) -> bool {
+        let last = self.pieces.last().unwrap();
+        let diagonal = |piece: &SharedSubstring| piece.start_a as isize - piece.start_b as isize;
+        piece.start_a >= last.start_a
+            && piece.start_b >= last.start_b
+            && piece.start_a <= self.end_a() + max_gap
+            && piece.start_b <= self.end_b() + max_gap
+            && (diagonal(piece) - diagonal(last)).unsigned_abs() <= max_gap
+    }
+}
+
+/// A passage of the first text which reuses a passage of the second, with
+/// the location of the passage in each text
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct SharedPassage {
+    pub location_a: Match,
+    pub location_b: Match,
+    // Number of characters of the passage in the first text which are part
+    // of a shared substring
+    pub shared_length: usize,
+    pub pieces: Vec<SharedSubstring>,
+}
+
+/// Merge shared substrings into passages, joining pieces separated by at
+/// most max_gap characters. Passages are returned in order of their start
+/// in the first text.
+pub fn merge_shared_substrings(mut substrings: Vec<SharedSubstring>, max_gap: usize) -> Vec<SharedSubstrings> {
+    substrings.sort();
+    let mut passages: Vec<SharedSubstrings> = vec![];
+    // Passages which could still be continued, as indices into passages
+    let mut open: Vec<usize> = vec![];
+    for piece in substrings {
+        open.retain(|passage| passages[*passage].end_a() + max_gap >= piece.start_a);
+        match open.iter().find(|passage| passages[**passage].continued_by(&piece, max_gap)) {
+            Some(passage) => passages[*passage].pieces.push(piece),
+            None => {
+                open.push(passages.len());
+                passages.push(SharedSubstrings { pieces: vec![piece] });
+            },
+        }
+    }
+    passages
+}
+
+/// Find the index of the text with the given name
+pub(crate) fn text_index_of<T: TrieStorage>(trie: &T, text_name: &str) -> Result<usize, io::Error> {
+    (0 .. trie.num_texts())
+        .find(|text_index| trie.get_text(*text_index).name == text_name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No text named {}", text_name)))
+}
+
+/// Leaves below a node, grouped by their text and then by the character
+/// before them, so that pairs which can't be reported are never formed
+#[derive(Default)]
+struct Branch {
+    texts: HashMap<usize, HashMap<Option<char>, Vec<Leaf>>>,
+    num_leaves: usize,
+}
+
+impl Branch {
+    fn add(&mut self, leaf: Leaf, left: Option<char>) {
+        self.texts.entry(leaf.text_index).or_default().entry(left).or_default().push(leaf);
+        self.num_leaves += 1;
+    }
+
+    /// Move the leaves of the other branch into this one
+    fn absorb(&mut self, other: Branch) {
+        for (text_index, by_left) in other.texts {
+            let ours = self.texts.entry(text_index).or_default();
+            for (left, mut leaves) in by_left {
+                ours.entry(left).or_default().append(&mut leaves);
+            }
+        }
+        self.num_leaves += other.num_leaves;
+    }
+
+    /// Call report with each pair of a leaf of this branch and a leaf of
+    /// the other in a wanted pair of texts, with different characters
+    /// before them, putting the leaf of the first text of the pair first
+    fn pair_with<W, R>(&self, other: &Branch, wanted: &W, report: &mut R)
+        where W: Fn(usize, usize) -> bool,
+              R: FnMut(&Leaf, &Leaf) {
+        for (text_index, by_left) in self.texts.iter() {
+            for (other_text_index, other_by_left) in other.texts.iter() {
+                let (forwards, backwards) = (wanted(*text_index, *other_text_index),
+                                             wanted(*other_text_index, *text_index));
+                if !forwards && !backwards {
+                    continue;
+                }
+                for (left, leaves) in by_left.iter() {
+                    for (other_left, other_leaves) in other_by_left.iter() {
+                        // The start of a sentence differs from every character
+                        if left.is_some() && left == other_left {
+                            continue;
+                        }
+                        for leaf in leaves.iter() {
+                            for other_leaf in other_leaves.iter() {
+                                // Within a text, the earlier leaf comes first
+                                let in_order = text_index != other_text_index
+                                    || leaf.index_in_str < other_leaf.index_in_str;
+                                if forwards && in_order {
+                                    report(leaf, other_leaf);
+                                } else if backwards && (text_index != other_text_index || !in_order) {
+                                    report(other_leaf, leaf);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Walk the trie once, calling report with each maximal pair of leaves
+/// whose shared string is at least min_length characters long, and the
+/// length of that string. Only leaves for which keep returns true are
+/// considered, and only pairs of leaves of texts for which wanted returns
+/// true are reported, in that order. A pair of leaves of the same text is
+/// reported once, with the earlier leaf first.
+pub(crate) fn for_each_maximal_pair<T, K, W, R>(trie: &T,
+                                               min_length: usize,
+                                               keep: K,
+                                               wanted: W,
+                                               mut report: R)
+    where T: TrieStorage,
+          K: Fn(&Leaf) -> bool,
+          W: Fn(usize, usize) -> bool,
+          R: FnMut(&Leaf, &Leaf, usize) {
+    // Each node gives the leaves kept below it, if it is deep enough to be
+    // of interest
+    trie.fold_post_order(|node_index, depth, child_branches: Vec<Option<Branch>>| {
+        if depth < min_length {
+            // Nodes above this are shallower, so can't have long enough
+            // pairs either
+            return None;
+        }
+        let mut branches: Vec<Branch> = child_branches.into_iter().flatten().collect();
+        // Leaves of the node itself end at the end of a sentence, so each
+        // is followed by a different character to every other leaf
+        let mut own_leaves = vec![];
+        trie.extend_with_leaves(node_index, &mut own_leaves);
+        for leaf in own_leaves.into_iter().filter(|leaf| keep(leaf)) {
+            let mut branch = Branch::default();
+            branch.add(leaf, left_char(trie, &leaf));
+            branches.push(branch);
+        }
+
+        // Pair each branch with those before it, gathering the leaves into
+        // the largest branch so that fewer leaves are moved on the way up
+        branches.sort_by_key(|branch| std::cmp::Reverse(branch.num_leaves));
+        let mut branches = branches.into_iter();
+        let mut leaves = branches.next()?;
+        for branch in branches {
+            branch.pair_with(&leaves, &wanted, &mut |leaf, other_leaf| report(leaf, other_leaf, depth));
+            leaves.absorb(branch);
+        }
+        Some(leaves)
+    });
+}
+
+/// Maximal shared substrings of at least min_length characters between
+/// two texts, in order of their start in the first text. If the texts are
+/// the same, each pair of positions is reported once, with the earlier
+/// position first.
+pub(crate) fn shared_substrings<T: TrieStorage>(trie: &T,
+                                                text_a: usize,
+                                                text_b: usize,
+                                                min_length: usize) -> Vec<SharedSubstring> {
+    let mut substrings = vec![];
+    for_each_maximal_pair(trie,
+                          min_length,
+                          |leaf| leaf.text_index == text_a || leaf.text_index == text_b,
+                          |leaf_text, other_leaf_text| leaf_text == text_a && other_leaf_text == text_b,
+                          |leaf, other_leaf, length| substrings.push(SharedSubstring {
+                              start_a: leaf.index_in_str,
+                              start_b: other_leaf.index_in_str,
+                              length,
+                          }));
+    substrings.sort();
+    substrings
+}
+
+/// Passages shared between two texts, made up of maximal shared substrings
+/// of at least min_length characters separated by at most max_gap
+/// characters
+pub(crate) fn shared_passages<T: TrieStorage>(trie: &T,
+                                              text_a: usize,
+                                              text_b: usize,
+                                              min_length: usize,
+                                              max_gap: usize) -> Vec<SharedPassage> {
+    let substrings = shared_substrings(trie, text_a, text_b, min_length);
+    merge_shared_substrings(substrings, max_gap).into_iter().map(|passage| {
+        let location = |text_index: usize, start: usize, end: usize| {
+            trie.match_array_from_leaves(vec![Leaf::new(start, text_index)], end - start, 0).remove(0)
+        };
+        SharedPassage {
+            location_a: location(text_a, passage.start_a(), passage.end_a()),
+            location_b: location(text_b, passage.start_b(), passage.end_b()),
+            shared_length: passage.shared_length(),
+            pieces: passage.pieces,
+        }
+    }).collect()
+}
+
+/// Options for finding approximate text reuse
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct SeedOptions {
+    // Length of the exact shared substrings used as seeds
+    pub seed_length: usize,
+    // Only pairs scoring at least this are reported
+    pub min_score: i32,
+    pub scoring: ExtensionScoring,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        SeedOptions {
+            seed_length: 12,
+            min_score: 30,
+            scoring: ExtensionScoring::default(),
+        }
+    }
+}
+
+/// Passages of two texts which align with a high score, with their
+/// alignment. The alignment is described as in extend_alignment, taking
+/// the characters of the first text for matches and mismatches.
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct HighScoringPair {
+    pub location_a: Match,
+    pub location_b: Match,
+    pub score: i32,
+    pub string_a: String,
+    pub string_b: String,
+    pub alignment: Vec<AlignedChar>,
+}
+
+impl HighScoringPair {
+    /// The two passages laid out one above the other in the style of BLAST,
+    /// with the gap character where a character has no counterpart
+    pub fn aligned_strings(&self, gap: char) -> (String, String) {
+        let mut chars_b = self.string_b.chars();
+        let mut aligned_a = String::new();
+        let mut aligned_b = String::new();
+        for aligned in self.alignment.iter() {
+            match aligned.edit {
+                Edit::Match | Edit::Mismatch => {
+                    aligned_a.push(aligned.c);
+                    aligned_b.extend(chars_b.next());
+                },
+                Edit::Insertion => {
+                    aligned_a.push(aligned.c);
+                    aligned_b.push(gap);
+                },
+                Edit::Deletion => {
+                    aligned_a.push(gap);
+                    aligned_b.extend(chars_b.next());
+                },
+            }
+        }
+        (aligned_a, aligned_b)
+    }
+}
+
+/// Extend each seed shared by the two texts into a high-scoring segment
+/// pair, returning the pairs scoring at least the minimum score, best first
+pub(crate) fn high_scoring_pairs<T: TrieStorage>(trie: &T,
+                                                 text_a: usize,
+                                                 text_b: usize,
+                                                 options: &SeedOptions) -> Vec<HighScoringPair> {
+    let chars_of = |text_index: usize| {
+        let text = trie.get_text(text_index);
+        trie.owned_from_index(text, 0, text.last_index).chars().collect::<Vec<char>>()
+    };
+    let (chars_a, chars_b) = (chars_of(text_a), chars_of(text_b));
+    let scoring = &options.scoring;
+
+    // Longer seeds are extended first, so that shorter seeds lying on the
+    // alignments they give don't have to be
+    let mut seeds = shared_substrings(trie, text_a, text_b, options.seed_length);
+    seeds.sort_by_key(|seed| std::cmp::Reverse(seed.length));
+
+    let mut pairs: Vec<(SharedSubstrings, i32, Vec<AlignedChar>)> = vec![];
+    // Indices of the pairs whose alignments pass through each diagonal,
+    // where the diagonal of a position is its offset in b less its offset in a
+    let mut pairs_on_diagonal: HashMap<isize, Vec<usize>> = HashMap::new();
+    for seed in seeds {
+        let diagonal = seed.start_b as isize - seed.start_a as isize;
+        let covered = pairs_on_diagonal.get(&diagonal).is_some_and(|on_diagonal| {
+            on_diagonal.iter().any(|pair_index| {
+                let span = &pairs[*pair_index].0;
+                seed.start_a >= span.start_a() && seed.start_a + seed.length <= span.end_a()
+            })
+        });
+        if covered {
+            continue;
+        }
+
+        let (end_a, end_b) = (seed.start_a + seed.length, seed.start_b + seed.length);
+        let right = extend_alignment(&chars_a[end_a ..], &chars_b[end_b ..], scoring);
+        // Extend to the left by reading the texts backwards from the seed
+        let left = extend_alignment_by(seed.start_a, |i| chars_a[seed.start_a - 1 - i],
+                                       seed.start_b, |j| chars_b[seed.start_b - 1 - j],
+                                       scoring);
+
+        let mut alignment: Vec<AlignedChar> = left.alignment.into_iter().rev().collect();
+        alignment.extend(chars_a[seed.start_a .. end_a].iter().map(|c| AlignedChar { edit: Edit::Match, c: *c }));
+        alignment.extend(right.alignment);
+        let score = seed.length as i32 * scoring.match_score + left.score + right.score;
+        // Record the extent of the pair as a single piece on each side
+        let span = SharedSubstrings {
+            pieces: vec![SharedSubstring { start_a: seed.start_a - left.length_a, start_b: seed.start_b - left.length_b, length: 0 },
+                         SharedSubstring { start_a: end_a + right.length_a, start_b: end_b + right.length_b, length: 0 }],
+        };
+        let (min_diagonal, max_diagonal) = diagonal_range(&span, &alignment);
+        for diagonal in min_diagonal ..= max_diagonal {
+            pairs_on_diagonal.entry(diagonal).or_default().push(pairs.len());
+        }
+        pairs.push((span, score, alignment));
+    }
+
+    let mut pairs: Vec<HighScoringPair> = pairs.into_iter()
+        .filter(|(_, score, _)| *score >= options.min_score)
+        .map(|(span, score, alignment)| {
+            let errors = count_errors(&alignment);
+            let location = |text_index: usize, start: usize, end: usize| {
+                trie.match_array_from_leaves(vec![Leaf::new(start, text_index)], end - start, errors).remove(0)
+            };
+            HighScoringPair {
+                location_a: location(text_a, span.start_a(), span.end_a()),
+                location_b: location(text_b, span.start_b(), span.end_b()),
+                score,
+                string_a: chars_a[span.start_a() .. span.end_a()].iter().collect(),
+                string_b: chars_b[span.start_b() .. span.end_b()].iter().collect(),
+                alignment,
+            }
+        })
+        .collect();
+    pairs.sort_by(|a, b| (b.score, a.location_a.index_in_str).cmp(&(a.score, b.location_a.index_in_str)));
+    pairs
+}
+
+/// Lowest and highest diagonals the alignment of a pair passes through
+fn diagonal_range(span: &SharedSubstrings, alignment: &[AlignedChar]) -> (isize, isize) {
+    let mut diagonal = span.start_b() as isize - span.start_a() as isize;
+    let (mut min_diagonal, mut max_diagonal) = (diagonal, diagonal);
+    for aligned in alignment {
+        match aligned.edit {
+            Edit::Match | Edit::Mismatch => continue,
+            Edit::Insertion => diagonal -= 1,
+            Edit::Deletion => diagonal += 1,
+        }
+        min_diagonal = min_diagonal.min(diagonal);
+        max_diagonal = max_diagonal.max(diagonal);
+    }
+    (min_diagonal, max_diagonal)
+}
+
+/// Passages shared by a pair of texts of an index
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct ReuseEdge {
+    // Indices of the texts, with text_a < text_b
+    pub text_a: usize,
+    pub text_b: usize,
+    // Number of passages, leaving out those lying within a longer passage
+    // in text_a, such as the other pairings of a repeated string
+    pub passages: usize,
+    // Number of characters of text_a which are part of a shared substring
+    // of a passage, counting each character once
+    pub shared_length: usize,
+}
+
+/// Which texts of an index share passages with which others
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct ReuseGraph {
+    pub texts: Vec<String>,
+    // Only pairs of texts sharing at least one passage have an edge, in
+    // order of their text indices
+    pub edges: Vec<ReuseEdge>,
+}
+
+impl ReuseGraph {
+    /// Matrix of the number of passages shared by each pair of texts
+    pub fn passage_counts(&self) -> Vec<Vec<usize>> {
+        self.matrix(|edge| edge.passages)
+    }
+
+    /// Matrix of the total length of the passages shared by each pair of
+    /// texts
+    pub fn shared_lengths(&self) -> Vec<Vec<usize>> {
+        self.matrix(|edge| edge.shared_length)
+    }
+
+    fn matrix<F: Fn(&ReuseEdge) -> usize>(&self, value: F) -> Vec<Vec<usize>> {
+        let mut matrix = vec![vec![0; self.texts.len()]; self.texts.len()];
+        for edge in self.edges.iter() {
+            matrix[edge.text_a][edge.text_b] = value(edge);
+            matrix[edge.text_b][edge.text_a] = value(edge);
+        }
+        matrix
+    }
+
+    /// Write the edges as CSV with a header row, one row for each pair of
+    /// texts sharing passages
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record(["text_a", "text_b", "passages", "shared_length"])?;
+        for edge in self.edges.iter() {
+            csv_writer.write_record([&self.texts[edge.text_a], &self.texts[edge.text_b],
+                                     &edge.passages.to_string(), &edge.shared_length.to_string()])?;
+        }
+        csv_writer.flush()
+    }
+
+    /// Write the graph in the GraphViz DOT language, with a node for every
+    /// text and an edge labelled with the passage count and shared length
+    /// for each pair of texts sharing passages
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        let quoted = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        writeln!(writer, "graph reuse {{")?;
+        for text in self.texts.iter() {
+            writeln!(writer, "    {};", quoted(text))?;
+        }
+        for edge in self.edges.iter() {
+            writeln!(writer, "    {} -- {} [passages={}, shared_length={}, label=\"{} / {}\"];",
+                     quoted(&self.texts[edge.text_a]), quoted(&self.texts[edge.text_b]),
+                     edge.passages, edge.shared_length, edge.passages, edge.shared_length)?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+/// Number of positions covered by at least one of the ranges
+fn covered_length<I: Iterator<Item = (usize, usize)>>(ranges: I) -> usize {
+    let mut ranges: Vec<(usize, usize)> = ranges.collect();
+    ranges.sort();
+    let mut covered = 0;
+    let mut covered_to = 0;
+    for (start, end) in ranges {
+        let start = start.max(covered_to);
+        if end > start {
+            covered += end - start;
+            covered_to = end;
+        }
+    }
+    covered
+}
+
+/// Passages shared between every pair of different texts, made up of
+/// maximal shared substrings of at least min_length characters separated
+/// by at most max_gap characters
+pub(crate) fn reuse_graph<T: TrieStorage>(trie: &T, min_length: usize, max_gap: usize) -> ReuseGraph {
+    let mut substrings: HashMap<(usize, usize), Vec<SharedSubstring>> = HashMap::new();
+    for_each_maximal_pair(trie,
+                          min_length,
+                          |_| true,
+                          |leaf_text, other_leaf_text| leaf_text < other_leaf_text,
+                          |leaf, other_leaf, length| {
+                              substrings.entry((leaf.text_index, other_leaf.text_index))
+                                  .or_default()
+                                  .push(SharedSubstring {
+                                      start_a: leaf.index_in_str,
+                                      start_b: other_leaf.index_in_str,
+                                      length,
+                                  })
+                          });
+
+    let mut edges: Vec<ReuseEdge> = substrings.into_iter().map(|((text_a, text_b), substrings)| {
+        let mut passages = merge_shared_substrings(substrings, max_gap);
+        // A string repeated in the texts is shared at every pairing of its
+        // repeats, so drop passages lying within a longer one in text_a
+        passages.sort_by_key(|passage| (std::cmp::Reverse(passage.end_a() - passage.start_a()), passage.start_a()));
+        let mut kept: Vec<SharedSubstrings> = vec![];
+        for passage in passages {
+            if !kept.iter().any(|other| other.start_a() <= passage.start_a() && passage.end_a() <= other.end_a()) {
+                kept.push(passage);
+            }
+        }
+        let pieces = kept.iter().flat_map(|passage| passage.pieces.iter());
+        ReuseEdge {
+            text_a,
+            text_b,
+            passages: kept.len(),
+            shared_length: covered_length(pieces.map(|piece| (piece.start_a, piece.start_a + piece.length))),
+        }
+    }).collect();
+    edges.sort_by_key(|edge| (edge.text_a, edge.text_b));
+    ReuseGraph {
+        texts: (0 .. trie.num_texts()).map(|text_index| trie.get_text(text_index).name.clone()).collect(),
+        edges,
+    }
+}
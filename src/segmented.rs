@@ -0,0 +1,281 @@
+//! Index file made up of a sequence of segments, so that new texts can be
+//! added by appending to the file rather than rewriting it.
+//!
+//! The file is laid out as
+//!
+//! | magic number | version | segment | segment | ... |
+//!
+//! where each segment is the length of the segment as a little-endian u64
+//! followed by a suffix trie saved in the standard format. The segments are
+//! searched together, with the texts of each segment numbered after those
+//! of the segments before it. Compacting the index merges all the segments
+//! into one.
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::fs::{File,OpenOptions};
+use std::io;
+use std::io::{BufWriter,Error,ErrorKind,Write};
+
+use log::info;
+
+use crate::{BooleanHit,BooleanOptions,BooleanQuery,BuildOptions,Match,ProximityOptions,ProximityQuery,SuffixTrie,TextInfo};
+use crate::format::invalid_data;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SPYGLSEG";
+
+/// Version of the segmented layout written by this version of the library
+pub const SEGMENTED_FORMAT_VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 8 + 4;
+
+/// Index stored as a base suffix trie plus suffix tries appended later
+pub struct SegmentedIndex {
+    path: String,
+    segments: Vec<SuffixTrie>,
+}
+
+fn write_segment<W: Write>(writer: &mut W, suffix_trie: &SuffixTrie) -> Result<(), io::Error> {
+    let mut segment: Vec<u8> = vec![];
+    suffix_trie.save_to_writer(&mut segment)?;
+    writer.write_all(&(segment.len() as u64).to_le_bytes())?;
+    writer.write_all(&segment)
+}
+
+impl SegmentedIndex {
+    /// Create a new segmented index at the given path, with the given
+    /// suffix trie as its only segment
+    pub fn create(path: &str, suffix_trie: SuffixTrie) -> Result<SegmentedIndex, io::Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SEGMENTED_FORMAT_VERSION.to_le_bytes())?;
+        write_segment(&mut writer, &suffix_trie)?;
+        writer.flush()?;
+
+        Ok(SegmentedIndex {
+            path: path.to_string(),
+            segments: vec![suffix_trie],
+        })
+    }
+
+    /// Open an existing segmented index, loading all its segments
+    pub fn open(path: &str) -> Result<SegmentedIndex, io::Error> {
+        let contents = fs::read(path)?;
+        if contents.len() < HEADER_SIZE || &contents[0 .. 8] != MAGIC {
+            return Err(invalid_data("Not a segmented spyglass index: missing magic number"));
+        }
+        let version = u32::from_le_bytes(contents[8 .. HEADER_SIZE].try_into().unwrap());
+        if version != SEGMENTED_FORMAT_VERSION {
+            return Err(invalid_data(format!("Unsupported segmented index version {} (supported version is {})",
+                                            version, SEGMENTED_FORMAT_VERSION)));
+        }
+
+        let mut segments = vec![];
+        let mut remaining = &contents[HEADER_SIZE ..];
+        while !remaining.is_empty() {
+            if remaining.len() < 8 {
+                return Err(invalid_data("Segmented index is truncated: incomplete segment length"));
+            }
+            let length = u64::from_le_bytes(remaining[.. 8].try_into().unwrap()) as usize;
+            remaining = &remaining[8 ..];
+            if remaining.len() < length {
+                return Err(invalid_data(format!("Segmented index is truncated: segment {} is incomplete",
+                                                segments.len())));
+            }
+            segments.push(SuffixTrie::load_from_reader(&remaining[.. length])?);
+            remaining = &remaining[length ..];
+        }
+        if segments.is_empty() {
+            return Err(invalid_data("Segmented index is corrupt: no segments found"));
+        }
+        info!("Opened segmented index {} with {} segments", path, segments.len());
+
+        Ok(SegmentedIndex {
+            path: path.to_string(),
+            segments,
+        })
+    }
+
+    /// Add the suffix trie as a new segment at the end of the index file.
+    /// It must have been built with the same options as the existing segments.
+    pub fn append(&mut self, suffix_trie: SuffixTrie) -> Result<(), io::Error> {
+        if let Some(first) = self.segments.first() {
+            if first.get_options() != suffix_trie.get_options() {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("Cannot append trie built with options {:?} to index built with {:?}",
+                                              suffix_trie.get_options(), first.get_options())));
+            }
+        }
+        let mut writer = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        write_segment(&mut writer, &suffix_trie)?;
+        writer.flush()?;
+        self.segments.push(suffix_trie);
+        Ok(())
+    }
+
+    /// Merge all the segments into one and rewrite the index file
+    pub fn compact(&mut self) -> Result<(), io::Error> {
+        info!("Compacting {} segments of {}", self.segments.len(), self.path);
+        // Merge copies of the segments so that they are kept if compacting
+        // fails part way through
+        let mut merged = match self.segments.first() {
+            Some(first) => SuffixTrie::empty_with_options(first.get_options().clone()),
+            None => SuffixTrie::empty(),
+        };
+        for segment in self.segments.iter() {
+            merged.merge_from(segment)?;
+        }
+
+        // Write to a temporary file first so that the index is never left
+        // partially written
+        let temp_path = format!("{}.compacting", self.path);
+        let compacted = SegmentedIndex::create(&temp_path, merged)?;
+        if let Err(error) = fs::rename(&temp_path, &self.path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(error);
+        }
+        self.segments = compacted.segments;
+        Ok(())
+    }
+
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn find_edit_distance(&self, pattern: &str, max_errors: usize) -> Vec<Match> {
+        self.find_edit_distance_ignore(pattern, max_errors, HashMap::new())
+    }
+
+    pub fn find_edit_distance_ignore(&self,
+                                     pattern: &str,
+                                     max_errors: usize,
+                                     ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.find_in_segments(|segment| segment.find_edit_distance_ignore(pattern,
+                                                                          max_errors,
+                                                                          ignored_characters.clone()))
+    }
+
+    pub fn find_wildcard(&self, pattern: &str, max_errors: usize) -> Vec<Match> {
+        self.find_wildcard_ignore(pattern, max_errors, HashMap::new())
+    }
+
+    pub fn find_wildcard_ignore(&self,
+                                pattern: &str,
+                                max_errors: usize,
+                                ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.find_in_segments(|segment| segment.find_wildcard_ignore(pattern,
+                                                                     max_errors,
+                                                                     ignored_characters.clone()))
+    }
+
+    /// Find all exact matches of the given pattern
+    pub fn find_exact(&self, pattern: &str) -> Vec<Match> {
+        self.find_in_segments(|segment| segment.find_exact(pattern))
+    }
+
+    /// Count the exact matches of the pattern in each text
+    pub fn count_exact(&self, pattern: &str) -> Vec<usize> {
+        self.segments.iter().flat_map(|segment| segment.count_exact(pattern)).collect()
+    }
+
+    /// The units satisfying a boolean query, as for SuffixTrie. Units never
+    /// span texts, so each segment is searched on its own.
+    pub fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+        let mut hits = vec![];
+        let mut text_offset = 0;
+        for segment in self.segments.iter() {
+            for mut hit in segment.boolean_search(query, options)? {
+                hit.span.text_index += text_offset;
+                for match_obj in hit.matches.iter_mut() {
+                    match_obj.text_index += text_offset;
+                }
+                hits.push(hit);
+            }
+            text_offset += segment.num_texts();
+        }
+        Ok(hits)
+    }
+
+    /// Spans where the terms of the query are near each other, as for
+    /// SuffixTrie
+    pub fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+        self.find_in_segments(|segment| segment.find_near(query, options))
+    }
+
+    /// Match of the given length at a position in a text, as for SuffixTrie
+    pub fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+        if text_index >= self.segments.iter().map(|segment| segment.num_texts()).sum() {
+            return None;
+        }
+        let (segment_index, text_offset) = self.segment_of_text(text_index);
+        self.segments[segment_index].get_match_at(text_index - text_offset, index_in_str, length)
+            .map(|match_obj| Match { text_index, ..match_obj })
+    }
+
+    pub fn get_strings_of_match(&self,
+                                match_obj: &Match,
+                                context_lines: usize) -> (String, String, String) {
+        let (segment, segment_match) = self.segment_of_match(match_obj);
+        segment.get_strings_of_match(&segment_match, context_lines)
+    }
+
+    pub fn get_sentences_of_match(&self,
+                                  match_obj: &Match,
+                                  context_sentences: usize) -> (String, String, String) {
+        let (segment, segment_match) = self.segment_of_match(match_obj);
+        segment.get_sentences_of_match(&segment_match, context_sentences)
+    }
+
+    pub fn get_text_names(&self) -> Vec<String> {
+        self.segments.iter().flat_map(|segment| segment.get_text_names()).collect()
+    }
+
+    pub fn get_text_info(&self) -> Vec<TextInfo> {
+        self.segments.iter().flat_map(|segment| segment.get_text_info()).collect()
+    }
+
+    /// Options used to build the segments, which are the same for every segment
+    pub fn get_options(&self) -> &BuildOptions {
+        self.segments[0].get_options()
+    }
+
+    fn find_in_segments<F>(&self, find: F) -> Vec<Match>
+        where F: Fn(&SuffixTrie) -> Vec<Match> {
+        let mut matches = vec![];
+        let mut text_offset = 0;
+        for segment in self.segments.iter() {
+            for mut match_obj in find(segment) {
+                match_obj.text_index += text_offset;
+                matches.push(match_obj);
+            }
+            text_offset += segment.num_texts();
+        }
+        matches.sort();
+        matches
+    }
+
+    /// Find the segment containing the match, and the match with its text
+    /// index relative to that segment
+    fn segment_of_match(&self, match_obj: &Match) -> (&SuffixTrie, Match) {
+        let (segment_index, text_offset) = self.segment_of_text(match_obj.text_index);
+        let segment_match = Match {
+            text_index: match_obj.text_index - text_offset,
+            ..match_obj.clone()
+        };
+        (&self.segments[segment_index], segment_match)
+    }
+
+    /// Find the segment containing the text with the given index, and the
+    /// number of texts in the segments before it
+    fn segment_of_text(&self, text_index: usize) -> (usize, usize) {
+        let mut text_offset = 0;
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            let num_texts = segment.num_texts();
+            if text_index < text_offset + num_texts {
+                return (segment_index, text_offset);
+            }
+            text_offset += num_texts;
+        }
+        panic!("Text index out of bounds: {} but there are only {} texts", text_index, text_offset);
+    }
+}
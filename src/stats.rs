@@ -0,0 +1,152 @@
+//! Counts of the hits of a search in each text of an index, for when the
+//! number of matches matters rather than the matches themselves.
+//!
+//! Rates are given per 10,000 characters of the normalised text so that
+//! texts of different lengths can be compared.
+use serde::Serialize;
+
+use crate::{Match,SearchIndex,TextInfo};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts() -> Vec<TextInfo> {
+        vec![TextInfo { name: "first".to_string(), num_chars: 20000, num_lines: 5 },
+             TextInfo { name: "second".to_string(), num_chars: 0, num_lines: 1 }]
+    }
+
+    #[test]
+    fn rates_and_totals() {
+        let stats = SearchStats::from_text_counts(texts(), &[3, 0]);
+        assert_eq!(stats.total_hits, 3);
+        assert_eq!(stats.texts_with_hits, 1);
+        assert_eq!(stats.hits_per_10k_chars, 1.5);
+        assert_eq!(stats.texts[0].hits_per_10k_chars, 1.5);
+        // Empty texts have no rate rather than dividing by zero
+        assert_eq!(stats.texts[1].hits_per_10k_chars, 0.0);
+    }
+
+    #[test]
+    fn line_ranges() {
+        let mut stats = SearchStats::from_text_counts(texts(), &[3, 0]);
+        stats.add_hit_lines(&[(0, 0), (0, 4), (0, 1)], 2);
+        let ranges: Vec<(usize, usize, usize)> = stats.texts[0].line_ranges.iter()
+            .map(|range| (range.start_line, range.end_line, range.hits))
+            .collect();
+        assert_eq!(ranges, vec![(0, 1, 2), (2, 3, 0), (4, 4, 1)]);
+        assert_eq!(stats.texts[1].line_ranges.len(), 1);
+    }
+}
+
+/// Hits in a range of lines of a text, from start_line to end_line
+/// inclusive, counting lines from 0
+#[derive(Clone,Debug,PartialEq,Eq,Serialize)]
+pub struct LineRangeStats {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub hits: usize,
+}
+
+/// Hits in a single text
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub struct TextStats {
+    pub text_index: usize,
+    pub text_name: String,
+    pub num_chars: usize,
+    pub num_lines: usize,
+    pub hits: usize,
+    pub hits_per_10k_chars: f64,
+    // Hits in consecutive ranges of lines covering the whole text, if
+    // requested
+    pub line_ranges: Vec<LineRangeStats>,
+}
+
+/// Hits of a search in each text of an index, and across the whole index
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub struct SearchStats {
+    pub total_hits: usize,
+    pub total_chars: usize,
+    pub hits_per_10k_chars: f64,
+    pub texts_with_hits: usize,
+    pub texts: Vec<TextStats>,
+}
+
+pub(crate) fn per_10k_chars(hits: usize, num_chars: usize) -> f64 {
+    if num_chars == 0 {
+        0.0
+    } else {
+        hits as f64 * 10000.0 / num_chars as f64
+    }
+}
+
+impl SearchStats {
+    /// Statistics of a set of matches found in the index
+    pub fn from_matches(index: &dyn SearchIndex, matches: &[Match]) -> Self {
+        let texts = index.get_text_info();
+        let mut counts = vec![0; texts.len()];
+        for match_obj in matches.iter() {
+            counts[match_obj.text_index] += 1;
+        }
+        SearchStats::from_text_counts(texts, &counts)
+    }
+
+    /// Statistics of the exact matches of a pattern, counted without
+    /// finding the position of each match
+    pub fn from_exact_counts(index: &dyn SearchIndex, pattern: &str) -> Self {
+        SearchStats::from_text_counts(index.get_text_info(), &index.count_exact(pattern))
+    }
+
+    /// Statistics from the number of hits in each text
+    pub fn from_text_counts(texts: Vec<TextInfo>, counts: &[usize]) -> Self {
+        let texts: Vec<TextStats> = texts.into_iter().zip(counts.iter()).enumerate()
+            .map(|(text_index, (text, hits))| TextStats {
+                text_index,
+                text_name: text.name,
+                num_chars: text.num_chars,
+                num_lines: text.num_lines,
+                hits: *hits,
+                hits_per_10k_chars: per_10k_chars(*hits, text.num_chars),
+                line_ranges: vec![],
+            })
+            .collect();
+        let total_hits = texts.iter().map(|text| text.hits).sum();
+        let total_chars = texts.iter().map(|text| text.num_chars).sum();
+        SearchStats {
+            total_hits,
+            total_chars,
+            hits_per_10k_chars: per_10k_chars(total_hits, total_chars),
+            texts_with_hits: texts.iter().filter(|text| text.hits > 0).count(),
+            texts,
+        }
+    }
+
+    /// Break down the hits of the matches in each text by ranges of
+    /// lines_per_range lines, counting each match in the range containing
+    /// its first line
+    pub fn add_line_ranges(&mut self, matches: &[Match], lines_per_range: usize) {
+        let hit_lines: Vec<(usize, usize)> = matches.iter()
+            .map(|match_obj| (match_obj.text_index, match_obj.start_line))
+            .collect();
+        self.add_hit_lines(&hit_lines, lines_per_range);
+    }
+
+    /// Break down the hits in each text by ranges of lines, given the text
+    /// index and line of each hit
+    fn add_hit_lines(&mut self, hit_lines: &[(usize, usize)], lines_per_range: usize) {
+        let lines_per_range = lines_per_range.max(1);
+        for text in self.texts.iter_mut() {
+            let num_ranges = text.num_lines.div_ceil(lines_per_range).max(1);
+            text.line_ranges = (0 .. num_ranges).map(|range| LineRangeStats {
+                start_line: range * lines_per_range,
+                end_line: ((range + 1) * lines_per_range).min(text.num_lines.max(1)) - 1,
+                hits: 0,
+            }).collect();
+        }
+        for (text_index, line) in hit_lines.iter() {
+            let line_ranges = &mut self.texts[*text_index].line_ranges;
+            let range = (line / lines_per_range).min(line_ranges.len() - 1);
+            line_ranges[range].hits += 1;
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Storage for the characters of the texts in a suffix trie.
+//!
+//! The characters of a trie being built are kept in memory. Those of an
+//! index saved with its texts outside the index file (see TextStore) are
+//! only read from the store when they are first needed, a source file or
+//! the whole blob file at a time, so the index holds just its structure in
+//! memory. A search can't report an error, so loading the index checks
+//! that none of the files has changed since the index was saved, and a
+//! search only panics if one changes while the index is open.
+use std::fs;
+use std::io;
+use std::io::{Error,ErrorKind};
+use std::ops::{Index,Range};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::info;
+use serde::{Deserialize,Deserializer,Serialize,Serializer};
+
+use crate::BuildOptions;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_on_demand() {
+        let path = std::env::temp_dir().join(format!("spyglass_storage_{}.txt", std::process::id()));
+        fs::write(&path, "abc").unwrap();
+        let storage = CharStorage::External(vec![ExternalChars::new(0, path.clone(), crc32fast::hash(b"abc"), None)]);
+        // Nothing is read until a character is needed
+        fs::write(&path, "xyz").unwrap();
+        assert!(storage.verify().is_err());
+        fs::write(&path, "abc").unwrap();
+        assert!(storage.verify().is_ok());
+        assert_eq!((storage.len(), storage[1]), (3, 'b'));
+        assert_eq!(storage.chars(1 .. 3).collect::<String>(), "bc");
+        assert_eq!(storage, CharStorage::InMemory(vec!['a', 'b', 'c']));
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+/// Characters of all the texts, in memory or in an external text store
+#[derive(Debug)]
+pub(crate) enum CharStorage {
+    InMemory(Vec<char>),
+    // Files of the store in order of the characters read from them
+    External(Vec<ExternalChars>),
+}
+
+/// Characters read from a file of a text store when first needed
+#[derive(Debug)]
+pub(crate) struct ExternalChars {
+    // Index in the storage of the first character read from the file
+    offset: usize,
+    path: PathBuf,
+    checksum: u32,
+    // Options to normalise a source file with, or None for a blob file,
+    // which holds characters which are already normalised
+    normalise_with: Option<BuildOptions>,
+    chars: OnceLock<Vec<char>>,
+}
+
+impl ExternalChars {
+    pub(crate) fn new(offset: usize, path: PathBuf, checksum: u32, normalise_with: Option<BuildOptions>) -> Self {
+        ExternalChars {
+            offset,
+            path,
+            checksum,
+            normalise_with,
+            chars: OnceLock::new(),
+        }
+    }
+
+    /// The contents of the file, if it hasn't changed since the index was
+    /// saved
+    fn read_contents(&self) -> Result<Vec<u8>, io::Error> {
+        let contents = fs::read(&self.path).map_err(|error| {
+            Error::new(error.kind(), format!("Can't read text file {}: {}", self.path.display(), error))
+        })?;
+        if crc32fast::hash(&contents) != self.checksum {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("Text file {} has changed since the index was saved", self.path.display())));
+        }
+        Ok(contents)
+    }
+
+    fn read(&self) -> Result<Vec<char>, io::Error> {
+        info!("Reading text from {}", self.path.display());
+        let contents = self.read_contents()?;
+        match &self.normalise_with {
+            Some(options) => Ok(options.normalised_chars(&String::from_utf8_lossy(&contents))),
+            None => Ok(String::from_utf8(contents)
+                       .map_err(|error| Error::new(ErrorKind::InvalidData, error))?
+                       .chars().collect()),
+        }
+    }
+
+    fn chars(&self) -> &[char] {
+        self.chars.get_or_init(|| self.read().unwrap_or_else(|error| panic!("Can't read text store: {}", error)))
+    }
+}
+
+impl Default for CharStorage {
+    fn default() -> Self {
+        CharStorage::InMemory(vec![])
+    }
+}
+
+impl CharStorage {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            CharStorage::InMemory(chars) => chars.len(),
+            CharStorage::External(files) => files.last().map_or(0, |file| file.offset + file.chars().len()),
+        }
+    }
+
+    /// The characters in the range
+    pub(crate) fn chars(&self, range: Range<usize>) -> impl Iterator<Item = char> + '_ {
+        range.map(move |index| self[index])
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = char> + '_ {
+        self.chars(0 .. self.len())
+    }
+
+    /// Check the files of an external store which haven't been read yet,
+    /// returning an error if any is missing or has changed. Their
+    /// characters are still only kept once they are needed.
+    pub(crate) fn verify(&self) -> Result<(), io::Error> {
+        if let CharStorage::External(files) = self {
+            for file in files.iter().filter(|file| file.chars.get().is_none()) {
+                file.read_contents()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The characters in memory, reading them all in from an external
+    /// store first so that they can be changed
+    pub(crate) fn to_mut(&mut self) -> &mut Vec<char> {
+        if let CharStorage::External(_) = self {
+            *self = CharStorage::InMemory(self.iter().collect());
+        }
+        match self {
+            CharStorage::InMemory(chars) => chars,
+            CharStorage::External(_) => unreachable!(),
+        }
+    }
+}
+
+impl Index<usize> for CharStorage {
+    type Output = char;
+
+    fn index(&self, index: usize) -> &char {
+        match self {
+            CharStorage::InMemory(chars) => &chars[index],
+            CharStorage::External(files) => {
+                let file = &files[files.partition_point(|file| file.offset <= index) - 1];
+                &file.chars()[index - file.offset]
+            },
+        }
+    }
+}
+
+impl PartialEq for CharStorage {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+// Saved in the same way as the Vec<char> it replaced, so the format of
+// saved indexes is unchanged
+impl Serialize for CharStorage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for CharStorage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(CharStorage::InMemory(Vec::deserialize(deserializer)?))
+    }
+}
@@ -0,0 +1,517 @@
+//! Word-level index of the texts of a suffix trie, for searching for
+//! phrases a word at a time rather than a character at a time.
+//!
+//! Each word is given an ID, and the suffixes of each sentence are stored
+//! as sequences of IDs in a suffix trie whose edges are labelled with word
+//! IDs rather than characters. Texts added without marked sentences are
+//! split into sentences after words ending in '.', '!' or '?', so that the
+//! trie doesn't hold every suffix of a whole text. Words are separated by
+//! whitespace, with any punctuation at the start or end of a word removed,
+//! so the phrase "drunken sailor" matches "drunken\nsailor." Phrases can be
+//! matched exactly or with errors which insert, delete or substitute whole
+//! words.
+//!
+//! Matches refer to the characters of the underlying suffix trie, so they
+//! can be displayed in the same way as any other match. A saved word index
+//! is laid out as
+//!
+//! | magic number | version | trie length | suffix trie | words |
+//!
+//! where the trie length is a little-endian u64, the suffix trie is saved
+//! in the standard format and the words are the bincode encoding of the
+//! vocabulary, the word IDs of each sentence and the nodes of the word trie.
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter,Write};
+
+use log::info;
+use serde::{Deserialize,Serialize};
+
+use crate::{BooleanHit,BooleanOptions,BooleanQuery,BuildOptions,Leaf,Match,ProximityOptions,ProximityQuery,SearchIndex,SuffixTrie,TextInfo,TrieStorage};
+use crate::format::invalid_data;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(matches: &[Match]) -> Vec<(usize, usize, usize)> {
+        let mut positions: Vec<(usize, usize, usize)> = matches.iter()
+            .map(|match_obj| (match_obj.index_in_str, match_obj.length, match_obj.errors))
+            .collect();
+        positions.sort();
+        positions
+    }
+
+    #[test]
+    fn split_into_words() {
+        let chars: Vec<char> = "  (the) red-fish,\nswam ... away!".chars().collect();
+        let words: Vec<String> = word_spans(&chars, 0, chars.len()).into_iter()
+            .map(|(start, end)| chars[start .. end].iter().collect())
+            .collect();
+        assert_eq!(words, vec!["the", "red-fish", "swam", "away"]);
+        assert_eq!(word_spans(&chars, 0, 8), vec![(3, 6)]);
+    }
+
+    #[test]
+    fn exact_phrases() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "the red fish, the red\nfish and the blue fish<<STOP>>red fish");
+        let index = WordIndex::new(trie);
+        assert_eq!(index.vocabulary().len(), 5);
+        assert_eq!(positions(&index.find_phrase("red fish")), vec![(4, 8, 0), (18, 8, 0), (44, 8, 0)]);
+        assert_eq!(positions(&index.find_phrase("  fish the ")), vec![(8, 9, 0)]);
+        // Phrases don't cross sentence boundaries, or match parts of words
+        assert!(index.find_phrase("fish red").is_empty());
+        assert!(index.find_phrase("re fish").is_empty());
+        assert!(index.find_phrase("").is_empty());
+    }
+
+    #[test]
+    fn split_unmarked_sentences() {
+        assert_eq!(guess_sentence_starts(&"Yes. No! Why?".chars().collect::<Vec<char>>()), vec![0, 5, 9]);
+        // Without marked sentences, phrases end at sentence punctuation
+        let index = WordIndex::new(SuffixTrie::new("the red fish. red fish swam\nfish red"));
+        assert_eq!(positions(&index.find_phrase("red fish")), vec![(4, 8, 0), (14, 8, 0)]);
+        assert_eq!(positions(&index.find_phrase("fish red")), vec![(28, 8, 0)]);
+    }
+
+    #[test]
+    fn word_edit_distance() {
+        let mut trie = SuffixTrie::empty();
+        trie.add_sentences_from_text("first", "a b c d.<<STOP>> a x c d.<<STOP>> a c d.<<STOP>> b b b");
+        let index = WordIndex::new(trie);
+        assert_eq!(positions(&index.find_phrase_edit_distance("a b c", 0)), vec![(0, 5, 0)]);
+        // A substituted word, and deleted words
+        assert_eq!(positions(&index.find_phrase_edit_distance("a b c", 1)),
+                   vec![(0, 5, 0), (2, 3, 1), (9, 5, 1), (18, 3, 1)]);
+        // An inserted word at 0 and 9, substitutions at 2 and 11 and
+        // deletions at 4, 13 and 20
+        assert_eq!(positions(&index.find_phrase_edit_distance("a c d", 1)),
+                   vec![(0, 7, 1), (2, 5, 1), (4, 3, 1), (9, 7, 1), (11, 5, 1), (13, 3, 1), (18, 5, 0), (20, 3, 1)]);
+        assert!(index.find_phrase_edit_distance("unknown words", 1).is_empty());
+    }
+}
+
+pub(crate) const MAGIC: &[u8; 8] = b"SPYGLWRD";
+
+/// Version of the word index layout written by this version of the library
+pub const WORDS_FORMAT_VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 8 + 4 + 8;
+
+/// Where a word is in its text, counting characters of the normalised text
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize)]
+struct WordSpan {
+    text_index: usize,
+    start: usize,
+    end: usize,
+}
+
+/// Node of the word trie, whose edge is a run of words in word_storage
+#[derive(Clone,Debug,Default,Serialize,Deserialize)]
+struct WordNode {
+    children: HashMap<u32, usize>,
+    // Starts of the suffixes ending at this node, as indices into
+    // word_storage
+    leaves: Vec<usize>,
+    edge_start: usize,
+    edge_length: usize,
+}
+
+/// Suffix trie of the words of each sentence of the texts of a suffix trie
+#[derive(Debug)]
+pub struct WordIndex {
+    trie: SuffixTrie,
+    vocabulary: Vec<String>,
+    word_ids: HashMap<String, u32>,
+    // IDs of the words of every sentence, one sentence after another
+    word_storage: Vec<u32>,
+    // Position of each word of word_storage in its text
+    word_spans: Vec<WordSpan>,
+    nodes: Vec<WordNode>,
+}
+
+/// Start and end of each word between start and end of the characters,
+/// leaving out any punctuation at the start or end of the word
+fn word_spans(chars: &[char], start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut index = start;
+    while index < end {
+        while index < end && chars[index].is_whitespace() {
+            index += 1;
+        }
+        let mut word_start = index;
+        while index < end && !chars[index].is_whitespace() {
+            index += 1;
+        }
+        let mut word_end = index;
+        while word_start < word_end && !chars[word_start].is_alphanumeric() {
+            word_start += 1;
+        }
+        while word_end > word_start && !chars[word_end - 1].is_alphanumeric() {
+            word_end -= 1;
+        }
+        if word_start < word_end {
+            spans.push((word_start, word_end));
+        }
+    }
+    spans
+}
+
+/// Starts of the sentences of a text with no marked sentences, taking
+/// each word ending in '.', '!' or '?' to end a sentence
+fn guess_sentence_starts(chars: &[char]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for index in 1 .. chars.len() {
+        if chars[index].is_whitespace() && matches!(chars[index - 1], '.' | '!' | '?') {
+            starts.push(index + 1);
+        }
+    }
+    starts
+}
+
+impl WordIndex {
+    /// Build a word index of the texts of the suffix trie
+    pub fn new(trie: SuffixTrie) -> Self {
+        let mut index = WordIndex {
+            trie,
+            vocabulary: vec![],
+            word_ids: HashMap::new(),
+            word_storage: vec![],
+            word_spans: vec![],
+            nodes: vec![WordNode::default()],
+        };
+        for text_index in 0 .. index.trie.num_texts() {
+            let text = index.trie.get_text(text_index);
+            let chars: Vec<char> = index.trie.owned_from_index(text, 0, text.last_index).chars().collect();
+            let mut sentence_starts = text.sentence_start_indices.clone().unwrap_or_default();
+            if sentence_starts.first() != Some(&0) {
+                sentence_starts.insert(0, 0);
+            }
+            if sentence_starts.len() == 1 {
+                sentence_starts = guess_sentence_starts(&chars);
+            }
+            sentence_starts.push(chars.len());
+            for sentence in sentence_starts.windows(2) {
+                let sentence_start = index.word_storage.len();
+                for (start, end) in word_spans(&chars, sentence[0], sentence[1]) {
+                    let word: String = chars[start .. end].iter().collect();
+                    let word_id = index.word_id_or_insert(word);
+                    index.word_storage.push(word_id);
+                    index.word_spans.push(WordSpan { text_index, start, end });
+                }
+                let sentence_end = index.word_storage.len();
+                for suffix_start in sentence_start .. sentence_end {
+                    index.add_suffix(suffix_start, sentence_end);
+                }
+            }
+        }
+        index
+    }
+
+    /// Save the word index, with its suffix trie, to the given path
+    pub fn save(&self, path: &str) -> Result<(), io::Error> {
+        let mut trie = vec![];
+        self.trie.save_to_writer(&mut trie)?;
+        let words = bincode::serialize(&(&self.vocabulary, &self.word_storage, &self.word_spans, &self.nodes))
+            .map_err(invalid_data)?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&WORDS_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(trie.len() as u64).to_le_bytes())?;
+        writer.write_all(&trie)?;
+        writer.write_all(&words)?;
+        writer.flush()
+    }
+
+    /// Open a word index saved by save
+    pub fn open(path: &str) -> Result<WordIndex, io::Error> {
+        let contents = fs::read(path)?;
+        if contents.len() < HEADER_SIZE || &contents[0 .. 8] != MAGIC {
+            return Err(invalid_data("Not a spyglass word index: missing magic number"));
+        }
+        let version = u32::from_le_bytes(contents[8 .. 12].try_into().unwrap());
+        if version != WORDS_FORMAT_VERSION {
+            return Err(invalid_data(format!("Unsupported word index version {} (supported version is {})",
+                                            version, WORDS_FORMAT_VERSION)));
+        }
+        let trie_length = u64::from_le_bytes(contents[12 .. HEADER_SIZE].try_into().unwrap()) as usize;
+        if contents.len() - HEADER_SIZE < trie_length {
+            return Err(invalid_data("Word index is truncated: the suffix trie is incomplete"));
+        }
+        let trie = SuffixTrie::load_from_reader(&contents[HEADER_SIZE .. HEADER_SIZE + trie_length])?;
+        let (vocabulary, word_storage, word_spans, nodes): (Vec<String>, Vec<u32>, Vec<WordSpan>, Vec<WordNode>) =
+            bincode::deserialize(&contents[HEADER_SIZE + trie_length ..]).map_err(invalid_data)?;
+
+        let word_ids = vocabulary.iter().enumerate()
+            .map(|(word_id, word)| (word.clone(), word_id as u32))
+            .collect();
+        let index = WordIndex { trie, vocabulary, word_ids, word_storage, word_spans, nodes };
+        index.check()?;
+        info!("Opened word index {} with {} words", path, index.vocabulary.len());
+        Ok(index)
+    }
+
+    /// Check that the words refer to the texts of the trie and the nodes to
+    /// the words, so that a corrupt file can't make a search panic
+    fn check(&self) -> Result<(), io::Error> {
+        let num_words = self.word_storage.len();
+        let words_valid = self.word_spans.len() == num_words
+            && self.word_storage.iter().all(|word_id| (*word_id as usize) < self.vocabulary.len())
+            && self.word_spans.iter().all(|span| span.text_index < self.trie.num_texts()
+                                          && span.start < span.end
+                                          && span.end <= self.trie.get_text(span.text_index).last_index);
+        let nodes_valid = !self.nodes.is_empty() && self.nodes.iter().all(|node| {
+            node.edge_start + node.edge_length <= num_words
+                && node.children.values().all(|child| *child < self.nodes.len())
+                && node.leaves.iter().all(|leaf| *leaf < num_words)
+        });
+        if words_valid && nodes_valid {
+            Ok(())
+        } else {
+            Err(invalid_data("Word index is corrupt: words don't match the texts"))
+        }
+    }
+
+    /// The suffix trie the word index was built from
+    pub fn trie(&self) -> &SuffixTrie {
+        &self.trie
+    }
+
+    /// The suffix trie the word index was built from, without its words
+    pub fn into_trie(self) -> SuffixTrie {
+        self.trie
+    }
+
+    /// Each different word in the texts, in order of first appearance
+    pub fn vocabulary(&self) -> &[String] {
+        &self.vocabulary
+    }
+
+    /// Find the phrase, matching whole words and ignoring the whitespace and
+    /// punctuation between them
+    pub fn find_phrase(&self, phrase: &str) -> Vec<Match> {
+        self.find_phrase_edit_distance(phrase, 0)
+    }
+
+    /// Find the phrase with up to max_errors words inserted, deleted or
+    /// substituted. Each match starts at a different word, with as few
+    /// errors as possible.
+    pub fn find_phrase_edit_distance(&self, phrase: &str, max_errors: usize) -> Vec<Match> {
+        let pattern = self.pattern_ids(phrase);
+        if pattern.is_empty() {
+            return vec![];
+        }
+
+        // Fewest errors, then fewest words, matching from each start word.
+        // Each leaf is below a single path from the root, so it is given
+        // the best match along that path once the path ends, either where
+        // it stops matching or at the node where the leaf's suffix ends.
+        let mut best: HashMap<usize, (usize, usize)> = HashMap::new();
+        let first_row: Vec<usize> = (0 ..= pattern.len()).collect();
+        let mut to_process = vec![(0, 0, first_row, None)];
+        while let Some((node_index, depth, row, best_above)) = to_process.pop() {
+            'children: for child_index in self.nodes[node_index].children.values() {
+                let child = &self.nodes[*child_index];
+                let mut row = row.clone();
+                let mut depth = depth;
+                let mut best_here: Option<(usize, usize)> = best_above;
+                for word_id in self.word_storage[child.edge_start .. child.edge_start + child.edge_length].iter() {
+                    row = next_row(&row, &pattern, *word_id);
+                    depth += 1;
+                    let errors = row[pattern.len()];
+                    if errors <= max_errors {
+                        best_here = Some(best_here.map_or((errors, depth), |found| found.min((errors, depth))));
+                    }
+                    if row.iter().all(|errors| *errors > max_errors) {
+                        if let Some(found) = best_here {
+                            best.extend(self.leaves_below(child).into_iter().map(|leaf| (leaf, found)));
+                        }
+                        continue 'children;
+                    }
+                }
+                if let Some(found) = best_here {
+                    best.extend(child.leaves.iter().map(|leaf| (*leaf, found)));
+                }
+                to_process.push((*child_index, depth, row, best_here));
+            }
+        }
+
+        let mut matches: Vec<Match> = best.into_iter()
+            .map(|(leaf, (errors, num_words))| self.match_of_words(leaf, num_words, errors))
+            .collect();
+        matches.sort();
+        matches
+    }
+
+    fn word_id_or_insert(&mut self, word: String) -> u32 {
+        if let Some(word_id) = self.word_ids.get(&word) {
+            return *word_id;
+        }
+        let word_id = self.vocabulary.len() as u32;
+        self.vocabulary.push(word.clone());
+        self.word_ids.insert(word, word_id);
+        word_id
+    }
+
+    /// IDs of the words of the normalised pattern, or None for words which
+    /// aren't in any text
+    fn pattern_ids(&self, phrase: &str) -> Vec<Option<u32>> {
+        let chars: Vec<char> = self.trie.build_options().normaliser.normalise(phrase).chars().collect();
+        word_spans(&chars, 0, chars.len()).into_iter()
+            .map(|(start, end)| self.word_ids.get(&chars[start .. end].iter().collect::<String>()).cloned())
+            .collect()
+    }
+
+    /// Add the words of word_storage from start to end as a suffix,
+    /// splitting an edge if the suffix leaves it part of the way along
+    fn add_suffix(&mut self, start: usize, end: usize) {
+        let mut node_index = 0;
+        let mut position = start;
+        while position < end {
+            let word_id = self.word_storage[position];
+            let child_index = match self.nodes[node_index].children.get(&word_id) {
+                Some(child_index) => *child_index,
+                None => {
+                    self.nodes.push(WordNode {
+                        leaves: vec![start],
+                        edge_start: position,
+                        edge_length: end - position,
+                        ..WordNode::default()
+                    });
+                    let new_index = self.nodes.len() - 1;
+                    self.nodes[node_index].children.insert(word_id, new_index);
+                    return;
+                },
+            };
+
+            let (edge_start, edge_length) = (self.nodes[child_index].edge_start, self.nodes[child_index].edge_length);
+            let shared = (0 .. edge_length)
+                .take_while(|offset| position + offset < end
+                            && self.word_storage[edge_start + offset] == self.word_storage[position + offset])
+                .count();
+            if shared < edge_length {
+                // Split the edge where the suffix leaves it
+                let child = &mut self.nodes[child_index];
+                child.edge_start += shared;
+                child.edge_length -= shared;
+                let child_word = self.word_storage[child.edge_start];
+                self.nodes.push(WordNode {
+                    children: HashMap::from([(child_word, child_index)]),
+                    edge_start,
+                    edge_length: shared,
+                    ..WordNode::default()
+                });
+                let middle_index = self.nodes.len() - 1;
+                self.nodes[node_index].children.insert(word_id, middle_index);
+                node_index = middle_index;
+            } else {
+                node_index = child_index;
+            }
+            position += shared;
+        }
+        self.nodes[node_index].leaves.push(start);
+    }
+
+    fn leaves_below(&self, node: &WordNode) -> Vec<usize> {
+        let mut leaves = node.leaves.clone();
+        let mut to_process: Vec<usize> = node.children.values().cloned().collect();
+        while let Some(node_index) = to_process.pop() {
+            leaves.extend(&self.nodes[node_index].leaves);
+            to_process.extend(self.nodes[node_index].children.values());
+        }
+        leaves
+    }
+
+    /// Match covering num_words words of word_storage starting at start
+    fn match_of_words(&self, start: usize, num_words: usize, errors: usize) -> Match {
+        let first = self.word_spans[start];
+        let last = self.word_spans[start + num_words - 1];
+        let leaf = Leaf::new(first.start, first.text_index);
+        self.trie.match_array_from_leaves(vec![leaf], last.end - first.start, errors).remove(0)
+    }
+}
+
+/// Next row of the edit distance table after reading another word, where
+/// row[j] is the fewest errors matching the first j words of the pattern
+fn next_row(row: &[usize], pattern: &[Option<u32>], word_id: u32) -> Vec<usize> {
+    let mut next = Vec::with_capacity(row.len());
+    next.push(row[0] + 1);
+    for (j, pattern_word) in pattern.iter().enumerate() {
+        let substitution = if *pattern_word == Some(word_id) { 0 } else { 1 };
+        next.push((row[j] + substitution).min(row[j + 1] + 1).min(next[j] + 1));
+    }
+    next
+}
+
+// Ignored characters and wildcards work a character at a time, so they
+// don't apply to the whole words matched by searches of a word index.
+// Boolean and proximity searches match the characters of the texts, as in
+// the suffix trie.
+impl SearchIndex for WordIndex {
+    fn find_exact(&self, pattern: &str) -> Vec<Match> {
+        self.find_phrase(pattern)
+    }
+
+    fn count_exact(&self, pattern: &str) -> Vec<usize> {
+        let mut counts = vec![0; self.trie.num_texts()];
+        for match_obj in self.find_phrase(pattern) {
+            counts[match_obj.text_index] += 1;
+        }
+        counts
+    }
+
+    fn find_edit_distance_ignore(&self,
+                                 pattern: &str,
+                                 max_errors: usize,
+                                 _ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.find_phrase_edit_distance(pattern, max_errors)
+    }
+
+    fn find_wildcard_ignore(&self,
+                            pattern: &str,
+                            max_errors: usize,
+                            _ignored_characters: HashMap<char, bool>) -> Vec<Match> {
+        self.find_phrase_edit_distance(pattern, max_errors)
+    }
+
+    fn get_strings_of_match(&self,
+                            match_obj: &Match,
+                            context_lines: usize) -> (String, String, String) {
+        self.trie.get_strings_of_match(match_obj, context_lines)
+    }
+
+    fn get_sentences_of_match(&self,
+                              match_obj: &Match,
+                              context_sentences: usize) -> (String, String, String) {
+        self.trie.get_sentences_of_match(match_obj, context_sentences)
+    }
+
+    fn get_text_info(&self) -> Vec<TextInfo> {
+        self.trie.get_text_info()
+    }
+
+    fn get_options(&self) -> &BuildOptions {
+        self.trie.get_options()
+    }
+
+    fn get_match_at(&self, text_index: usize, index_in_str: usize, length: usize) -> Option<Match> {
+        self.trie.get_match_at(text_index, index_in_str, length)
+    }
+
+    fn boolean_search(&self, query: &BooleanQuery, options: &BooleanOptions) -> Result<Vec<BooleanHit>, io::Error> {
+        self.trie.boolean_search(query, options)
+    }
+
+    fn find_near(&self, query: &ProximityQuery, options: &ProximityOptions) -> Vec<Match> {
+        self.trie.find_near(query, options)
+    }
+
+    fn matches_words(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,424 @@
+use std::io::{BufRead,BufReader,Read,Write};
+use std::net::TcpStream;
+use std::process::{Command,Output,Stdio};
+
+fn temp_path(name: &str) -> String {
+    let mut path = std::env::temp_dir();
+    path.push(format!("spyglass_cli_{}_{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+fn spyglass(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_spyglass"))
+        .args(args)
+        .output()
+        .expect("Failed to run spyglass")
+}
+
+fn stdout_of(args: &[&str]) -> String {
+    let output = spyglass(args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn index_and_search() {
+    let path = temp_path("index_and_search.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let output = stdout_of(&["search", &path, "sentences"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt:2:and sentences. Sometimes sentences cross over\n\
+                resources/tests/simple/small.txt:2:and sentences. Sometimes sentences cross over\n");
+
+    let output = stdout_of(&["search", &path, "sentences", "-m", "1", "-C", "1"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt-1-This is a file. It has lines in it\n\
+                resources/tests/simple/small.txt:2:and sentences. Sometimes sentences cross over\n\
+                resources/tests/simple/small.txt-3-multiple lines and \n");
+
+    // Matches both "sentence" and "sentences" at each position
+    let output = stdout_of(&["search", &path, "sent?nce*", "-w"]);
+    assert_eq!(output.lines().count(), 4);
+
+    let output = stdout_of(&["search", &path, "sentencxs", "-e", "1"]);
+    assert_eq!(output.lines().count(), 2);
+
+    let output = stdout_of(&["search", &path, "sentnces", "-e", "1", "-m", "1", "--format", "matches"]);
+    assert_eq!(output, "sent{e}nces\n");
+    let output = stdout_of(&["search", &path, "sentnces", "-e", "1", "-m", "1", "--format", "matches",
+                             "--highlight", "none"]);
+    assert_eq!(output, "sentences\n");
+
+    let output = stdout_of(&["search", &path, "sentences", "--format", "matches"]);
+    assert_eq!(output, "sentences\nsentences\n");
+
+    let output = stdout_of(&["search", &path, "sentences", "--format", "kwic", "--width", "15", "--sort", "left"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt:2: ines in it and   sentences  . Sometimes sen\n\
+                resources/tests/simple/small.txt:2: ces. Sometimes   sentences   cross over mul\n");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn boolean_query() {
+    let path = temp_path("boolean_query.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let output = stdout_of(&["query", &path, "drunken AND NOT What", "--scope", "line"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt:6:with a drunken sailor.\n\
+                resources/tests/simple/small.txt:8:with a drunken sailor.\n");
+
+    let output = stdout_of(&["query", &path, "(sailor OR sentences) AND NOT \"a file\"", "--scope", "line",
+                             "--format", "jsonl"]);
+    assert_eq!(output.lines().count(), 4);
+
+    // The whole text is a single sentence
+    let output = stdout_of(&["query", &path, "sailr AND early", "-e", "1", "--format", "matches"]);
+    assert_eq!(output.lines().count(), 1);
+    assert!(output.starts_with("This is a file."));
+
+    let output = spyglass(&["query", &path, "sailor AND"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid query"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn proximity_query() {
+    let path = temp_path("proximity_query.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    // Each span is shown whole, even across lines
+    let output = stdout_of(&["near", &path, "What BEFORE/3 with", "--format", "matches"]);
+    assert_eq!(output, "What should we do with\n".repeat(3));
+    let output = stdout_of(&["near", &path, "sailor NEAR/2 What", "--unit", "characters"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt:6:with a drunken sailor.\n\
+                resources/tests/simple/small.txt:7:What should we do\n\
+                resources/tests/simple/small.txt:8:with a drunken sailor.\n\
+                resources/tests/simple/small.txt:9:What should we do with a drunken sailor\n");
+    // The spans with the fewest errors come first
+    let output = stdout_of(&["near", &path, "sailr NEAR/0 drunken", "-e", "1", "-m", "3", "--format", "matches"]);
+    assert_eq!(output, "drunken sail\n".repeat(3));
+
+    let output = spyglass(&["near", &path, "sailor drunken"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("NEAR/k"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn word_search() {
+    let path = temp_path("word_search.ssw");
+    stdout_of(&["index", "-o", &path, "--format", "words", "resources/tests/simple/small.txt"]);
+    assert!(stdout_of(&["info", &path]).contains("Format: words\n"));
+
+    // Words may be separated by a line break and punctuation
+    let output = stdout_of(&["search", &path, "sailor early", "--format", "matches", "--highlight", "none"]);
+    assert_eq!(output, "sailor early\n");
+
+    // A substituted word is one error, and isn't marked character by character
+    let output = stdout_of(&["search", &path, "with a sober sailor", "-e", "1"]);
+    assert_eq!(output,
+               "resources/tests/simple/small.txt:6:with a drunken sailor.\n\
+                resources/tests/simple/small.txt:8:with a drunken sailor.\n\
+                resources/tests/simple/small.txt:9:What should we do with a drunken sailor\n");
+
+    let output = spyglass(&["search", &path, "sailor", "-w"]);
+    assert!(!output.status.success());
+
+    // Analyses use the characters of the texts, as for a standard index
+    let output = stdout_of(&["ngrams", &path, "-n", "2", "--words", "--top", "1"]);
+    assert_eq!(output, "text_name,ngram,count\n,What should,3\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn search_stats() {
+    let path = temp_path("stats.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let output = stdout_of(&["stats", &path, "sailor"]);
+    assert_eq!(output, concat!("    hits    per 10k      chars    lines  name\n",
+                               "       3    112.360        267       11  resources/tests/simple/small.txt\n",
+                               "       3    112.360        267           total (1 of 1 texts with hits)\n"));
+
+    let output = stdout_of(&["stats", &path, "sailxr", "-e", "1", "--lines-per-range", "8", "--format", "json"]);
+    let stats: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(stats["total_hits"], 3);
+    assert_eq!(stats["texts"][0]["line_ranges"][1], serde_json::json!({"start_line": 8, "end_line": 10, "hits": 1}));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reuse_graph() {
+    let path = temp_path("reuse.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/"]);
+
+    // The three copies of ABCD.txt share all of their 154 characters apart
+    // from the sentences shorter than the minimum length, and small.txt
+    // shares nothing. Their repeated lines are shared many times over, but
+    // each character is only counted once.
+    let output = stdout_of(&["reuse", &path, "--format", "csv"]);
+    assert_eq!(output, concat!("text_a,text_b,passages,shared_length\n",
+                               "resources/tests/simple/ABCD.txt,resources/tests/simple/ABCD_duplicate.txt,2,110\n",
+                               "resources/tests/simple/ABCD.txt,resources/tests/simple/ABCD_duplicate2.txt,2,110\n",
+                               "resources/tests/simple/ABCD_duplicate.txt,resources/tests/simple/ABCD_duplicate2.txt,2,110\n"));
+    let output = stdout_of(&["reuse", &path, "--format", "csv", "--min-length", "8"]);
+    assert!(output.contains("resources/tests/simple/ABCD.txt,resources/tests/simple/ABCD_duplicate.txt,1,153\n"));
+
+    let output = stdout_of(&["reuse", &path, "--format", "dot"]);
+    assert!(output.starts_with("graph reuse {\n"));
+    assert!(output.contains("    \"resources/tests/simple/small.txt\";\n"));
+    assert_eq!(output.matches(" -- ").count(), 3);
+    std::fs::remove_file(&path).unwrap();
+
+    let path = temp_path("reuse_segmented.sst");
+    stdout_of(&["index", "-o", &path, "--format", "segmented", "resources/tests/simple/"]);
+    assert!(!spyglass(&["reuse", &path]).status.success());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn ngram_frequencies() {
+    let path = temp_path("ngrams.sst");
+    stdout_of(&["index", "-o", &path, "--format", "mapped", "resources/tests/simple/small.txt"]);
+
+    let output = stdout_of(&["ngrams", &path, "-n", "2", "--words", "--top", "2"]);
+    assert_eq!(output, "text_name,ngram,count\n,What should,3\n,a drunken,3\n");
+    // N-grams containing newlines are quoted
+    let output = stdout_of(&["ngrams", &path, "-n", "7", "--min-count", "3", "--per-text", "--top", "2"]);
+    assert_eq!(output, concat!("text_name,ngram,count\n",
+                               "resources/tests/simple/small.txt,\"\nWhat s\",3\n",
+                               "resources/tests/simple/small.txt, a drun,3\n"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn distinctive_phrases() {
+    let path = temp_path("distinctive.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/large_100/"]);
+
+    let args = ["distinctive", &path, "-a", "resources/tests/large_100/shakespeare.txt",
+                "-b", "resources/tests/large_100/para.txt", "--top", "5"];
+    let output = stdout_of(&args);
+    assert_eq!(output.lines().count(), 6);
+    assert!(output.starts_with("   log-lik group  count a  count b  a per 10k  b per 10k  string\n"));
+
+    let output = stdout_of(&[&args[..], &["--format", "json"]].concat());
+    let phrases: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(phrases.as_array().unwrap().len(), 5);
+    assert_eq!(phrases[0]["favours"], "A");
+
+    let output = spyglass(&["distinctive", &path, "-a", "missing.txt", "-b", "resources/tests/large_100/para.txt"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No text named missing.txt"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn collocations() {
+    let path = temp_path("collocations.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let output = stdout_of(&["collocations", &path, "sailxr", "-e", "1", "--left", "2", "--right", "0", "--top", "2"]);
+    assert_eq!(output, concat!("   count     left    right in index      pmi  t-score  word\n",
+                               "       3        3        0        4    2.615    1.449  a\n",
+                               "       3        3        0        3    3.030    1.520  drunken\n"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn index_formats_and_info() {
+    for format in ["standard", "mapped", "segmented"] {
+        let path = temp_path(&format!("formats_{}.sst", format));
+        stdout_of(&["index", "-o", &path, "--format", format, "resources/tests/simple/"]);
+
+        let output = stdout_of(&["info", &path]);
+        assert!(output.contains("Texts: 4"));
+        assert!(output.contains("ABCD.txt"));
+
+        let output = stdout_of(&["search", &path, "ABCDEFGHIJ", "-m", "3"]);
+        assert_eq!(output.lines().count(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn append_to_segmented() {
+    let path = temp_path("append.sst");
+    stdout_of(&["index", "-o", &path, "--format", "segmented", "resources/tests/simple/small.txt"]);
+    stdout_of(&["index", "-o", &path, "--append", "resources/tests/simple/ABCD.txt"]);
+
+    let output = stdout_of(&["info", &path]);
+    assert!(output.contains("Texts: 2"));
+    let output = stdout_of(&["search", &path, "ABCDEFGHIJ", "-m", "1"]);
+    assert!(output.starts_with("resources/tests/simple/ABCD.txt:1:"));
+
+    // Boolean and proximity queries find the same as in a single trie,
+    // including in texts of later segments
+    let standard_path = temp_path("append_standard.sst");
+    stdout_of(&["index", "-o", &standard_path, "resources/tests/simple/small.txt", "resources/tests/simple/ABCD.txt"]);
+    for args in [vec!["query", "drunken AND NOT What", "--scope", "line"],
+                 vec!["query", "ABCDEFGHIJ AND NOT sailor"],
+                 vec!["near", "sailor NEAR/2 What", "--unit", "characters"],
+                 vec!["near", "HIJ BEFORE/1 ABC"]] {
+        let run = |index: &str| stdout_of(&[&[args[0], index], &args[1 ..]].concat());
+        let output = run(&path);
+        assert!(!output.is_empty());
+        assert_eq!(output, run(&standard_path));
+    }
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&standard_path).unwrap();
+}
+
+#[test]
+fn missing_index_is_an_error() {
+    let output = spyglass(&["search", "resources/tests/does_not_exist.sst", "pattern"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does_not_exist.sst"));
+}
+
+#[test]
+fn interactive_shell() {
+    let path = temp_path("shell.sst");
+    let home = temp_path("shell_home");
+    std::fs::create_dir_all(&home).unwrap();
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spyglass"))
+        .args(["shell", &path])
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap()
+        .write_all(b":page 1\nsentences\n\n:format matches\n:errors 1\nsentencxs\n:settings\n:quit\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    // Results are shown a page at a time
+    assert_eq!(output.matches("2 matches in").count(), 2);
+    assert_eq!(output.matches("(1 more matches, press Enter to show more)").count(), 2);
+    assert_eq!(output.matches("small.txt:2:and sentences.").count(), 2);
+    // Output is not to a terminal, so errors are marked with brackets
+    assert!(output.contains("\nsentenc[e]s\n"));
+    assert!(output.contains("errors: 1\n"));
+    assert!(output.contains("format: matches\n"));
+
+    // Queries are saved to the history
+    let history = std::fs::read_to_string(format!("{}/.spyglass_history", home)).unwrap();
+    assert!(history.contains("sentencxs"));
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn shell_searches_for_colons() {
+    let text_path = temp_path("shell_colons.txt");
+    let path = temp_path("shell_colons.sst");
+    let home = temp_path("shell_colons_home");
+    std::fs::create_dir_all(&home).unwrap();
+    std::fs::write(&text_path, "Meet at 10:30, not 11:30\n:errors begins this line\n").unwrap();
+    stdout_of(&["index", "-o", &path, &text_path]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spyglass"))
+        .args(["shell", &path])
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap()
+        .write_all(b"::30\n:search :errors\n:search\n:help\n:quit\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.contains("2 matches in"));
+    assert!(output.contains("1 matches in"));
+    assert!(output.contains("Expected a pattern to search for"));
+    assert!(output.contains("::PATTERN"));
+
+    for file in [text_path, path] {
+        std::fs::remove_file(&file).unwrap();
+    }
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+/// Send a GET request and return the status line and body of the response
+fn http_get(address: &str, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, address).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response.lines().next().unwrap().to_string();
+    let body = response.split("\r\n\r\n").nth(1).unwrap().to_string();
+    (status, body)
+}
+
+#[test]
+fn http_server() {
+    let path = temp_path("serve.sst");
+    stdout_of(&["index", "-o", &path, "resources/tests/simple/small.txt"]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spyglass"))
+        .args(["serve", &path, "--address", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first_line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first_line).unwrap();
+    let address = first_line.trim().trim_start_matches("Listening on http://").to_string();
+
+    let (status, body) = http_get(&address, "/texts");
+    assert!(status.contains("200"));
+    assert!(body.starts_with("[{\"name\":\"resources/tests/simple/small.txt\""));
+
+    let (status, body) = http_get(&address, "/search?q=sentences&limit=1");
+    assert!(status.contains("200"));
+    assert!(body.contains("\"total\":2"));
+    assert_eq!(body.matches("\"index_in_str\"").count(), 1);
+
+    let (_, body) = http_get(&address, "/search?q=sent%3Fnce*&wildcard=true&offset=1");
+    assert!(body.contains("\"total\":4"));
+    assert!(body.contains("\"offset\":1"));
+    assert_eq!(body.matches("\"index_in_str\"").count(), 3);
+
+    let (_, body) = http_get(&address, "/search?q=sentencxs&errors=1");
+    assert!(body.contains("\"total\":2"));
+
+    let (status, body) = http_get(&address, "/snippet?text_index=0&index_in_str=39&length=9&start_line=1&end_line=1&context=1");
+    assert!(status.contains("200"));
+    assert!(body.contains("\"matching\":\"sentences\""));
+    assert!(body.contains("This is a file."));
+
+    let (status, _) = http_get(&address, "/snippet?text_index=5&index_in_str=0&length=1&start_line=0&end_line=0");
+    assert!(status.contains("400"));
+    // The lines must agree with the position of the match
+    let (status, body) = http_get(&address, "/snippet?text_index=0&index_in_str=0&length=1&start_line=2&end_line=2");
+    assert!(status.contains("400"));
+    assert!(body.contains("start_line"));
+    let (status, body) = http_get(&address, "/snippet?text_index=0&index_in_str=39&length=9&context=1");
+    assert!(status.contains("200"));
+    assert!(body.contains("This is a file."));
+    let (status, _) = http_get(&address, "/snippet?text_index=0&index_in_str=39&length=0");
+    assert!(status.contains("400"));
+    let (status, body) = http_get(&address, "/search?errors=1");
+    assert!(status.contains("400"));
+    assert!(body.contains("Missing parameter q"));
+    let (status, _) = http_get(&address, "/unknown");
+    assert!(status.contains("404"));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
@@ -3,7 +3,15 @@ use std::collections::HashMap;
 
 use utilities;
 
-use spyglass::{Match,SuffixTrie};
+use spyglass::{detect_index_kind,load_index,IndexKind,Match,SuffixTrie};
+use spyglass::{align_match,concordance,render_alignment,sort_concordance,
+               write_csv,write_json_lines,BooleanOptions,BooleanQuery,
+               BuildOptions,CollocationOptions,CollocationSort,ConcordanceSort,
+               DistanceUnit,DistinctiveOptions,HighlightStyle,MatchRecord,
+               IndexHeader,MappedSuffixTrie,NgramOptions,NgramTable,NgramUnit,
+               Normaliser,ProximityOptions,ProximityQuery,ReuseEdge,SearchIndex,
+               SearchStats,SeedOptions,SegmentedIndex,SharedSubstring,Subcorpus,
+               TextStore,TrieAnalysis,WordIndex,FORMAT_VERSION};
 
 fn compare_match_indices(matches: Vec<Match>, indices: Vec<usize>) {
     let match_indices: Vec<usize> = matches.iter().map(|l| l.index_in_str).collect();
@@ -14,6 +22,15 @@ fn compare_matches(mut expected: Vec<Match>, mut matches: Vec<Match>) {
     expected.sort();
     matches.sort();
     assert_eq!(expected, matches);
+    // Equality of matches only compares where they are, so check their
+    // positions separately
+    for (expected_match, match_obj) in expected.iter().zip(matches.iter()) {
+        assert_eq!((expected_match.start_line, expected_match.start_column, expected_match.start_byte),
+                   (match_obj.start_line, match_obj.start_column, match_obj.start_byte));
+        assert_eq!(expected_match.sentence, match_obj.sentence);
+        assert_eq!((expected_match.end_line, expected_match.end_column, expected_match.end_byte),
+                   (match_obj.end_line, match_obj.end_column, match_obj.end_byte));
+    }
 }
 
 #[test]
@@ -147,27 +164,53 @@ fn matches_from_directory() {
     let mut expected_e_del: Vec<Match> = vec![];
     let mut expected_e_ins: Vec<Match> = vec![];
     let mut expected_h: Vec<Match> = vec![];
+    // Byte offsets of the starts of lines in the original files, which
+    // include the sentence splitters
+    let line_start_bytes = [0, 30, 60, 90, 128, 158, 188];
+    // Sentences containing the start and end of each line. Lines 3 and 6
+    // end with a sentence splitter.
+    let first_sentences = [0, 1, 2, 3, 5, 6, 7];
+    let second_sentences = [1, 2, 3, 4, 6, 7, 8];
     for text_index in vec![0, 1, 2] {
-        for line in 0..7 {
+        for (line, &line_start_byte) in line_start_bytes.iter().enumerate() {
             let first_match_a = Match {
                 text_index,
                 index_in_str: 0 + 22*line,
                 start_line: line,
                 end_line: line,
+                start_column: 0,
+                end_column: 6,
                 length: 6,
                 errors: 0,
+                sentence: Some(first_sentences[line]),
+                start_byte: Some(line_start_byte),
+                end_byte: Some(line_start_byte + 6),
             };
             let second_match_a = Match {
                 index_in_str: 11 + 22*line,
+                start_column: 11,
+                end_column: 17,
+                sentence: Some(second_sentences[line]),
+                start_byte: Some(line_start_byte + 19),
+                end_byte: Some(line_start_byte + 25),
                 ..first_match_a
             };
 
             let first_match_e = Match {
                 index_in_str: 4 + 22*line,
+                start_column: 4,
+                end_column: 10,
+                start_byte: Some(line_start_byte + 4),
+                end_byte: Some(line_start_byte + 10),
                 ..first_match_a
             };
             let second_match_e = Match {
                 index_in_str: 15 + 22*line,
+                start_column: 15,
+                end_column: 21,
+                sentence: Some(second_sentences[line]),
+                start_byte: Some(line_start_byte + 23),
+                end_byte: Some(line_start_byte + 29),
                 ..first_match_a
             };
             let first_match_e_error = Match {
@@ -203,8 +246,13 @@ fn matches_from_directory() {
                 index_in_str: 18 + 22*line,
                 start_line: line,
                 end_line: line + 1,
+                start_column: 18,
+                end_column: 1,
                 length: 5,
                 errors: 0,
+                sentence: Some(second_sentences[line]),
+                start_byte: Some(line_start_bytes[line] + 26),
+                end_byte: Some(line_start_bytes[line + 1] + 1),
             };
             expected_h.push(match_h);
         }
@@ -284,14 +332,27 @@ fn match_dodgy_characters() {
         length: 6,
         start_line: 0,
         end_line: 0,
+        start_column: 9,
+        end_column: 15,
         errors: 0,
+        sentence: Some(0),
+        start_byte: Some(11),
+        end_byte: Some(17),
     };
     let alf_match2 = Match {
         index_in_str: 18,
+        start_column: 18,
+        end_column: 24,
+        start_byte: Some(20),
+        end_byte: Some(26),
         ..alf_match
     };
     let alf_match4 = Match {
         index_in_str: 34,
+        start_column: 34,
+        end_column: 40,
+        start_byte: Some(36),
+        end_byte: Some(42),
         ..alf_match
     };
     let alf_expected = vec![alf_match.clone(), alf_match2.clone(), alf_match4.clone()];
@@ -302,21 +363,871 @@ fn match_dodgy_characters() {
     let alfric_match = Match {
         errors: 2,
         length: 11,
+        end_column: 20,
+        end_byte: Some(22),
         ..alf_match
     };
     let alfric_match2 = Match {
         index_in_str: 18,
+        start_column: 18,
+        end_column: 29,
+        start_byte: Some(20),
+        end_byte: Some(31),
         ..alfric_match
     };
     let alfric_match3 = Match {
         index_in_str: 27,
         length: 9,
+        start_column: 27,
+        end_column: 36,
+        start_byte: Some(29),
+        end_byte: Some(38),
         ..alfric_match
     };
     let alfric_match4 = Match {
         index_in_str: 34,
+        start_column: 34,
+        end_column: 45,
+        start_byte: Some(36),
+        end_byte: Some(47),
         ..alfric_match
     };
     let alfric_expected = vec![alfric_match, alfric_match2, alfric_match3, alfric_match4];
     compare_matches(alfric_expected, alfric_matches);
 }
+
+#[test]
+fn merge_matches_union() {
+    utilities::init_testing();
+    let mut expected = SuffixTrie::from_file("resources/tests/simple/ABCD.txt").unwrap();
+    expected.add_file("resources/tests/simple/small.txt").unwrap();
+
+    let trie = SuffixTrie::from_file("resources/tests/simple/ABCD.txt").unwrap();
+    let other = SuffixTrie::from_file("resources/tests/simple/small.txt").unwrap();
+    let merged = trie.merge(other).unwrap();
+
+    assert_eq!(merged.get_text_names(), expected.get_text_names());
+    for pattern in ["ABCDEF", "HIJ\nA", "drunken", "early", "e", "the "] {
+        compare_matches(expected.find_exact(pattern), merged.find_exact(pattern));
+        compare_matches(expected.find_edit_distance(pattern, 1),
+                        merged.find_edit_distance(pattern, 1));
+    }
+
+    let matches = merged.find_exact("drunken");
+    assert_eq!(matches.len(), 3);
+    for match_obj in matches {
+        assert_eq!(merged.get_strings_of_match(&match_obj, 1),
+                   expected.get_strings_of_match(&match_obj, 1));
+    }
+}
+
+#[test]
+fn remove_and_replace_text() {
+    utilities::init_testing();
+    let mut expected = SuffixTrie::from_file("resources/tests/simple/ABCD.txt").unwrap();
+    expected.add_file("resources/tests/simple/small.txt").unwrap();
+
+    let mut trie = SuffixTrie::from_directory("./resources/tests/simple/").unwrap();
+    trie.remove_text("./resources/tests/simple/ABCD_duplicate.txt").unwrap();
+    trie.remove_text("./resources/tests/simple/ABCD_duplicate2.txt").unwrap();
+    let mut names = trie.get_text_names();
+    names.iter_mut().for_each(|name| *name = name.replace("./", ""));
+    assert_eq!(names, expected.get_text_names());
+
+    for pattern in ["ABCDEF", "HIJ\nA", "drunken", "early", "e"] {
+        compare_matches(expected.find_exact(pattern), trie.find_exact(pattern));
+        compare_matches(expected.find_edit_distance(pattern, 1),
+                        trie.find_edit_distance(pattern, 1));
+    }
+
+    trie.compact_str_storage();
+    let matches = trie.find_exact("drunken");
+    assert_eq!(matches.len(), 3);
+    for match_obj in matches {
+        assert_eq!(trie.get_strings_of_match(&match_obj, 1),
+                   expected.get_strings_of_match(&match_obj, 1));
+    }
+
+    trie.replace_text("./resources/tests/simple/ABCD.txt", "ABCDEFGHIJ drunken").unwrap();
+    assert_eq!(trie.get_text_names().len(), 2);
+    compare_match_indices(trie.find_exact("ABCDEF"), vec![0]);
+    assert_eq!(trie.find_exact("drunken").len(), 4);
+    assert!(trie.remove_text("not a text").is_err());
+}
+
+fn temp_path(name: &str) -> String {
+    let mut path = std::env::temp_dir();
+    path.push(format!("spyglass_{}_{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn save_and_load() {
+    utilities::init_testing();
+    let options = BuildOptions {
+        normaliser: Normaliser::DeunicodeLowercase,
+        ..BuildOptions::default()
+    };
+    let mut trie = SuffixTrie::empty_with_options(options.clone());
+    trie.add_file("resources/tests/simple/small.txt").unwrap();
+    let path = temp_path("save_and_load.sst");
+    trie.save(&path).unwrap();
+
+    let header = IndexHeader::read(&path).unwrap();
+    assert_eq!(header.version, FORMAT_VERSION);
+    assert_eq!(header.options, options);
+
+    let loaded = SuffixTrie::load(&path).unwrap();
+    assert_eq!(loaded.get_options(), &options);
+    assert_eq!(loaded.get_text_names(), trie.get_text_names());
+    compare_matches(trie.find_exact("DRUNKEN"), loaded.find_exact("drunken"));
+    assert_eq!(loaded.find_exact("Drunken").len(), 3);
+
+    // Corrupt the last byte of the payload
+    let mut contents = std::fs::read(&path).unwrap();
+    let last = contents.len() - 1;
+    contents[last] ^= 0xff;
+    let error = SuffixTrie::load_from_reader(&contents[..]).unwrap_err();
+    assert!(error.to_string().contains("checksum"));
+
+    // Claim to be from a future version
+    let mut contents = std::fs::read(&path).unwrap();
+    contents[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    let error = SuffixTrie::load_from_reader(&contents[..]).unwrap_err();
+    assert!(error.to_string().contains("Unsupported index format version"));
+
+    let error = SuffixTrie::load_from_reader(&b"not an index"[..]).unwrap_err();
+    assert!(error.to_string().contains("Not a spyglass index"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn search_mapped_index() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("./resources/tests/large_100/").unwrap();
+    let path = temp_path("search_mapped_index.sstm");
+    trie.save_mapped(&path).unwrap();
+
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+    assert_eq!(mapped.get_text_names(), trie.get_text_names());
+    assert_eq!(mapped.get_options(), trie.get_options());
+    for pattern in ["ell", "that", "loss of Eden", "Ælfred", "not present in any text"] {
+        let matches = mapped.find_exact(pattern);
+        compare_matches(trie.find_exact(pattern), matches.clone());
+        compare_matches(trie.find_edit_distance(pattern, 1),
+                        mapped.find_edit_distance(pattern, 1));
+        assert_eq!(mapped.count_exact(pattern), trie.count_exact(pattern));
+        for match_obj in matches {
+            assert_eq!(mapped.get_strings_of_match(&match_obj, 2),
+                       trie.get_strings_of_match(&match_obj, 2));
+        }
+    }
+
+    // Truncated files and sizes which don't fit in the file are errors
+    // rather than panics
+    let bytes = std::fs::read(&path).unwrap();
+    let mut corrupt_files = vec![bytes[.. bytes.len() - 1].to_vec(), bytes[.. 40].to_vec()];
+    for size in [u64::MAX / 2, bytes.len() as u64] {
+        let mut corrupt = bytes.clone();
+        corrupt[12 .. 20].copy_from_slice(&size.to_le_bytes());
+        corrupt_files.push(corrupt);
+    }
+    for corrupt in corrupt_files {
+        std::fs::write(&path, corrupt).unwrap();
+        assert_eq!(MappedSuffixTrie::open(&path).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    // A saved index in the standard format can't be opened as a mapped index
+    trie.save(&path).unwrap();
+    assert!(MappedSuffixTrie::open(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn save_with_external_texts() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("./resources/tests/simple/").unwrap();
+    let embedded_path = temp_path("external_texts_embedded.sst");
+    let path = temp_path("external_texts.sst");
+    let blob_path = temp_path("external_texts.txt");
+    // A relative blob path is relative to the directory of the index
+    let relative_blob = format!("spyglass_{}_external_texts_relative.txt", std::process::id());
+    trie.save(&embedded_path).unwrap();
+
+    for text_store in [TextStore::SourceFiles, TextStore::BlobFile(relative_blob.clone()),
+                       TextStore::BlobFile(blob_path.clone())] {
+        trie.save_with_text_store(&path, &text_store).unwrap();
+        let header = IndexHeader::read(&path).unwrap();
+        assert_eq!(header.text_store, text_store);
+        if text_store == TextStore::SourceFiles {
+            // Source files are found relative to the index rather than the
+            // current directory
+            let index_dir = std::path::Path::new(&path).parent().unwrap();
+            for (source_path, name) in header.source_paths.iter().zip(trie.get_text_names()) {
+                assert!(std::path::Path::new(source_path).is_relative());
+                assert_eq!(std::fs::canonicalize(index_dir.join(source_path)).unwrap(),
+                           std::fs::canonicalize(name).unwrap());
+            }
+        }
+        // Only the structure of the index is stored in the index file
+        let embedded_size = std::fs::metadata(&embedded_path).unwrap().len();
+        assert!(std::fs::metadata(&path).unwrap().len() < embedded_size);
+
+        let loaded = SuffixTrie::load(&path).unwrap();
+        let matches = loaded.find_exact("drunken");
+        compare_matches(trie.find_exact("drunken"), matches.clone());
+        for match_obj in matches {
+            assert_eq!(loaded.get_strings_of_match(&match_obj, 1),
+                       trie.get_strings_of_match(&match_obj, 1));
+        }
+    }
+
+    let relative_blob = std::env::temp_dir().join(relative_blob);
+    assert!(relative_blob.exists());
+
+    // A text file which has changed since the index was loaded is detected
+    // by verifying the store, and one which changed before by loading
+    let loaded = SuffixTrie::load(&path).unwrap();
+    std::fs::write(&blob_path, "changed").unwrap();
+    let error = loaded.verify_text_store().unwrap_err();
+    assert!(error.to_string().contains("has changed"));
+    let error = SuffixTrie::load(&path).unwrap_err();
+    assert!(error.to_string().contains("has changed"));
+    std::fs::remove_file(&blob_path).unwrap();
+    assert!(SuffixTrie::load(&path).is_err());
+    std::fs::write(&blob_path, "").unwrap();
+    std::fs::remove_file(&relative_blob).unwrap();
+
+    // Texts not read from files can't be stored as source files
+    let trie = SuffixTrie::new("abc");
+    assert!(trie.save_with_text_store(&path, &TextStore::SourceFiles).is_err());
+
+    for file in [embedded_path, path, blob_path] {
+        std::fs::remove_file(&file).unwrap();
+    }
+}
+
+#[test]
+fn append_to_segmented_index() {
+    utilities::init_testing();
+    let expected = SuffixTrie::from_directory("./resources/tests/simple/").unwrap();
+    let mut paths = expected.get_text_names();
+    let path = temp_path("segmented.sstseg");
+
+    let first = SuffixTrie::from_file(&paths.remove(0)).unwrap();
+    let mut index = SegmentedIndex::create(&path, first).unwrap();
+    for text_path in paths.iter() {
+        let size_before = std::fs::metadata(&path).unwrap().len();
+        index.append(SuffixTrie::from_file(text_path).unwrap()).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > size_before);
+    }
+
+    let mut index = SegmentedIndex::open(&path).unwrap();
+    assert_eq!(index.num_segments(), 4);
+    assert_eq!(index.get_text_names(), expected.get_text_names());
+    for compacted in [false, true] {
+        if compacted {
+            // The index keeps its segments if it can't be compacted
+            let blocked = format!("{}.compacting", path);
+            std::fs::create_dir(&blocked).unwrap();
+            assert!(index.compact().is_err());
+            std::fs::remove_dir(&blocked).unwrap();
+            assert_eq!(index.num_segments(), 4);
+            assert_eq!(index.get_text_names(), expected.get_text_names());
+
+            index.compact().unwrap();
+            assert_eq!(index.num_segments(), 1);
+            index = SegmentedIndex::open(&path).unwrap();
+            assert_eq!(index.num_segments(), 1);
+        }
+        for pattern in ["ABCDEF", "HIJ\nA", "drunken", "e"] {
+            let matches = index.find_exact(pattern);
+            compare_matches(expected.find_exact(pattern), matches.clone());
+            compare_matches(expected.find_edit_distance(pattern, 1),
+                            index.find_edit_distance(pattern, 1));
+            assert_eq!(index.count_exact(pattern), expected.count_exact(pattern));
+            for match_obj in matches {
+                assert_eq!(index.get_strings_of_match(&match_obj, 1),
+                           expected.get_strings_of_match(&match_obj, 1));
+                // A match can be rebuilt from its position alone
+                let rebuilt = index.get_match_at(match_obj.text_index, match_obj.index_in_str, match_obj.length);
+                assert_eq!(rebuilt.as_ref(), Some(&match_obj));
+                assert_eq!(rebuilt.unwrap().start_line, match_obj.start_line);
+            }
+            assert_eq!(index.get_match_at(4, 0, 1), None);
+            assert_eq!(index.get_match_at(0, 0, 0), None);
+        }
+    }
+
+    let other_options = BuildOptions {
+        normaliser: Normaliser::DeunicodeLowercase,
+        ..BuildOptions::default()
+    };
+    assert!(index.append(SuffixTrie::empty_with_options(other_options)).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn find_wildcard() {
+    utilities::init_testing();
+    let trie = SuffixTrie::new("test text tent toast t't");
+    println!("Result is {:#?}", trie);
+
+    compare_match_indices(trie.find_wildcard("te?t", 0), vec![0, 5, 10]);
+    // Without wildcards the ? is matched literally
+    compare_match_indices(trie.find_edit_distance("te?t", 0), vec![]);
+    compare_match_indices(trie.find_wildcard("t*t", 0), vec![0, 5, 10, 15]);
+    // A trailing wildcard matches each possible length of word
+    let lengths: Vec<usize> = trie.find_wildcard("to*", 0).iter().map(|m| m.length).collect();
+    assert_eq!(lengths, vec![2, 3, 4, 5]);
+    // Errors are allowed in the rest of the pattern
+    let indices: Vec<usize> = trie.find_wildcard("tw*t", 1).iter().map(|m| m.index_in_str).collect();
+    for index in [0, 5, 10, 15] {
+        assert!(indices.contains(&index));
+    }
+
+    let matches = trie.find_wildcard("t*t", 0);
+    let lengths: Vec<usize> = matches.iter().map(|m| m.length).collect();
+    assert_eq!(lengths, vec![4, 4, 4, 5]);
+}
+
+#[test]
+fn export_matches() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("first", "An apple, a day\nkeeps \"the\" doctor away");
+    let matches = trie.find_exact("doctor");
+
+    let record = MatchRecord::new(&trie, &matches[0], 0);
+    assert_eq!(record, MatchRecord {
+        text_name: "first".to_string(),
+        start_line: 1,
+        end_line: 1,
+        start_column: 12,
+        end_column: 18,
+        offset: 28,
+        start_byte: Some(28),
+        end_byte: Some(34),
+        length: 6,
+        errors: 0,
+        matched: "doctor".to_string(),
+        before: "keeps \"the\" ".to_string(),
+        after: " away".to_string(),
+    });
+
+    let mut output = vec![];
+    write_json_lines(&mut output, &trie, &matches, 1).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "{\"text_name\":\"first\",\"start_line\":1,\"end_line\":1,\"start_column\":12,\"end_column\":18,\"offset\":28,\
+               \"start_byte\":28,\"end_byte\":34,\"length\":6,\"errors\":0,\"matched\":\"doctor\",\"before\":\"An apple, a day\\nkeeps \\\"the\\\" \",\
+               \"after\":\" away\"}\n");
+
+    let mut output = vec![];
+    write_csv(&mut output, &trie, &matches, 0).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "text_name,start_line,end_line,start_column,end_column,offset,start_byte,end_byte,length,errors,matched,before,after\n\
+               first,1,1,12,18,28,28,34,6,0,doctor,\"keeps \"\"the\"\" \", away\n");
+
+    let mut output = vec![];
+    write_csv(&mut output, &trie, &[], 0).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "text_name,start_line,end_line,start_column,end_column,offset,start_byte,end_byte,length,errors,matched,before,after\n");
+}
+
+#[test]
+fn sentence_context() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("first", "One fish.<<STOP>> Two fish.<<STOP>> Red\nfish.<<STOP>> Blue fish.");
+    let matches = trie.find_exact("Red");
+    assert_eq!(matches[0].sentence, Some(2));
+
+    let (before, matching, after) = trie.get_sentences_of_match(&matches[0], 0);
+    assert_eq!((before.as_str(), matching.as_str(), after.as_str()), (" ", "Red", "\nfish."));
+    let (before, _, after) = trie.get_sentences_of_match(&matches[0], 1);
+    assert_eq!((before.as_str(), after.as_str()), (" Two fish. ", "\nfish. Blue fish."));
+    let (before, _, after) = trie.get_sentences_of_match(&matches[0], 5);
+    assert_eq!((before.as_str(), after.as_str()), ("One fish. Two fish. ", "\nfish. Blue fish."));
+
+    let matches = trie.find_exact("One");
+    assert_eq!(matches[0].sentence, Some(0));
+    let (before, _, after) = trie.get_sentences_of_match(&matches[0], 1);
+    assert_eq!((before.as_str(), after.as_str()), ("", " fish. Two fish."));
+
+    // Matches rebuilt from a position may cross sentences
+    let crossing = trie.get_match_at(0, 4, 10).unwrap();
+    let (before, matching, after) = trie.get_sentences_of_match(&crossing, 0);
+    assert_eq!((before.as_str(), matching.as_str(), after.as_str()), ("One ", "fish. Two ", "fish."));
+    let (before, _, after) = trie.get_sentences_of_match(&crossing, 1);
+    assert_eq!((before.as_str(), after.as_str()), ("One ", "fish. Red\nfish."));
+    let to_end = trie.get_match_at(0, 4, 15).unwrap();
+    assert_eq!(trie.get_sentences_of_match(&to_end, 0).2, "");
+
+    // Sentences are kept when the index is saved
+    let path = temp_path("sentence_context.smap");
+    trie.save_mapped(&path).unwrap();
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+    let matches = mapped.find_exact("Red");
+    assert_eq!(matches[0].sentence, Some(2));
+    assert_eq!(mapped.get_sentences_of_match(&matches[0], 1), trie.get_sentences_of_match(&matches[0], 1));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn concordance_lines() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("first", "fish swim\nin the sea\nand some fish\nlive in\trivers, but no fish fly");
+    let matches = trie.find_exact("fish");
+    let mut lines = concordance(&trie, &matches, 12);
+    sort_concordance(&mut lines, ConcordanceSort::Position);
+
+    // Context stops at the start and end of the text, and spans lines
+    let contexts: Vec<(&str, &str, &str)> = lines.iter()
+        .map(|line| (line.left.as_str(), line.keyword.as_str(), line.right.as_str()))
+        .collect();
+    assert_eq!(contexts, vec![("", "fish", " swim in the"),
+                              ("ea and some ", "fish", " live in riv"),
+                              ("ers, but no ", "fish", " fly")]);
+    assert_eq!(lines[0].format(12), "              fish   swim in the");
+
+    sort_concordance(&mut lines, ConcordanceSort::Right);
+    let rights: Vec<&str> = lines.iter().map(|line| line.right.as_str()).collect();
+    assert_eq!(rights, vec![" fly", " live in riv", " swim in the"]);
+}
+
+#[test]
+fn highlight_errors() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("first", "He was wracked with guilt and rack\u{2019}d with pain");
+    let render = |pattern: &str, m: &Match, ignored: &HashMap<char, bool>| {
+        render_alignment(&align_match(&trie, pattern, m, ignored, false), HighlightStyle::Brackets)
+    };
+
+    let matches = trie.find_edit_distance("wricked", 1);
+    assert_eq!(render("wricked", &matches[0], &HashMap::new()), "wr[a]cked");
+
+    // The pattern is normalised in the same way as the text, and ignorable
+    // characters aren't marked as errors
+    let ignored: HashMap<char, bool> = [('\'', true)].iter().cloned().collect();
+    let matches = trie.find_edit_distance_ignore("wrack\u{2019}d", 1, ignored.clone());
+    let rendered: Vec<String> = matches.iter().take(4).map(|m| render("wrack\u{2019}d", m, &ignored)).collect();
+    assert_eq!(rendered, vec!["wracked", "wracke<d>", "<w>racked", "<w>rack'd"]);
+}
+
+#[test]
+fn search_stats() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("./resources/tests/simple/").unwrap();
+    for pattern in ["drunken", "ABCDEF", "e", "not present"] {
+        // Counting leaves gives the same counts as finding the matches
+        let matches = trie.find_exact(pattern);
+        let counts = trie.count_exact(pattern);
+        assert_eq!(counts.iter().sum::<usize>(), matches.len());
+        assert_eq!(SearchStats::from_exact_counts(&trie, pattern), SearchStats::from_matches(&trie, &matches));
+    }
+
+    let matches = trie.find_exact("drunken sailor");
+    let mut stats = SearchStats::from_matches(&trie, &matches);
+    assert_eq!(stats.total_hits, 3);
+    assert_eq!(stats.texts_with_hits, 1);
+    let small = &stats.texts[3];
+    assert_eq!((small.text_name.as_str(), small.hits), ("./resources/tests/simple/small.txt", 3));
+    assert_eq!(small.hits_per_10k_chars, 3.0 * 10000.0 / small.num_chars as f64);
+
+    stats.add_line_ranges(&matches, 4);
+    let hits: Vec<usize> = stats.texts[3].line_ranges.iter().map(|range| range.hits).collect();
+    assert_eq!(hits, vec![0, 2, 1]);
+    assert_eq!(stats.texts[3].line_ranges[2].end_line, 10);
+}
+
+#[test]
+fn repeats() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_file("./resources/tests/simple/small.txt").unwrap();
+    let text: Vec<char> = trie.get_strings_of_match(&trie.find_exact("This")[0], 20).2.chars().collect();
+    let text: String = "This".chars().chain(text).collect();
+
+    // Check against a brute force search of every substring, counting
+    // overlapping occurrences
+    let occurrences = |string: &str| (0 .. text.len()).filter(|start| text[*start ..].starts_with(string)).count();
+    let longest = trie.longest_repeats();
+    let longest_length = longest[0].string.chars().count();
+    assert!(longest.iter().all(|repeat| repeat.count() == occurrences(&repeat.string)));
+    for start in 0 .. text.len() - longest_length {
+        assert!(occurrences(&text[start ..= start + longest_length]) < 2);
+    }
+    assert_eq!(longest[0].string, ".\nWhat should we do\nwith a drunken sailor.\nWhat should we do");
+
+    let maximal = trie.maximal_repeats(4);
+    for repeat in maximal.iter() {
+        assert!(repeat.string.chars().count() >= 4);
+        assert_eq!(repeat.count(), occurrences(&repeat.string));
+        compare_matches(trie.find_exact(&repeat.string), repeat.occurrences.clone());
+    }
+    let supermaximal = trie.supermaximal_repeats(4);
+    assert!(supermaximal.len() < maximal.len());
+    for repeat in supermaximal.iter() {
+        assert!(maximal.contains(repeat));
+        // No other maximal repeat contains a supermaximal one
+        assert!(maximal.iter().all(|other| other == repeat || !other.string.contains(&repeat.string)));
+    }
+
+    // The same repeats are found in a mapped index
+    let path = temp_path("repeats.smap");
+    trie.save_mapped(&path).unwrap();
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+    assert_eq!(mapped.supermaximal_repeats(4), supermaximal);
+    assert_eq!(mapped.longest_repeats(), longest);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shared_passages() {
+    utilities::init_testing();
+    let text_a = "the knight rode forth into the forest and found a well of water";
+    let text_b = "then the knyght rode forth into the forest and found a wel of water, the end";
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("a", text_a);
+    trie.add_sentences_from_text("b", text_b);
+
+    // Compare with every pair of positions which can't be extended
+    let (a, b): (Vec<char>, Vec<char>) = (text_a.chars().collect(), text_b.chars().collect());
+    let mut expected = vec![];
+    for i in 0 .. a.len() {
+        for j in 0 .. b.len() {
+            let length = a[i ..].iter().zip(b[j ..].iter()).take_while(|(x, y)| x == y).count();
+            let left_maximal = i == 0 || j == 0 || a[i - 1] != b[j - 1];
+            if length >= 4 && left_maximal {
+                expected.push(SharedSubstring { start_a: i, start_b: j, length });
+            }
+        }
+    }
+    expected.sort();
+    assert_eq!(trie.find_shared_substrings("a", "b", 4).unwrap(), expected);
+
+    // The changed spellings split the passage into three pieces
+    let passages = trie.find_shared_passages("a", "b", 6, 3).unwrap();
+    assert_eq!(passages.len(), 1);
+    let passage = &passages[0];
+    assert_eq!(passage.pieces.len(), 3);
+    assert_eq!((passage.location_a.index_in_str, passage.location_a.length), (0, a.len()));
+    assert_eq!((passage.location_b.index_in_str, passage.location_b.length), (5, a.len() - 1));
+    assert_eq!(passage.shared_length, a.len() - 1);
+
+    // Shared substrings never cross sentence boundaries, but passages do
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("a", "one two three.<<STOP>> four five six.");
+    trie.add_sentences_from_text("b", "zero one two three. four five six. seven");
+    let passages = trie.find_shared_passages("a", "b", 5, 0).unwrap();
+    assert_eq!(passages.len(), 1);
+    assert_eq!(passages[0].pieces.len(), 2);
+    assert_eq!(passages[0].location_a.length, "one two three. four five six.".len());
+
+    assert!(trie.find_shared_passages("a", "missing", 5, 0).is_err());
+}
+
+#[test]
+fn high_scoring_pairs() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("a", "prologue. the knight rode forth into the forest and found a well of water");
+    trie.add_sentences_from_text("b", "then the knyght rode forth into the forest and found a wel of water, the end");
+
+    // A single seed is extended across both changed spellings
+    let options = SeedOptions { seed_length: 10, min_score: 20, ..SeedOptions::default() };
+    let pairs = trie.find_high_scoring_pairs("a", "b", &options).unwrap();
+    assert_eq!(pairs.len(), 1);
+    let pair = &pairs[0];
+    assert_eq!(pair.string_a, " the knight rode forth into the forest and found a well of water");
+    assert_eq!(pair.string_b, " the knyght rode forth into the forest and found a wel of water");
+    // 62 matching characters, a mismatch and a gap
+    assert_eq!(pair.score, 62 - 1 - 2);
+    assert_eq!(pair.location_a.errors, 2);
+    assert_eq!(pair.location_b.index_in_str, 4);
+    let (aligned_a, aligned_b) = pair.aligned_strings('-');
+    assert_eq!(aligned_a, pair.string_a);
+    assert_eq!(aligned_b, " the knyght rode forth into the forest and found a wel- of water");
+
+    // Seeds longer than any shared substring find nothing
+    let options = SeedOptions { seed_length: 50, ..SeedOptions::default() };
+    assert!(trie.find_high_scoring_pairs("a", "b", &options).unwrap().is_empty());
+    assert!(trie.find_high_scoring_pairs("a", "missing", &options).is_err());
+}
+
+#[test]
+fn reuse_graph() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_sentences_from_text("a", "the knight rode forth into the forest and found a well of water");
+    trie.add_sentences_from_text("b", "then the knyght rode forth into the forest and found a wel of water, the end");
+    trie.add_sentences_from_text("c", "nothing in common with the others at all");
+    trie.add_sentences_from_text("d", "he rode forth into the forest, and so on");
+
+    // The single walk finds the same passages as comparing each pair
+    let graph = trie.reuse_graph(10, 3);
+    assert_eq!(graph.texts, vec!["a", "b", "c", "d"]);
+    let mut expected = vec![];
+    for text_a in 0 .. 4 {
+        for text_b in text_a + 1 .. 4 {
+            let passages = trie.find_shared_passages(&graph.texts[text_a], &graph.texts[text_b], 10, 3).unwrap();
+            if !passages.is_empty() {
+                let shared_length = passages.iter().map(|passage| passage.shared_length).sum();
+                expected.push(ReuseEdge { text_a, text_b, passages: passages.len(), shared_length });
+            }
+        }
+    }
+    assert_eq!(graph.edges, expected);
+    assert_eq!(graph.edges.len(), 3);
+    assert_eq!(graph.passage_counts()[2], vec![0, 0, 0, 0]);
+}
+
+#[test]
+fn ngram_frequencies() {
+    utilities::init_testing();
+    let contents = std::fs::read_to_string("resources/tests/simple/small.txt").unwrap();
+    let texts = [("small", contents.replace(". ", ".<<STOP>> ")), ("other", "What should we do?<<STOP>>Sailor".to_string())];
+    let mut trie = SuffixTrie::empty();
+    for (name, text) in texts.iter() {
+        trie.add_sentences_from_text(name, text);
+    }
+
+    // Compare with counting the n-grams of each sentence
+    let brute_force = |unit: NgramUnit, n: usize, text: &str| {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for sentence in text.split("<<STOP>>") {
+            let ngrams: Vec<String> = match unit {
+                NgramUnit::Character => {
+                    let chars: Vec<char> = sentence.chars().collect();
+                    chars.windows(n).map(|window| window.iter().collect()).collect()
+                },
+                NgramUnit::Word => {
+                    let words: Vec<&str> = sentence.split_whitespace().collect();
+                    words.windows(n).map(|window| window.join(" ")).collect()
+                },
+            };
+            for ngram in ngrams {
+                *counts.entry(ngram).or_insert(0) += 1;
+            }
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| (b.1, &a.0).cmp(&(a.1, &b.0)));
+        counts
+    };
+    let table_counts = |table: &NgramTable| -> Vec<(String, usize)> {
+        table.counts.iter().map(|count| (count.ngram.clone(), count.count)).collect()
+    };
+
+    for (unit, n) in [(NgramUnit::Character, 1), (NgramUnit::Character, 4), (NgramUnit::Word, 1), (NgramUnit::Word, 3)] {
+        let options = NgramOptions { unit, n, ..NgramOptions::default() };
+        let tables = trie.ngram_frequencies_per_text(&options);
+        for (table, (name, text)) in tables.iter().zip(texts.iter()) {
+            assert_eq!(table.text_name.as_deref(), Some(*name));
+            assert_eq!(table_counts(table), brute_force(unit, n, text));
+        }
+        let all_text = texts.iter().map(|(_, text)| text.as_str()).collect::<Vec<&str>>().join("<<STOP>>");
+        assert_eq!(table_counts(&trie.ngram_frequencies(&options)), brute_force(unit, n, &all_text));
+    }
+
+    let options = NgramOptions { unit: NgramUnit::Word, n: 1, min_count: 3, top_k: Some(2) };
+    assert_eq!(table_counts(&trie.ngram_frequencies(&options)),
+               vec![("What".to_string(), 4), ("a".to_string(), 4)]);
+}
+
+#[test]
+fn distinctive_phrases() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("resources/tests/large_100").unwrap();
+    let names = trie.get_text_names();
+    let shakespeare = names.iter().position(|name| name.ends_with("shakespeare.txt")).unwrap();
+    let milton = names.iter().position(|name| name.ends_with("para.txt")).unwrap();
+
+    let options = DistinctiveOptions { min_length: 4, max_length: 12, min_count: 3, top_k: Some(30) };
+    let phrases = trie.find_distinctive_phrases(&[&names[shakespeare]], &[&names[milton]], &options).unwrap();
+    assert_eq!(phrases.len(), 30);
+    for phrase in phrases.iter() {
+        // Malory is in neither group, so isn't counted
+        let counts = trie.count_exact(&phrase.string);
+        assert_eq!((phrase.count_a, phrase.count_b), (counts[shakespeare], counts[milton]));
+        assert!(phrase.count_a + phrase.count_b >= 3);
+        let favours = if phrase.per_10k_chars_a >= phrase.per_10k_chars_b { Subcorpus::A } else { Subcorpus::B };
+        assert_eq!(phrase.favours, favours);
+    }
+    assert!(phrases.iter().any(|phrase| phrase.favours == Subcorpus::A));
+    assert!(phrases.iter().any(|phrase| phrase.favours == Subcorpus::B));
+}
+
+#[test]
+fn collocations() {
+    utilities::init_testing();
+    let mut trie = SuffixTrie::empty();
+    trie.add_file("resources/tests/simple/small.txt").unwrap();
+
+    let options = CollocationOptions { left: 2, right: 1, ..CollocationOptions::default() };
+    let collocates = trie.collocations(&trie.find_exact("drunken"), &options);
+    let counts: Vec<(&str, usize, usize, usize)> = collocates.iter()
+        .map(|collocate| (collocate.word.as_str(), collocate.count, collocate.left_count, collocate.corpus_count))
+        .collect();
+    assert_eq!(counts, vec![("a", 3, 3, 4), ("sailor", 3, 0, 3), ("with", 3, 3, 3)]);
+
+    // Words only found near the matches are most strongly associated
+    let options = CollocationOptions { sort: CollocationSort::Pmi, ..options };
+    let collocates = trie.collocations(&trie.find_exact("drunken"), &options);
+    assert_eq!(collocates[2].word, "a");
+    assert!(collocates[0].pmi > collocates[2].pmi);
+
+    let path = temp_path("collocations.smap");
+    trie.save_mapped(&path).unwrap();
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+    assert_eq!(mapped.collocations(&mapped.find_exact("drunken"), &options), collocates);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn boolean_search() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("resources/tests/large_100").unwrap();
+    let path = temp_path("boolean_search.smap");
+    trie.save_mapped(&path).unwrap();
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+
+    // Compare with checking each sentence for the terms
+    let mut sentences: Vec<(usize, usize, String)> = vec![];
+    for (text_index, name) in trie.get_text_names().iter().enumerate() {
+        let contents = std::fs::read_to_string(name).unwrap();
+        for (sentence_index, sentence) in contents.split("<<STOP>>").enumerate() {
+            if !sentence.is_empty() {
+                sentences.push((text_index, sentence_index, sentence.to_string()));
+            }
+        }
+    }
+    type Satisfies = fn(&str) -> bool;
+    let queries: [(&str, Satisfies); 3] = [
+        ("the AND and AND NOT Arthur", |s| s.contains("the") && s.contains("and") && !s.contains("Arthur")),
+        ("king OR sword", |s| s.contains("king") || s.contains("sword")),
+        ("NOT (the OR a)", |s| !s.contains("the") && !s.contains('a')),
+    ];
+    for (query, satisfies) in queries {
+        let expected: Vec<(usize, usize)> = sentences.iter()
+            .filter(|(_, _, sentence)| satisfies(sentence))
+            .map(|(text_index, sentence_index, _)| (*text_index, *sentence_index))
+            .collect();
+        assert!(!expected.is_empty());
+        let query = BooleanQuery::parse(query).unwrap();
+        let hits = trie.boolean_search(&query, &BooleanOptions::default()).unwrap();
+        let found: Vec<(usize, usize)> = hits.iter().map(|hit| (hit.span.text_index, hit.unit)).collect();
+        assert_eq!(found, expected, "{:?}", query);
+        assert!(hits.iter().all(|hit| hit.span.sentence == Some(hit.unit)));
+        assert_eq!(mapped.boolean_search(&query, &BooleanOptions::default()).unwrap(), hits);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn proximity_search() {
+    utilities::init_testing();
+    let trie = SuffixTrie::from_directory("resources/tests/large_100").unwrap();
+    let path = temp_path("proximity_search.smap");
+    trie.save_mapped(&path).unwrap();
+    let mapped = MappedSuffixTrie::open(&path).unwrap();
+
+    // Compare with checking every pair of matches of the two terms
+    let options = ProximityOptions { unit: DistanceUnit::Characters, ..ProximityOptions::default() };
+    for (query, ordered) in [("the NEAR/20 and", false), ("the BEFORE/20 and", true)] {
+        let (the, and) = (trie.find_exact("the"), trie.find_exact("and"));
+        let mut expected = vec![];
+        for (a, b) in the.iter().flat_map(|a| and.iter().map(move |b| (a, b))) {
+            for (first, second) in [(a, b), (b, a)].iter().take(if ordered { 1 } else { 2 }) {
+                let first_end = first.index_in_str + first.length;
+                if first.text_index == second.text_index && second.index_in_str >= first_end
+                    && second.index_in_str - first_end <= 20 {
+                    expected.push((first.text_index, first.index_in_str, second.index_in_str + second.length - first.index_in_str));
+                }
+            }
+        }
+        expected.sort();
+        expected.dedup();
+        assert!(!expected.is_empty());
+
+        let matches = trie.find_near(&ProximityQuery::parse(query).unwrap(), &options);
+        let mut found: Vec<(usize, usize, usize)> = matches.iter()
+            .map(|match_obj| (match_obj.text_index, match_obj.index_in_str, match_obj.length))
+            .collect();
+        found.sort();
+        assert_eq!(found, expected, "{}", query);
+        assert_eq!(mapped.find_near(&ProximityQuery::parse(query).unwrap(), &options), matches);
+    }
+
+    let query = ProximityQuery::parse("king NEAR/3 Arthur").unwrap();
+    let matches = trie.find_near(&query, &ProximityOptions::default());
+    assert!(!matches.is_empty());
+    for match_obj in matches.iter() {
+        let span = trie.get_strings_of_match(match_obj, 0).1;
+        assert!(span.starts_with("king") || span.starts_with("Arthur"), "{}", span);
+        assert!(span.split_whitespace().count() <= 5, "{}", span);
+    }
+    assert_eq!(mapped.find_near(&query, &ProximityOptions::default()), matches);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn word_index() {
+    utilities::init_testing();
+    let index = WordIndex::new(SuffixTrie::from_directory("resources/tests/large_100").unwrap());
+
+    // Compare with counting the phrases in the words of each sentence
+    let mut sentences: Vec<Vec<String>> = vec![];
+    for name in index.trie().get_text_names() {
+        let contents = std::fs::read_to_string(&name).unwrap();
+        for sentence in contents.split("<<STOP>>") {
+            sentences.push(sentence.split_whitespace()
+                           .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_string())
+                           .filter(|word| !word.is_empty())
+                           .collect());
+        }
+    }
+    for phrase in ["of the", "in the", "and the", "the", "to the"] {
+        let words: Vec<&str> = phrase.split(' ').collect();
+        let expected: usize = sentences.iter()
+            .map(|sentence| sentence.windows(words.len()).filter(|window| *window == &words[..]).count())
+            .sum();
+        assert!(expected > 0);
+        assert_eq!(index.find_phrase(phrase).len(), expected, "{}", phrase);
+    }
+
+    // Matches refer to the characters of the texts
+    let matches = index.find_phrase("of the");
+    assert!(matches.iter().all(|match_obj| index.get_strings_of_match(match_obj, 0).1 == "of the"));
+
+    let index = WordIndex::new(SuffixTrie::from_file("resources/tests/simple/small.txt").unwrap());
+    let matches = index.find_phrase("sailor, early");
+    assert_eq!(matches.len(), 1);
+    assert_eq!(index.get_strings_of_match(&matches[0], 0).1, "sailor\nearly");
+    assert!(index.trie().find_exact("sailor early").is_empty());
+
+    // Substituting a whole word is a single error
+    assert!(index.find_phrase("What should we do with a sober sailor").is_empty());
+    let matches = index.find_phrase_edit_distance("What should we do with a sober sailor", 1);
+    assert_eq!(matches.len(), 3);
+    assert!(matches.iter().all(|match_obj| match_obj.errors == 1));
+    assert!(matches.iter().all(|match_obj| index.get_strings_of_match(match_obj, 0).1.ends_with("drunken sailor")));
+
+    // A saved word index is opened as its own kind of index
+    let path = temp_path("words.ssw");
+    index.save(&path).unwrap();
+    assert_eq!(detect_index_kind(&path).unwrap(), IndexKind::Words);
+    let loaded = load_index(&path).unwrap();
+    assert_eq!(loaded.get_text_info(), index.trie().get_text_info());
+    for (phrase, errors) in [("sailor, early", 0), ("What should we do with a sober sailor", 1), ("drunken", 0)] {
+        compare_matches(loaded.find_edit_distance(phrase, errors), index.find_phrase_edit_distance(phrase, errors));
+    }
+    assert_eq!(loaded.count_exact("drunken sailor"), vec![3]);
+    assert!(loaded.matches_words());
+
+    // A truncated file is rejected rather than searched
+    let contents = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &contents[.. contents.len() - 4]).unwrap();
+    assert!(WordIndex::open(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}